@@ -1 +0,0 @@
-drop table if exists public.inscan_events;
\ No newline at end of file
@@ -1 +0,0 @@
-TRUNCATE public.inscan_events;
\ No newline at end of file
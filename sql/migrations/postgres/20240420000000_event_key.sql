@@ -0,0 +1,8 @@
+-- the identity of an event. Rows written before events had a key have null
+-- key columns and are never matched by upserts.
+ALTER TABLE public.inscan_events
+    ADD COLUMN IF NOT EXISTS input integer,
+    ADD COLUMN IF NOT EXISTS envelope_offset integer,
+    ADD COLUMN IF NOT EXISTS ordinal integer;
+CREATE UNIQUE INDEX IF NOT EXISTS inscan_events_key_idx
+    ON public.inscan_events (txhash, input, envelope_offset, protocol, ordinal);
@@ -0,0 +1,6 @@
+-- the last block written by the indexer of each protocol
+CREATE TABLE IF NOT EXISTS public.inscan_cursor (
+    protocol VARCHAR(255) PRIMARY KEY,
+    height integer NOT NULL,
+    block_hash VARCHAR(64) NOT NULL
+);
@@ -0,0 +1,5 @@
+-- the blocks written while indexing, with the hash they were indexed at
+CREATE TABLE IF NOT EXISTS public.inscan_blocks (
+    height integer PRIMARY KEY,
+    block_hash VARCHAR(64) NOT NULL
+);
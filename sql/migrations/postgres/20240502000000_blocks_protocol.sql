@@ -0,0 +1,6 @@
+-- the blocks written by the indexer of each protocol, like the cursors. Rows
+-- written before are taken as the ones of `all`.
+ALTER TABLE public.inscan_blocks ADD COLUMN IF NOT EXISTS protocol VARCHAR(255) NOT NULL DEFAULT 'all';
+ALTER TABLE public.inscan_blocks ALTER COLUMN protocol DROP DEFAULT;
+ALTER TABLE public.inscan_blocks DROP CONSTRAINT IF EXISTS inscan_blocks_pkey;
+ALTER TABLE public.inscan_blocks ADD PRIMARY KEY (protocol, height);
@@ -0,0 +1,29 @@
+-- the tables of the postgres schema, payloads are stored as json text
+CREATE TABLE inscan_events (
+    height INTEGER,
+    blocktime INTEGER,
+    txhash TEXT NOT NULL,
+    txindex INTEGER,
+    input INTEGER NOT NULL,
+    envelope_offset INTEGER NOT NULL,
+    ordinal INTEGER NOT NULL,
+    protocol TEXT NOT NULL,
+    payload TEXT NOT NULL
+);
+CREATE INDEX inscan_events_height_idx ON inscan_events (height);
+CREATE INDEX inscan_events_blocktime_idx ON inscan_events (blocktime);
+CREATE INDEX inscan_events_protocol_idx ON inscan_events (protocol);
+-- also serves lookups by txhash
+CREATE UNIQUE INDEX inscan_events_key_idx
+    ON inscan_events (txhash, input, envelope_offset, protocol, ordinal);
+
+CREATE TABLE inscan_cursor (
+    protocol TEXT PRIMARY KEY,
+    height INTEGER NOT NULL,
+    block_hash TEXT NOT NULL
+);
+
+CREATE TABLE inscan_blocks (
+    height INTEGER PRIMARY KEY,
+    block_hash TEXT NOT NULL
+);
@@ -0,0 +1,12 @@
+-- the blocks written by the indexer of each protocol, like the cursors. Rows
+-- written before are taken as the ones of `all`.
+CREATE TABLE inscan_blocks_by_protocol (
+    protocol TEXT NOT NULL,
+    height INTEGER NOT NULL,
+    block_hash TEXT NOT NULL,
+    PRIMARY KEY (protocol, height)
+);
+INSERT INTO inscan_blocks_by_protocol (protocol, height, block_hash)
+    SELECT 'all', height, block_hash FROM inscan_blocks;
+DROP TABLE inscan_blocks;
+ALTER TABLE inscan_blocks_by_protocol RENAME TO inscan_blocks;
@@ -0,0 +1,3 @@
+-- the cursor is enough to resume and roll back, the hashes of the blocks below
+-- it were never read
+DROP TABLE IF EXISTS inscan_blocks;
@@ -1,8 +1,58 @@
+use {
+    serde::{Deserialize, Serialize},
+    serde_json::{Map, Value},
+};
 
-pub struct ARC20Ins{
-    name: String
+/// the cbor payload of an atomicals operation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AtomicalsOperation<A> {
+    pub args: A,
+    /// `meta`, `ctx`, `init`... kept as inscribed
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-pub fn decode_arc20(){
-    println!("decode arc20...")
-}
\ No newline at end of file
+/// args of `dft`, deploying a decentralized mint ticker
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Arc20DeployArgs {
+    pub request_ticker: String,
+    pub mint_amount: u64,
+    pub mint_height: u64,
+    pub max_mints: u64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// args of `dmt`, minting a decentralized ticker
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Arc20MintArgs {
+    pub mint_ticker: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// args of `ft`, minting the whole supply of a direct ticker at once
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Arc20TickerArgs {
+    pub request_ticker: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// args of an `nft` requesting a realm or subrealm
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RealmArgs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_realm: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_subrealm: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+pub type Arc20Dft = AtomicalsOperation<Arc20DeployArgs>;
+pub type Arc20Dmt = AtomicalsOperation<Arc20MintArgs>;
+pub type Arc20Ft = AtomicalsOperation<Arc20TickerArgs>;
+/// `y` splits the atomicals of its inputs, keyed by atomical id
+pub type Arc20Split = Map<String, Value>;
+pub type AtomRealm = AtomicalsOperation<RealmArgs>;
@@ -1,73 +1,300 @@
-
+use inscan::blk::BlkIndex;
 use inscan::cli;
+use inscan::db;
+use inscan::mempool;
+use inscan::notify::{BlockNotifier, ZMQ_FALLBACK_POLL};
+use inscan::decoder::Registry;
+use inscan::pipeline::PipelineOptions;
+use inscan::replay;
+use inscan::runes;
 use inscan::scan;
+use inscan::sink::{self, EventSink, FanOut, ParquetSink, RotatingJsonlSink};
+use inscan::source::{BlockSource, EsploraSource};
 
 use {
     std::process::exit,
-    clap::{Parser, Subcommand},
+    clap::Parser,
+    anyhow::Context,
+    ordinals::{Pile, RuneId},
+    std::collections::BTreeMap,
     bitcoincore_rpc::{Auth, Client},
-    bitcoin::{Txid, hashes::Hash, Transaction}
+    futures::executor::block_on,
+    sqlx::{
+        migrate::{Migrate, Migrator},
+        Database, Executor, PgPool, Pool,
+    },
 };
 
-fn create_connection(host: &String, user: String, pass: String)-> Result<Client, bitcoincore_rpc::Error>{
-    let rpc = match Client::new(host, Auth::UserPass(user,pass)) {
-        Ok(rpc) => rpc,
-        Err(err) => return Err(err),
-    };
-    Ok(rpc)
+fn create_connection(host: &str, user: String, pass: String)-> Result<Client, bitcoincore_rpc::Error>{
+    Client::new(host, Auth::UserPass(user,pass))
+}
+
+async fn run_db_action<DB: Database>(pool: &Pool<DB>, migrator: &Migrator, action: &cli::DbCommands) -> anyhow::Result<()>
+where
+    DB::Connection: Migrate,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    match action {
+        cli::DbCommands::Init => db::init(pool, migrator).await?,
+        cli::DbCommands::Migrate => db::migrate(pool, migrator).await?,
+        cli::DbCommands::Status => {
+            for migration in db::status(pool, migrator).await? {
+                let state = if migration.applied { "applied" } else { "pending" };
+                println!("{} {} {state}", migration.version, migration.description);
+            }
+        }
+        cli::DbCommands::Drop => db::drop(pool).await?,
+    }
+    Ok(())
+}
+
+/// run a `db` subcommand against the `--out-db` database, postgres or sqlite
+fn run_db(url: &str, action: &cli::DbCommands) -> anyhow::Result<()> {
+    block_on(async {
+        if url.starts_with("sqlite:") {
+            let pool = db::connect_sqlite(url).await?;
+            run_db_action(&pool, &db::SQLITE_MIGRATOR, action).await
+        } else {
+            let pool = PgPool::connect(url).await?;
+            run_db_action(&pool, &db::PG_MIGRATOR, action).await
+        }
+    })
+}
+
+fn run_simulate(raw_tx: &str, inputs: &str, mint: Option<&str>) -> anyhow::Result<()> {
+    let tx: bitcoin::Transaction = bitcoin::consensus::deserialize(&scan::read_raw(raw_tx)?).context("invalid transaction")?;
+    let inputs = serde_json::from_str::<Vec<BTreeMap<RuneId, Pile>>>(inputs).context("invalid --inputs")?;
+    let mint = mint.map(serde_json::from_str::<Pile>).transpose().context("invalid --mint")?;
+    println!("{}", serde_json::to_string_pretty(&runes::simulate(&tx, &inputs, mint)?)?);
+    Ok(())
 }
 
 fn main() {
     let cli = cli::Cli::parse();
+
+    if let Some(cli::Commands::Runes { action: cli::RunesCommands::Simulate { raw_tx, inputs, mint } }) = &cli.command {
+        if let Err(err) = run_simulate(raw_tx, inputs, mint.as_deref()) {
+            eprintln!("ERROR: {err:#}");
+            exit(1);
+        }
+        return;
+    }
+
+    if let Some(cli::Commands::Db { action }) = &cli.command {
+        if cli.out_db.is_empty() {
+            eprintln!("ERROR: db commands require the database url in --out-db");
+            exit(1);
+        }
+        for url in &cli.out_db {
+            if let Err(err) = run_db(url, action) {
+                eprintln!("ERROR: {err:#}");
+                exit(1);
+            }
+        }
+        return;
+    }
     
-    let protocol = cli.protocol;
-    
-    if (cli.out_file.is_some() && cli.out_db.is_some()) || (cli.out_file.is_none() && cli.out_db.is_none()){
-        eprintln!("ERROR: out_file and out_db can only choose one. can't be both extis or both none!");
+    let protocol = cli.protocol.to_lowercase();
+    let registry = Registry::default();
+    if !registry.supports(&protocol) {
+        eprintln!("ERROR: unknown protocol {protocol:?}, expected all or one of {:?}", registry.names());
+        exit(1);
+    }
+
+    if cli.out_file.is_empty() && cli.out_db.is_empty() && cli.out_parquet.is_empty() && cli.out_dir.is_empty() {
+        eprintln!("ERROR: at least one out_file, out_db, out_parquet or out_dir is required!");
         exit(1);
     };
 
-    // connection to rpc server
-    let rpc = create_connection(&cli.rpc_host, cli.rpc_user, cli.rpc_pass).unwrap();
+    let target = cli.out_file
+        .iter()
+        .map(|file| if file == "-" { "stdout" } else { file })
+        .chain(cli.out_db.iter().map(|_| "database"))
+        .chain(cli.out_parquet.iter().map(String::as_str))
+        .chain(cli.out_dir.iter().map(String::as_str))
+        .collect::<Vec<&str>>()
+        .join(", ");
 
-    // matches just as you would the top level cmd
-    match &cli.command {
-        Some(cli::Commands::Decode { block, txid }) => {
+    // connections to esplora or the rpc server, one per block fetcher, for the commands reading the chain
+    let connect = || -> anyhow::Result<Box<dyn BlockSource>> {
+        if let Some(esplora_url) = &cli.esplora_url {
+            return Ok(Box::new(EsploraSource::new(esplora_url)));
+        }
+        let (Some(rpc_user), Some(rpc_pass)) = (cli.rpc_user.clone(), cli.rpc_pass.clone()) else {
+            anyhow::bail!("--rpc-user and --rpc-pass, or --esplora-url, are required to read blocks");
+        };
+        Ok(Box::new(create_connection(&cli.rpc_host, rpc_user, rpc_pass)?))
+    };
+
+    let mempool = matches!(cli.command, Some(cli::Commands::Mempool { .. }));
+    if mempool && (!cli.out_db.is_empty() || !cli.out_parquet.is_empty() || !cli.out_dir.is_empty()) {
+        eprintln!("ERROR: mempool updates are written to --out-file only");
+        exit(1);
+    }
 
-            if (block.is_some() && txid.is_some()) || (block.is_none() && txid.is_none()){
-                panic!("height and txid can only choose one. can't be both extis or both none");
+    let options = PipelineOptions {
+        fetchers: cli.fetchers,
+        workers: cli.workers.unwrap_or(PipelineOptions::default().workers),
+        max_blocks: cli.max_blocks,
+    };
+    // every output is opened once for the whole run, one connection pool per database
+    let mut sinks = Vec::new();
+    // the --out-file outputs of `mempool` get its update lines, not the events alone
+    for output in cli.out_file.iter().filter(|_| !mempool).chain(&cli.out_db) {
+        match sink::open(output) {
+            Ok(sink) => sinks.push(sink),
+            Err(err) => {
+                eprintln!("ERROR: failed to open {}: {err:#}", if cli.out_db.contains(output) { "database" } else { output });
+                exit(1);
             }
-            
-            if block.is_some() && cli.out_file.is_some(){
-                println!("Extract {protocol:?} from blocks {block:?} and save to local file ...");
-                scan::run_blocks(&rpc, &block.as_ref().unwrap(), &protocol, &cli.out_file.as_ref().unwrap());
+        }
+    }
+    for dir in &cli.out_parquet {
+        match ParquetSink::open(dir, cli.parquet_blocks) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(err) => {
+                eprintln!("ERROR: failed to open {dir}: {err:#}");
+                exit(1);
             }
-            if block.is_some() && cli.out_db.is_some(){
-                println!("Extract {protocol:?} from blocks {block:?} and save to database ...");
-                scan::run_blocks(&rpc, &block.as_ref().unwrap(), &protocol, &cli.out_db.as_ref().unwrap());
+        }
+    }
+    for dir in &cli.out_dir {
+        match RotatingJsonlSink::open(dir, cli.rotate_blocks, cli.compression) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(err) => {
+                eprintln!("ERROR: failed to open {dir}: {err:#}");
+                exit(1);
             }
+        }
+    }
+    let mut sink = FanOut::new(sinks);
 
-            if txid.is_some() && cli.out_file.is_some(){
-                println!("Extract {protocol:?} from txs {txid:?} and save to local file ...");
-                scan::run_txs(&rpc, &txid.as_ref().unwrap(), &protocol, &cli.out_file.as_ref().unwrap());
-            }
-            if txid.is_some() && cli.out_db.is_some(){
-                println!("Extract {protocol:?} from txs {txid:?} and save to database ...");
-                scan::run_txs(&rpc, &txid.as_ref().unwrap(), &protocol, &cli.out_db.as_ref().unwrap());
-                // TODO
+    // matches just as you would the top level cmd
+    match &cli.command {
+        Some(cli::Commands::Decode { block, txid, raw_tx, raw_block }) => {
+            let result = match (block, txid) {
+                _ if !raw_tx.is_empty() => {
+                    eprintln!("Extract {protocol:?} from {} raw transactions and save to {target} ...", raw_tx.len());
+                    scan::run_raw_txs(raw_tx, &registry, &protocol, &mut sink)
+                }
+                _ if !raw_block.is_empty() => {
+                    eprintln!("Extract {protocol:?} from {} raw blocks and save to {target} ...", raw_block.len());
+                    scan::run_raw_blocks(raw_block, &registry, &protocol, &mut sink)
+                }
+                (Some(block), None) => match &cli.blocks_dir {
+                    Some(blocks_dir) => {
+                        eprintln!("Scanning the block files of {blocks_dir} ...");
+                        // the node, when it can be asked, tells which chain of the files is valid
+                        let best = match (&cli.rpc_user, &cli.esplora_url) {
+                            (None, None) => Ok(None),
+                            _ => connect().and_then(|source| source.block_hash(source.tip_height()?)).map(Some),
+                        };
+                        best.and_then(|best| BlkIndex::open(blocks_dir, best)).and_then(|index| {
+                            eprintln!("Extract {protocol:?} from blocks {block:?} of the block files (tip {}) and save to {target} ...", index.height());
+                            scan::run_blk_files(&index, &registry, block, &protocol, &options, &mut sink)
+                        })
+                    }
+                    None => {
+                        eprintln!("Extract {protocol:?} from blocks {block:?} and save to {target} ...");
+                        scan::run_blocks(&connect, &registry, block, &protocol, &options, &mut sink)
+                    }
+                },
+                (None, Some(txid)) => {
+                    eprintln!("Extract {protocol:?} from txs {txid:?} and save to {target} ...");
+                    connect().and_then(|source| scan::run_txs(source.as_ref(), &registry, txid, &protocol, &mut sink))
+                }
+                _ => unreachable!("clap requires exactly one input to decode"),
+            };
+            if let Err(err) = result {
+                eprintln!("ERROR: {err:#}");
+                if let Err(err) = sink.finish() {
+                    eprintln!("ERROR: {err:#}");
+                }
+                exit(1);
             }
         }
         Some(cli::Commands::Index { start }) => {
-            if cli.out_file.is_some(){
-                println!("Start scaning {protocol:?} from block {start:?} to latest block and save to local file ...");
-                scan::index_realtime(&rpc, *start, &protocol, &cli.out_file.as_ref().unwrap());
+            match start {
+                Some(start) => eprintln!("Start scaning {protocol:?} from block {start:?} to latest block and save to {target} ..."),
+                None => eprintln!("Resume scaning {protocol:?} to latest block and save to {target} ..."),
             }
-            if cli.out_db.is_some(){
-                println!("Start scaning {protocol:?} from block {start:?} to latest block and save to database ...");
-                scan::index_realtime(&rpc, *start, &protocol, &cli.out_db.as_ref().unwrap());
+            let notifier = match &cli.zmq {
+                Some(endpoint) => BlockNotifier::zmq(endpoint, ZMQ_FALLBACK_POLL),
+                None => Ok(BlockNotifier::polling(std::time::Duration::from_secs(cli.poll_interval))),
+            };
+            let result = notifier.and_then(|notifier| scan::index_realtime(&connect, &registry, *start, &protocol, &options, &notifier, &mut sink));
+            if let Err(err) = result {
+                eprintln!("ERROR: {err:#}");
+                if let Err(err) = sink.finish() {
+                    eprintln!("ERROR: {err:#}");
+                }
+                exit(1);
             }
         }
-        None => {}
+        Some(cli::Commands::Brc20 { start, end }) => {
+            eprintln!("Build the brc-20 ledger and save to {target} ...");
+            if let Err(err) = scan::run_brc20(&connect, *start, *end, cli.network, &options, &mut sink) {
+                eprintln!("ERROR: {err:#}");
+                if let Err(err) = sink.finish() {
+                    eprintln!("ERROR: {err:#}");
+                }
+                exit(1);
+            }
+        }
+        Some(cli::Commands::Transfers { start, end }) => {
+            eprintln!("Follow the inscriptions and save their transfers to {target} ...");
+            if let Err(err) = scan::run_transfers(&connect, *start, *end, cli.network, &options, &mut sink) {
+                eprintln!("ERROR: {err:#}");
+                if let Err(err) = sink.finish() {
+                    eprintln!("ERROR: {err:#}");
+                }
+                exit(1);
+            }
+        }
+        Some(cli::Commands::Runes { action: cli::RunesCommands::Index { start, end } }) => {
+            eprintln!("Build the runes state and save to {target} ...");
+            if let Err(err) = scan::run_runes(&connect, *start, *end, cli.network, &options, &mut sink) {
+                eprintln!("ERROR: {err:#}");
+                if let Err(err) = sink.finish() {
+                    eprintln!("ERROR: {err:#}");
+                }
+                exit(1);
+            }
+        }
+        Some(cli::Commands::Mempool { interval }) => {
+            eprintln!("Follow {protocol:?} in the mempool and save to {target} ...");
+            let result = mempool::open_outputs(&cli.out_file).and_then(|mut outputs| {
+                let source = connect()?;
+                mempool::watch(source.as_ref(), &registry, &protocol, std::time::Duration::from_secs(*interval), &mut outputs)
+            });
+            if let Err(err) = result {
+                eprintln!("ERROR: {err:#}");
+                exit(1);
+            }
+        }
+        Some(cli::Commands::Replay { input }) => {
+            eprintln!("Replay {protocol:?} from {input:?} and save to {target} ...");
+            match replay::expand_inputs(input).and_then(|paths| replay::replay(&paths, &protocol, &mut sink)) {
+                Ok(stats) => eprintln!(
+                    "Replayed {} events of {} blocks from {} files, skipped {} invalid lines",
+                    stats.events, stats.blocks, stats.files, stats.invalid
+                ),
+                Err(err) => {
+                    eprintln!("ERROR: {err:#}");
+                    if let Err(err) = sink.finish() {
+                        eprintln!("ERROR: {err:#}");
+                    }
+                    exit(1);
+                }
+            }
+        }
+        Some(cli::Commands::Db { .. } | cli::Commands::Runes { action: cli::RunesCommands::Simulate { .. } }) | None => {}
+    }
+
+    // write what buffering outputs still hold
+    if let Err(err) = sink.finish() {
+        eprintln!("ERROR: {err:#}");
+        exit(1);
     }
 
     // Continued program logic goes here...
@@ -0,0 +1,265 @@
+use {
+    anyhow::{bail, Context, Result},
+    bitcoin::{
+        block::Header,
+        consensus::{deserialize, Decodable},
+        hashes::Hash,
+        pow::Work,
+        Block, BlockHash,
+    },
+    std::{
+        collections::HashMap,
+        fs::{self, File},
+        io::{ErrorKind, Read, Seek, SeekFrom},
+        path::{Path, PathBuf},
+    },
+};
+
+/// where a block is in the `blk*.dat` files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+    file: u32,
+    /// of the serialized block, after its magic and size
+    offset: u64,
+    size: u32,
+    hash: BlockHash,
+}
+
+/// the blocks of the best chain in the `blocks` directory of a bitcoind node,
+/// read straight from its `blk*.dat` files.
+///
+/// The files are scanned once for block headers, which are chained from the
+/// first block of `blk00000.dat` (the genesis block) to the best block of the
+/// node when it can be asked, so its block index database isn't needed. Else,
+/// with the node stopped, the chain goes to the tip with the most work, which
+/// may end with blocks the node found invalid. The files are only read. Files obfuscated with
+/// the key of `xor.dat` (bitcoind 28 and later) are read too. Blocks the node
+/// is still writing are left out.
+pub struct BlkIndex {
+    dir: PathBuf,
+    xor: Option<[u8; 8]>,
+    /// the location of the block at each height of the best chain
+    chain: Vec<Location>,
+}
+
+impl BlkIndex {
+    /// the chain ends at `best`, the best block of the node, unless it isn't
+    /// in the files yet
+    pub fn open(dir: impl AsRef<Path>, best: Option<BlockHash>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let xor = match fs::read(dir.join("xor.dat")) {
+            Ok(key) => Some(<[u8; 8]>::try_from(key.as_slice()).context("xor.dat is not an 8 byte key")?),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let mut index = Self {
+            dir,
+            xor: xor.filter(|key| key != &[0; 8]),
+            chain: Vec::new(),
+        };
+        index.chain = index.scan(best)?;
+        Ok(index)
+    }
+
+    /// the height of the tip of the best chain
+    pub fn height(&self) -> u64 {
+        self.chain.len().saturating_sub(1) as u64
+    }
+
+    pub fn block_hash(&self, height: u64) -> Option<BlockHash> {
+        self.chain.get(height as usize).map(|location| location.hash)
+    }
+
+    pub fn block(&self, height: u64) -> Result<Block> {
+        let location = self
+            .chain
+            .get(height as usize)
+            .with_context(|| format!("block {height} is above the tip {} of the block files", self.height()))?;
+        let mut file = File::open(self.file_path(location.file))?;
+        let bytes = self.read_at(&mut file, location.offset, location.size as usize)?;
+        let block: Block = deserialize(&bytes).with_context(|| format!("invalid block {height} in blk{:05}.dat", location.file))?;
+        if block.block_hash() != location.hash {
+            bail!("block {height} changed in blk{:05}.dat", location.file);
+        }
+        Ok(block)
+    }
+
+    fn file_path(&self, file: u32) -> PathBuf {
+        self.dir.join(format!("blk{file:05}.dat"))
+    }
+
+    /// `len` bytes at `offset`, deobfuscated
+    fn read_at(&self, file: &mut File, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let mut bytes = vec![0; len];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut bytes)?;
+        if let Some(key) = self.xor {
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte ^= key[((offset + i as u64) % 8) as usize];
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// the headers of every complete block of a file, with their location
+    fn headers(&self, number: u32, magic: &mut Option<[u8; 4]>) -> Result<Vec<(Header, Location)>> {
+        let mut file = File::open(self.file_path(number))?;
+        let len = file.metadata()?.len();
+        let mut headers = Vec::new();
+        let mut offset = 0;
+        while offset + 8 + 80 <= len {
+            let record = self.read_at(&mut file, offset, 8)?;
+            let (record_magic, size) = record.split_at(4);
+            // files are preallocated with zeros after the last block
+            if record_magic == [0; 4] {
+                break;
+            }
+            let record_magic: [u8; 4] = record_magic.try_into().expect("4 bytes");
+            if *magic.get_or_insert(record_magic) != record_magic {
+                bail!("unexpected network magic at {offset} of blk{number:05}.dat");
+            }
+            let size = u32::from_le_bytes(size.try_into().expect("4 bytes"));
+            if offset + 8 + size as u64 > len {
+                break;
+            }
+            let header = Header::consensus_decode(&mut self.read_at(&mut file, offset + 8, 80)?.as_slice())?;
+            headers.push((
+                header,
+                Location {
+                    file: number,
+                    offset: offset + 8,
+                    size,
+                    hash: header.block_hash(),
+                },
+            ));
+            offset += 8 + size as u64;
+        }
+        Ok(headers)
+    }
+
+    /// the locations of the blocks of the chain ending at `best`, or else of the chain with the most work
+    fn scan(&self, best: Option<BlockHash>) -> Result<Vec<Location>> {
+        let mut magic = None;
+        let mut blocks = HashMap::new();
+        let mut children: HashMap<BlockHash, Vec<BlockHash>> = HashMap::new();
+        let mut genesis = None;
+        for number in 0.. {
+            if !self.file_path(number).exists() {
+                break;
+            }
+            for (header, location) in self.headers(number, &mut magic)? {
+                if genesis.is_none() {
+                    genesis = Some(location.hash);
+                }
+                children.entry(header.prev_blockhash).or_default().push(location.hash);
+                blocks.insert(location.hash, (header, location));
+            }
+        }
+        let Some(genesis) = genesis else {
+            bail!("no block in {}", self.file_path(0).display());
+        };
+        if blocks[&genesis].0.prev_blockhash != BlockHash::all_zeros() {
+            bail!("the first block of blk00000.dat is not a genesis block");
+        }
+
+        let tip = match best.filter(|best| blocks.contains_key(best)) {
+            Some(best) => best,
+            None => {
+                // the chain work of every block connected to the genesis block
+                let mut best = (blocks[&genesis].0.work(), genesis);
+                let mut stack = vec![(genesis, blocks[&genesis].0.work())];
+                while let Some((hash, work)) = stack.pop() {
+                    if work > best.0 {
+                        best = (work, hash);
+                    }
+                    for child in children.get(&hash).into_iter().flatten() {
+                        let child_work: Work = work + blocks[child].0.work();
+                        stack.push((*child, child_work));
+                    }
+                }
+                best.1
+            }
+        };
+
+        let mut chain = Vec::new();
+        let mut hash = tip;
+        loop {
+            let Some((header, location)) = blocks.get(&hash).copied() else {
+                bail!("the best block {tip} of the node doesn't build on the genesis block of the files");
+            };
+            chain.push(location);
+            if hash == genesis {
+                break;
+            }
+            hash = header.prev_blockhash;
+        }
+        chain.reverse();
+        Ok(chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        bitcoin::{blockdata::constants::genesis_block, consensus::serialize, Network},
+    };
+
+    const MAGIC: [u8; 4] = [0xfa, 0xbf, 0xb5, 0xda];
+
+    fn child(parent: &Block, nonce: u32) -> Block {
+        let mut block = parent.clone();
+        block.header.prev_blockhash = parent.block_hash();
+        block.header.nonce = nonce;
+        block
+    }
+
+    fn write(dir: &Path, number: u32, blocks: &[&Block], key: [u8; 8]) {
+        let mut bytes = Vec::new();
+        for block in blocks {
+            let block = serialize(*block);
+            bytes.extend(MAGIC);
+            bytes.extend((block.len() as u32).to_le_bytes());
+            bytes.extend(block);
+        }
+        // space preallocated by bitcoind
+        bytes.extend([0; 16]);
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte ^= key[i % 8];
+        }
+        fs::write(dir.join(format!("blk{number:05}.dat")), bytes).unwrap();
+    }
+
+    #[test]
+    fn the_chain_with_most_work_is_read_from_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [1, 2, 3, 4, 5, 6, 7, 8];
+        fs::write(dir.path().join("xor.dat"), key).unwrap();
+
+        let genesis = genesis_block(Network::Regtest);
+        let one = child(&genesis, 1);
+        let two = child(&one, 2);
+        let three = child(&two, 3);
+        let stale = child(&one, 4);
+        // blocks are stored in the order they are received, not by height
+        write(dir.path(), 0, &[&genesis, &two, &one], key);
+        write(dir.path(), 1, &[&stale, &three], key);
+
+        let index = BlkIndex::open(dir.path(), None).unwrap();
+        assert_eq!(index.height(), 3);
+        for (height, block) in [&genesis, &one, &two, &three].into_iter().enumerate() {
+            assert_eq!(index.block_hash(height as u64), Some(block.block_hash()));
+            assert_eq!(&index.block(height as u64).unwrap(), block);
+        }
+        assert!(index.block(4).is_err());
+
+        // the node may have found blocks of the longer chain invalid
+        let index = BlkIndex::open(dir.path(), Some(stale.block_hash())).unwrap();
+        assert_eq!(index.height(), 2);
+        assert_eq!(index.block_hash(2), Some(stale.block_hash()));
+
+        // blocks the node has but not the files yet
+        let index = BlkIndex::open(dir.path(), Some(child(&three, 5).block_hash())).unwrap();
+        assert_eq!(index.height(), 3);
+    }
+}
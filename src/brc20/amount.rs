@@ -0,0 +1,108 @@
+use std::fmt::{self, Display, Formatter};
+
+/// the most decimals of a token, and the default ones
+pub const MAX_DECIMALS: u8 = 18;
+
+const ONE: u128 = 10u128.pow(MAX_DECIMALS as u32);
+
+/// an amount of a brc-20 token, in units of 10^-18 whatever the decimals of the
+/// token, up to `u64::MAX` tokens
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(u128);
+
+impl Amount {
+    pub const ZERO: Self = Self(0);
+    pub const MAX: Self = Self(u64::MAX as u128 * ONE);
+
+    /// an inscribed amount: digits with an optional fraction of at most
+    /// `decimals` digits, without sign, exponent or spaces
+    pub fn parse(text: &str, decimals: u8) -> Result<Self, String> {
+        let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+        let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if integer.is_empty() || !digits(integer) || !digits(fraction) || text.ends_with('.') {
+            return Err(format!("invalid amount {text:?}"));
+        }
+        if fraction.len() > decimals as usize {
+            return Err(format!("amount {text:?} has more than {decimals} decimals"));
+        }
+        let integer = integer.trim_start_matches('0');
+        if integer.len() > 20 {
+            return Err(format!("amount {text:?} is above the maximum supply"));
+        }
+        let integer = if integer.is_empty() { 0 } else { integer.parse::<u128>().expect("digits") };
+        let fraction = format!("{fraction:0<18}").parse::<u128>().expect("digits");
+        let amount = Self(integer * ONE + fraction);
+        if amount > Self::MAX {
+            return Err(format!("amount {text:?} is above the maximum supply"));
+        }
+        Ok(amount)
+    }
+
+    /// the `dec` field of a deploy, a whole number up to 18
+    pub fn parse_decimals(text: &str) -> Result<u8, String> {
+        match text.parse::<u8>() {
+            Ok(decimals) if decimals <= MAX_DECIMALS && text.bytes().all(|byte| byte.is_ascii_digit()) => Ok(decimals),
+            _ => Err(format!("invalid decimals {text:?}")),
+        }
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn units(self) -> u128 {
+        self.0
+    }
+
+    pub(super) fn from_units(units: u128) -> Self {
+        Self(units)
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    /// the amount changed by signed units, which never takes it below zero
+    pub(super) fn add_signed(self, units: i128) -> Self {
+        Self(self.0.checked_add_signed(units).expect("balances don't go below zero"))
+    }
+}
+
+impl std::ops::Add for Amount {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+/// the amount in tokens, without trailing zeros: `1000`, `0.5`
+impl Display for Amount {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (integer, fraction) = (self.0 / ONE, self.0 % ONE);
+        if fraction == 0 {
+            return write!(f, "{integer}");
+        }
+        let fraction = format!("{fraction:018}");
+        write!(f, "{integer}.{}", fraction.trim_end_matches('0'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_are_plain_decimals() {
+        assert_eq!(Amount::parse("1000", 18).unwrap().to_string(), "1000");
+        assert_eq!(Amount::parse("0.50", 2).unwrap().to_string(), "0.5");
+        assert_eq!(Amount::parse("007", 0).unwrap().to_string(), "7");
+        assert_eq!(Amount::parse("18446744073709551615", 18).unwrap(), Amount::MAX);
+        for invalid in ["", ".5", "5.", "1e3", "-1", "+1", " 1", "1.2.3", "18446744073709551616", "0.001"] {
+            assert!(Amount::parse(invalid, 2).is_err(), "{invalid:?}");
+        }
+        assert_eq!(Amount::parse_decimals("8"), Ok(8));
+        assert!(Amount::parse_decimals("19").is_err());
+        assert!(Amount::parse_decimals("+1").is_err());
+    }
+}
@@ -0,0 +1,553 @@
+use {
+    crate::{
+        decoder::{located_events, DecodedEvent, Location, ORD_ENVELOPE},
+        event::Event,
+        ord::{brcX::brc20::{Brc20Deploy, Brc20Mint, Brc20Transfer}, InscriptionId, ParsedEnvelope},
+        sat_flow::{self, Prevouts},
+    },
+    anyhow::Result,
+    bitcoin::{Block, Network, OutPoint, Transaction},
+    ordinals::SatPoint,
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    std::collections::{BTreeMap, HashMap},
+};
+
+pub use self::amount::{Amount, MAX_DECIMALS};
+
+mod amount;
+
+/// the protocol of the verdicts of the ledger
+pub const LEDGER_PROTOCOL: &str = "brc20-ledger";
+/// the protocol of the balance changes of the ledger
+pub const BALANCE_PROTOCOL: &str = "brc20-balance";
+/// the height of the first brc-20 deploy on mainnet
+pub const FIRST_HEIGHT: u64 = 779832;
+/// the height from which 5 byte tickers can be deployed, for self minted tokens
+pub const SELF_MINT_HEIGHT: u64 = 837090;
+
+/// whether the ledger applied an operation, or why not
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "verdict", content = "reason", rename_all = "lowercase")]
+pub enum Verdict {
+    Valid,
+    Invalid(String),
+}
+
+/// a brc-20 operation as applied by the ledger: `deploy`, `mint`,
+/// `inscribe-transfer`, or `transfer` when a transfer inscription is spent
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Brc20Operation {
+    pub op: String,
+    pub tick: String,
+    pub inscription_id: InscriptionId,
+    /// the amount minted, made transferable or transferred. A mint over the
+    /// remaining supply only mints what remains.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amt: Option<String>,
+    /// whose balance is made transferable or transferred
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// who deployed, minted or received. A transfer spent as fee goes back to
+    /// the sender, one sent to an OP_RETURN output is burned and has none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(flatten)]
+    pub verdict: Verdict,
+}
+
+/// the change of the balance of a token of an owner, an address or the hex of
+/// a script without address
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Brc20BalanceChange {
+    pub tick: String,
+    pub owner: String,
+    /// signed, e.g. `-1000`
+    pub available_change: String,
+    pub transferable_change: String,
+    /// the balance after the change
+    pub available: String,
+    pub transferable: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Balance {
+    pub available: Amount,
+    pub transferable: Amount,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    /// as deployed
+    tick: String,
+    max: Amount,
+    lim: Amount,
+    dec: u8,
+    minted: Amount,
+    deploy: InscriptionId,
+    /// 5 byte tickers, only minted by children of the deploy inscription
+    self_mint: bool,
+}
+
+/// an inscribed transfer waiting for its inscription to be spent
+#[derive(Debug, Clone)]
+struct PendingTransfer {
+    inscription_id: InscriptionId,
+    /// of the inscription in its output
+    offset: u64,
+    tick: String,
+    amt: Amount,
+    owner: String,
+}
+
+/// the events of a transaction being indexed, with their location
+struct TxEvents(Vec<(Location, Event)>);
+
+impl TxEvents {
+    fn operation(&mut self, location: Location, operation: Brc20Operation) {
+        self.0.push((location, Event::Brc20Operation(operation)));
+    }
+}
+
+/// the brc-20 state built from the inscriptions of the blocks, in order: the
+/// deployed tokens, the available and transferable balance of every owner and
+/// the inscribed transfers not spent yet.
+///
+/// Deploys are first come first served, tickers are case insensitive and 4
+/// bytes long, or 5 bytes for self minted tokens from [`SELF_MINT_HEIGHT`].
+/// Mints are capped by `lim` and credit at most the remaining supply. An
+/// inscribed transfer reserves the amount from the available balance, and
+/// moves it to the owner of the output its inscription lands in once spent.
+pub struct Brc20Ledger {
+    network: Network,
+    /// by lowercase ticker
+    tokens: HashMap<String, Token>,
+    /// by owner and lowercase ticker
+    balances: HashMap<(String, String), Balance>,
+    /// by the output holding their inscription
+    transfers: HashMap<OutPoint, Vec<PendingTransfer>>,
+}
+
+impl Brc20Ledger {
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            tokens: HashMap::new(),
+            balances: HashMap::new(),
+            transfers: HashMap::new(),
+        }
+    }
+
+    pub fn balance(&self, owner: &str, tick: &str) -> Balance {
+        self.balances.get(&(owner.to_string(), tick.to_lowercase())).copied().unwrap_or_default()
+    }
+
+    /// the minted supply of a token, `None` if it isn't deployed
+    pub fn minted(&self, tick: &str) -> Option<Amount> {
+        self.tokens.get(&tick.to_lowercase()).map(|token| token.minted)
+    }
+
+    /// apply the block at `height` and return its verdicts and balance changes.
+    ///
+    /// `events` are the `ord-brc20` events of the block, only their transactions
+    /// are searched for brc-20 inscriptions. `prevouts` gives the values of the
+    /// inputs before an inscription, when it isn't on the first input.
+    pub fn index_block(&mut self, block: &Block, height: u64, events: &[DecodedEvent], prevouts: &mut Prevouts) -> Result<Vec<DecodedEvent>> {
+        let mut inscribed: BTreeMap<usize, Vec<&DecodedEvent>> = BTreeMap::new();
+        for event in events.iter().filter(|event| event.event.protocol() == "ord-brc20") {
+            if let Some(txindex) = event.txindex {
+                inscribed.entry(txindex as usize).or_default().push(event);
+            }
+        }
+
+        let mut decoded = Vec::new();
+        for (txindex, tx) in block.txdata.iter().enumerate() {
+            let mut tx_events = TxEvents(Vec::new());
+            self.settle_transfers(tx, prevouts, &mut tx_events)?;
+            if let Some(events) = inscribed.get(&txindex) {
+                self.apply_inscriptions(tx, height, events, prevouts, &mut tx_events)?;
+            }
+            prevouts.add(tx);
+
+            decoded.extend(located_events(tx, Some((block, height, txindex)), tx_events.0));
+        }
+        prevouts.clear();
+        Ok(decoded)
+    }
+
+    /// move the transfers whose inscription is spent by the transaction
+    fn settle_transfers(&mut self, tx: &Transaction, prevouts: &mut Prevouts, events: &mut TxEvents) -> Result<()> {
+        for (input, txin) in tx.input.iter().enumerate() {
+            let Some(transfers) = self.transfers.remove(&txin.previous_output) else {
+                continue;
+            };
+            let input_offset = prevouts.input_offset(tx, input)?;
+            let location = Location { input: input as u32, offset: 0 };
+            for transfer in transfers {
+                let destination = sat_flow::locate(tx, input_offset + transfer.offset);
+                let to = match destination {
+                    // spent as fee, back to the sender
+                    None => Some(transfer.owner.clone()),
+                    Some(satpoint) => {
+                        let script = &tx.output[satpoint.outpoint.vout as usize].script_pubkey;
+                        (!script.is_op_return()).then(|| sat_flow::owner(script, self.network))
+                    }
+                };
+                let amt = transfer.amt.units() as i128;
+                match &to {
+                    Some(to) if *to == transfer.owner => self.change(to, &transfer.tick, amt, -amt, location, events),
+                    Some(to) => {
+                        self.change(&transfer.owner, &transfer.tick, 0, -amt, location, events);
+                        self.change(to, &transfer.tick, amt, 0, location, events);
+                    }
+                    None => self.change(&transfer.owner, &transfer.tick, 0, -amt, location, events),
+                }
+                events.operation(
+                    location,
+                    Brc20Operation {
+                        op: "transfer".into(),
+                        tick: self.tokens[&transfer.tick].tick.clone(),
+                        inscription_id: transfer.inscription_id,
+                        amt: Some(transfer.amt.to_string()),
+                        from: Some(transfer.owner),
+                        to,
+                        verdict: Verdict::Valid,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_inscriptions(&mut self, tx: &Transaction, height: u64, inscribed: &[&DecodedEvent], prevouts: &mut Prevouts, events: &mut TxEvents) -> Result<()> {
+        let envelopes = ParsedEnvelope::from_transaction(tx, ORD_ENVELOPE);
+        let output_value = tx.output.iter().map(|output| output.value).sum::<u64>();
+        for event in inscribed {
+            let Some(index) = envelopes
+                .iter()
+                .position(|envelope| envelope.input == event.input && envelope.offset == event.offset)
+            else {
+                continue;
+            };
+            let envelope = &envelopes[index];
+            let inscription_id = InscriptionId { txid: tx.txid(), index: index as u32 };
+            let offset = match envelope.payload.pointer() {
+                Some(pointer) if pointer < output_value => pointer,
+                _ => prevouts.input_offset(tx, envelope.input as usize)?,
+            };
+            let satpoint = sat_flow::locate(tx, offset);
+            let owner = satpoint.map(|satpoint| sat_flow::owner(&tx.output[satpoint.outpoint.vout as usize].script_pubkey, self.network));
+            let location = Location { input: event.input, offset: event.offset };
+            let context = Inscribed {
+                inscription_id,
+                parent: envelope.payload.parent(),
+                height,
+                location,
+            };
+
+            let (op, tick, result) = match (&event.event, satpoint.zip(owner)) {
+                (Event::Brc20Deploy(deploy), Some((_, owner))) => ("deploy", &deploy.tick, self.deploy(deploy, &context, owner)),
+                (Event::Brc20Mint(mint), Some((_, owner))) => ("mint", &mint.tick, self.mint(mint, &context, owner, events)),
+                (Event::Brc20Transfer(transfer), Some((satpoint, owner))) => {
+                    ("inscribe-transfer", &transfer.tick, self.inscribe_transfer(transfer, &context, satpoint, owner, events))
+                }
+                (Event::Brc20Deploy(Brc20Deploy { tick, .. }) | Event::Brc20Mint(Brc20Mint { tick, .. }) | Event::Brc20Transfer(Brc20Transfer { tick, .. }), None) => {
+                    (operation_name(&event.event), tick, Err("inscribed as fee".to_string()))
+                }
+                (Event::Other { payload, .. }, _) => {
+                    let op = payload.get("op").and_then(Value::as_str).unwrap_or_default();
+                    let tick = payload.get("tick").and_then(Value::as_str).unwrap_or_default().to_string();
+                    events.operation(location, invalid(op, &tick, inscription_id, "invalid operation or fields"));
+                    continue;
+                }
+                _ => continue,
+            };
+            match result {
+                Ok(operation) => events.operation(location, operation),
+                Err(reason) => events.operation(location, invalid(op, tick, inscription_id, &reason)),
+            }
+        }
+        Ok(())
+    }
+
+    fn deploy(&mut self, deploy: &Brc20Deploy, context: &Inscribed, owner: String) -> Result<Brc20Operation, String> {
+        let self_mint = match deploy.tick.len() {
+            4 => false,
+            5 if context.height < SELF_MINT_HEIGHT => return Err(format!("5 byte tickers are deployed from height {SELF_MINT_HEIGHT}")),
+            5 if deploy.extra.get("self_mint") != Some(&Value::from("true")) => return Err("5 byte tickers must be self minted".into()),
+            5 => true,
+            _ => return Err("ticker must be 4 bytes long".into()),
+        };
+        let key = deploy.tick.to_lowercase();
+        if self.tokens.contains_key(&key) {
+            return Err("ticker already deployed".into());
+        }
+        let dec = deploy.dec().map(Amount::parse_decimals).transpose()?.unwrap_or(MAX_DECIMALS);
+        let max = match Amount::parse(&deploy.max, dec)? {
+            max if max.is_zero() && self_mint => Amount::MAX,
+            max if max.is_zero() => return Err("max supply is zero".into()),
+            max => max,
+        };
+        let lim = match deploy.lim().map(|lim| Amount::parse(lim, dec)).transpose()? {
+            None => max,
+            Some(lim) if lim.is_zero() && self_mint => max,
+            Some(lim) if lim.is_zero() => return Err("mint limit is zero".into()),
+            Some(lim) => lim,
+        };
+        self.tokens.insert(
+            key,
+            Token {
+                tick: deploy.tick.clone(),
+                max,
+                lim,
+                dec,
+                minted: Amount::ZERO,
+                deploy: context.inscription_id,
+                self_mint,
+            },
+        );
+        Ok(valid("deploy", &deploy.tick, context.inscription_id, None, None, Some(owner)))
+    }
+
+    fn mint(&mut self, mint: &Brc20Mint, context: &Inscribed, owner: String, events: &mut TxEvents) -> Result<Brc20Operation, String> {
+        let key = mint.tick.to_lowercase();
+        let token = self.tokens.get_mut(&key).ok_or("ticker not deployed")?;
+        let amt = Amount::parse(&mint.amt, token.dec)?;
+        if amt.is_zero() {
+            return Err("amount is zero".into());
+        }
+        if amt > token.lim {
+            return Err("amount above the mint limit".into());
+        }
+        if token.self_mint && context.parent != Some(token.deploy) {
+            return Err("self minted tokens are minted by children of the deploy inscription".into());
+        }
+        let remaining = token.max.saturating_sub(token.minted);
+        if remaining.is_zero() {
+            return Err("fully minted".into());
+        }
+        let minted = amt.min(remaining);
+        token.minted = token.minted + minted;
+        let tick = token.tick.clone();
+        self.change(&owner, &key, minted.units() as i128, 0, context.location, events);
+        Ok(valid("mint", &tick, context.inscription_id, Some(minted), None, Some(owner)))
+    }
+
+    fn inscribe_transfer(
+        &mut self,
+        transfer: &Brc20Transfer,
+        context: &Inscribed,
+        satpoint: SatPoint,
+        owner: String,
+        events: &mut TxEvents,
+    ) -> Result<Brc20Operation, String> {
+        let key = transfer.tick.to_lowercase();
+        let token = self.tokens.get(&key).ok_or("ticker not deployed")?;
+        let amt = Amount::parse(&transfer.amt, token.dec)?;
+        if amt.is_zero() {
+            return Err("amount is zero".into());
+        }
+        if self.balance(&owner, &key).available < amt {
+            return Err("insufficient available balance".into());
+        }
+        let tick = token.tick.clone();
+        self.change(&owner, &key, -(amt.units() as i128), amt.units() as i128, context.location, events);
+        self.transfers.entry(satpoint.outpoint).or_default().push(PendingTransfer {
+            inscription_id: context.inscription_id,
+            offset: satpoint.offset,
+            tick: key,
+            amt,
+            owner: owner.clone(),
+        });
+        Ok(valid("inscribe-transfer", &tick, context.inscription_id, Some(amt), Some(owner), None))
+    }
+
+    /// change the balance of a token of an owner by signed units, and record it
+    fn change(&mut self, owner: &str, key: &str, available: i128, transferable: i128, location: Location, events: &mut TxEvents) {
+        let balance = self.balances.entry((owner.to_string(), key.to_string())).or_default();
+        balance.available = balance.available.add_signed(available);
+        balance.transferable = balance.transferable.add_signed(transferable);
+        let balance = *balance;
+        events.0.push((
+            location,
+            Event::Brc20BalanceChange(Brc20BalanceChange {
+                tick: self.tokens[key].tick.clone(),
+                owner: owner.to_string(),
+                available_change: signed(available),
+                transferable_change: signed(transferable),
+                available: balance.available.to_string(),
+                transferable: balance.transferable.to_string(),
+            }),
+        ));
+    }
+}
+
+/// the inscription of an operation
+struct Inscribed {
+    inscription_id: InscriptionId,
+    parent: Option<InscriptionId>,
+    height: u64,
+    location: Location,
+}
+
+fn operation_name(event: &Event) -> &'static str {
+    match event {
+        Event::Brc20Deploy(_) => "deploy",
+        Event::Brc20Mint(_) => "mint",
+        _ => "inscribe-transfer",
+    }
+}
+
+fn valid(op: &str, tick: &str, inscription_id: InscriptionId, amt: Option<Amount>, from: Option<String>, to: Option<String>) -> Brc20Operation {
+    Brc20Operation {
+        op: op.into(),
+        tick: tick.into(),
+        inscription_id,
+        amt: amt.map(|amt| amt.to_string()),
+        from,
+        to,
+        verdict: Verdict::Valid,
+    }
+}
+
+fn invalid(op: &str, tick: &str, inscription_id: InscriptionId, reason: &str) -> Brc20Operation {
+    Brc20Operation {
+        op: op.into(),
+        tick: tick.into(),
+        inscription_id,
+        amt: None,
+        from: None,
+        to: None,
+        verdict: Verdict::Invalid(reason.into()),
+    }
+}
+
+/// units of 10^-18 as a signed amount of tokens
+fn signed(units: i128) -> String {
+    let amount = Amount::from_units(units.unsigned_abs());
+    if units < 0 {
+        format!("-{amount}")
+    } else {
+        amount.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            decoder::Registry,
+            ord::Inscription,
+            test_util::{block, script, tx},
+        },
+        bitcoin::{hashes::Hash, Txid, Witness},
+    };
+
+    fn address(owner: u8) -> String {
+        sat_flow::owner(&script(owner), Network::Regtest)
+    }
+
+    /// a brc-20 inscription sent to `owner`, spending an output of its own
+    fn inscribe(funding: u32, body: &str, owner: u8) -> Transaction {
+        let witness = Inscription::new(Some("text/plain;charset=utf-8".into()), Some(body.as_bytes().to_vec())).to_witness();
+        tx(&[(OutPoint::new(Txid::all_zeros(), funding), witness)], &[(owner, 546)])
+    }
+
+    fn index(ledger: &mut Brc20Ledger, height: u64, txdata: Vec<Transaction>) -> Vec<Brc20Operation> {
+        let block = block(txdata);
+        let events = Registry::default().decode_block(&block, height, "all");
+        let decoded = ledger.index_block(&block, height, &events, &mut Prevouts::new(None)).unwrap();
+        decoded
+            .into_iter()
+            .filter_map(|event| match event.event {
+                Event::Brc20Operation(operation) => Some(operation),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn verdicts(operations: &[Brc20Operation]) -> Vec<(&str, Verdict)> {
+        operations.iter().map(|operation| (operation.op.as_str(), operation.verdict.clone())).collect()
+    }
+
+    #[test]
+    fn deploys_and_mints_are_validated() {
+        let mut ledger = Brc20Ledger::new(Network::Regtest);
+        let operations = index(
+            &mut ledger,
+            800000,
+            vec![
+                inscribe(0, r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"1000","lim":"600"}"#, 1),
+                inscribe(1, r#"{"p":"brc-20","op":"deploy","tick":"ORDI","max":"5"}"#, 2),
+                inscribe(2, r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"600"}"#, 1),
+                inscribe(3, r#"{"p":"brc-20","op":"mint","tick":"Ordi","amt":"600"}"#, 2),
+                inscribe(4, r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"1"}"#, 2),
+                inscribe(5, r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"700"}"#, 2),
+                inscribe(6, r#"{"p":"brc-20","op":"deploy","tick":"abc","max":"5"}"#, 2),
+                inscribe(7, r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":1}"#, 2),
+            ],
+        );
+        assert_eq!(
+            verdicts(&operations),
+            [
+                ("deploy", Verdict::Valid),
+                ("deploy", Verdict::Invalid("ticker already deployed".into())),
+                ("mint", Verdict::Valid),
+                ("mint", Verdict::Valid),
+                ("mint", Verdict::Invalid("fully minted".into())),
+                ("mint", Verdict::Invalid("amount above the mint limit".into())),
+                ("deploy", Verdict::Invalid("ticker must be 4 bytes long".into())),
+                ("mint", Verdict::Invalid("invalid operation or fields".into())),
+            ]
+        );
+        // the second mint only gets what remains
+        assert_eq!(operations[3].amt.as_deref(), Some("400"));
+        assert_eq!(operations[3].to, Some(address(2)));
+        assert_eq!(ledger.minted("ORDI"), Amount::parse("1000", 18).ok());
+        assert_eq!(ledger.balance(&address(1), "ordi").available.to_string(), "600");
+        assert_eq!(ledger.balance(&address(2), "ordi").available.to_string(), "400");
+
+        let line = serde_json::to_value(&operations[1]).unwrap();
+        assert_eq!(line["verdict"], "invalid");
+        assert_eq!(line["reason"], "ticker already deployed");
+        assert_eq!(serde_json::to_value(&operations[0]).unwrap()["verdict"], "valid");
+    }
+
+    #[test]
+    fn transfers_move_once_their_inscription_is_spent() {
+        let mut ledger = Brc20Ledger::new(Network::Regtest);
+        index(
+            &mut ledger,
+            800000,
+            vec![
+                inscribe(0, r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"1000"}"#, 1),
+                inscribe(1, r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"600"}"#, 1),
+                inscribe(2, r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"400"}"#, 2),
+            ],
+        );
+
+        let transfer = inscribe(3, r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"250"}"#, 1);
+        let overdrawn = inscribe(4, r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"351"}"#, 1);
+        let send = tx(&[(OutPoint::new(transfer.txid(), 0), Witness::new())], &[(2, 546)]);
+        let operations = index(&mut ledger, 800001, vec![transfer.clone(), overdrawn, send]);
+        assert_eq!(
+            verdicts(&operations),
+            [
+                ("inscribe-transfer", Verdict::Valid),
+                ("inscribe-transfer", Verdict::Invalid("insufficient available balance".into())),
+                ("transfer", Verdict::Valid),
+            ]
+        );
+        assert_eq!((operations[2].from.clone(), operations[2].to.clone()), (Some(address(1)), Some(address(2))));
+        assert_eq!(ledger.balance(&address(1), "ordi"), Balance { available: Amount::parse("350", 18).unwrap(), transferable: Amount::ZERO });
+        assert_eq!(ledger.balance(&address(2), "ordi").available.to_string(), "650");
+
+        // spent as fee, the transfer goes back to its sender
+        let transfer = inscribe(5, r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"50"}"#, 2);
+        let fee = tx(&[(OutPoint::new(transfer.txid(), 0), Witness::new())], &[]);
+        let operations = index(&mut ledger, 800002, vec![transfer, fee]);
+        assert_eq!(operations[1].to, Some(address(2)));
+        assert_eq!(ledger.balance(&address(2), "ordi"), Balance { available: Amount::parse("650", 18).unwrap(), transferable: Amount::ZERO });
+    }
+}
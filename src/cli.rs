@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
 
 
 /// Extract inscription events from bitcoin.
@@ -10,25 +10,77 @@ pub struct Cli {
     #[arg(short='s', long, default_value = "http://localhost:8332")]
     pub rpc_host: String,
 
-    /// bitcoin rpc server user name
-    #[arg(short='u', long, required=true)]
-    pub rpc_user: String,
+    /// bitcoin rpc server user name, required to decode and index
+    #[arg(short='u', long)]
+    pub rpc_user: Option<String>,
 
-    /// bitcoin rpc server user password
-    #[arg(short='w', long, required=true)]
-    pub rpc_pass: String,
+    /// bitcoin rpc server user password, required to decode and index
+    #[arg(short='w', long)]
+    pub rpc_pass: Option<String>,
+
+    /// read blocks and transactions from this Esplora (electrs) http api, e.g. https://blockstream.info/api, instead of bitcoind rpc
+    #[arg(long)]
+    pub esplora_url: Option<String>,
+
+    /// the chain of the addresses of the ledgers: bitcoin, testnet, signet or regtest
+    #[arg(long, default_value = "bitcoin")]
+    pub network: bitcoin::Network,
 
     /// the protocols[all,atom-arc20,atom-relam,atom-nft,atom-others,stamp-src20,rune-stone,rune-alpha,ord-brc20,ord-brc100,ord-brc420,ord-bitmap,ord-sns,ord-tap,ord] to extract,
     #[arg(short='p', long, default_value="all")]
     pub protocol: String,
 
-    /// save decoded event to local jsonl file
+    /// save decoded event to local jsonl file, `-` for stdout. Can be repeated
     #[arg(short='f', long)]
-    pub out_file: Option<String>,
+    pub out_file: Vec<String>,
 
-    /// save decoded event to postgres database
+    /// save decoded event to a postgres:// or sqlite:// database. Can be repeated
     #[arg(short='d', long)]
-    pub out_db: Option<String>,
+    pub out_db: Vec<String>,
+
+    /// save decoded event to parquet files in a directory, partitioned by protocol. Can be repeated
+    #[arg(long)]
+    pub out_parquet: Vec<String>,
+
+    /// blocks per parquet file
+    #[arg(long, default_value_t = crate::sink::DEFAULT_BLOCKS_PER_FILE)]
+    pub parquet_blocks: u64,
+
+    /// save decoded event to compressed jsonl files in a directory, one per window of --rotate-blocks blocks. Can be repeated
+    #[arg(long)]
+    pub out_dir: Vec<String>,
+
+    /// blocks per file of --out-dir
+    #[arg(long, default_value_t = crate::sink::DEFAULT_BLOCKS_PER_FILE)]
+    pub rotate_blocks: u64,
+
+    /// compression of the files of --out-dir
+    #[arg(long, value_enum, default_value_t = crate::sink::Compression::Zstd)]
+    pub compression: crate::sink::Compression,
+
+    /// read the blocks of `decode --block` from the blk*.dat files of this bitcoind `blocks` directory instead of rpc, up to the best block of the node when rpc or esplora is given
+    #[arg(long)]
+    pub blocks_dir: Option<String>,
+
+    /// bitcoind zmqpubhashblock or zmqpubrawblock endpoint, e.g. tcp://127.0.0.1:28332, to index new blocks as soon as they are announced
+    #[arg(long)]
+    pub zmq: Option<String>,
+
+    /// seconds between two looks at the tip while waiting for a block, without --zmq
+    #[arg(long, default_value_t = 1)]
+    pub poll_interval: u64,
+
+    /// blocks fetched at once from bitcoind or esplora, each over its own connection
+    #[arg(long, default_value_t = 4)]
+    pub fetchers: usize,
+
+    /// threads decoding blocks, one per cpu if omitted
+    #[arg(long)]
+    pub workers: Option<usize>,
+
+    /// blocks fetched and decoded ahead of the one written to the outputs, which bounds memory
+    #[arg(long, default_value_t = 64)]
+    pub max_blocks: usize,
 
     #[command(subcommand)]
     pub command: Option<Commands>,
@@ -38,6 +90,7 @@ pub struct Cli {
 pub enum Commands {
     /// blocks params
     #[command(about = "decode specific block height or transaction id")]
+    #[command(group(ArgGroup::new("input").required(true).args(["block", "txid", "raw_tx", "raw_block"])))]
     Decode {
         /// the block height to extract, single(824958) or range(820000:820010)
         #[arg(short='H', long)]
@@ -46,14 +99,115 @@ pub enum Commands {
         /// the tx id to extract, single(04ij...dhf92) or range(04ij...dhf92,jgi..8gjs)
         #[arg(short='T', long)]
         txid: Option<String>,
-        
+
+        /// a serialized transaction to decode without rpc: hex, @file or - for stdin. Can be repeated
+        #[arg(long)]
+        raw_tx: Vec<String>,
+
+        /// a serialized block to decode without rpc: hex, @file or - for stdin. Can be repeated
+        #[arg(long)]
+        raw_block: Vec<String>,
+
     },
     /// transactions params
     #[command(about = "scan all blocks to latest height in real time")]
     Index {
-        /// lists test values
+        /// the block height to start from, resume after the last indexed block if omitted
+        #[arg(short='S', long)]
+        start: Option<u64>,
+
+    },
+    /// follow the mempool
+    #[command(
+        about = "decode unconfirmed transactions and follow them until they leave the mempool",
+        long_about = "decode unconfirmed transactions and follow them until they leave the mempool. \
+            Updates are written to --out-file outputs only: the rows of --out-db, --out-parquet \
+            and --out-dir outputs would stay once their transaction is replaced or evicted"
+    )]
+    Mempool {
+        /// seconds between two polls of the mempool
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+    },
+    /// brc-20 ledger
+    #[command(about = "validate brc-20 operations and track balances, writing verdicts and balance changes")]
+    Brc20 {
+        /// the first block, at most and by default the first brc-20 deploy (779832) on mainnet, else 0
+        #[arg(short='S', long)]
+        start: Option<u64>,
+
+        /// the last block, the tip if omitted
+        #[arg(short='E', long)]
+        end: Option<u64>,
+    },
+    /// inscription transfers
+    #[command(about = "follow ord, bitmap, brc-420 and sns inscriptions, writing their transfers")]
+    Transfers {
+        /// the first block, at most and by default the first inscription (767430) on mainnet, else 0
+        #[arg(short='S', long)]
+        start: Option<u64>,
+
+        /// the last block, the tip if omitted
+        #[arg(short='E', long)]
+        end: Option<u64>,
+    },
+    /// runes state
+    #[command(about = "index the runes state: etchings, mints, burns and the runes of every output")]
+    Runes {
+        #[command(subcommand)]
+        action: RunesCommands,
+    },
+    /// load jsonl archives into the outputs
+    #[command(about = "load events of jsonl files written by --out-file or --out-dir into the outputs")]
+    Replay {
+        /// the jsonl files to load, plain or compressed (.zst, .gz), as paths or glob patterns. Can be repeated
+        #[arg(short='i', long, required = true, num_args = 1..)]
+        input: Vec<String>,
+    },
+    /// manage the schema of the `--out-db` database
+    #[command(about = "create, migrate or drop the tables of the output database")]
+    Db {
+        #[command(subcommand)]
+        action: DbCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbCommands {
+    /// create the tables in an empty database
+    Init,
+    /// apply the pending schema migrations
+    Migrate,
+    /// list the schema migrations and whether they are applied
+    Status,
+    /// drop every table created by inscan, with its data
+    Drop,
+}
+
+#[derive(Subcommand)]
+pub enum RunesCommands {
+    /// apply the runestones of the blocks and write the etchings, mints, burns and balance changes
+    Index {
+        /// the first block, at most and by default the first rune block (840000) on mainnet
         #[arg(short='S', long)]
-        start: u64,
+        start: Option<u64>,
+
+        /// the last block, the tip if omitted
+        #[arg(short='E', long)]
+        end: Option<u64>,
+    },
+    /// preview where the runes of a transaction go, without index, and print it as json
+    Simulate {
+        /// the serialized transaction: hex, @file or - for stdin
+        #[arg(long)]
+        raw_tx: String,
 
-    }
+        /// the runes of each input, in order, as json: `[{"840000:1":{"amount":1000,"divisibility":2,"symbol":"x"}},{}]`
+        #[arg(long, default_value = "[]")]
+        inputs: String,
+
+        /// what the mint of the transaction mints, as json: `{"amount":100,"divisibility":2,"symbol":"x"}`
+        #[arg(long)]
+        mint: Option<String>,
+    },
 }
@@ -0,0 +1,197 @@
+use {
+    anyhow::{bail, Result},
+    bitcoin::BlockHash,
+    serde::{Deserialize, Serialize},
+    crate::decoder::ALL_PROTOCOLS,
+    sqlx::{Executor, PgPool, Postgres, Row, Sqlite, SqlitePool, Transaction},
+    std::{fs, io::ErrorKind, path::PathBuf},
+};
+
+/// the last block whose events are fully written to an output
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub height: u64,
+    pub block_hash: BlockHash,
+}
+
+/// the state file next to a jsonl output, `events.jsonl.cursor` for `events.jsonl`
+pub fn file_path(output: &str) -> PathBuf {
+    PathBuf::from(format!("{output}.cursor"))
+}
+
+/// the content of a state file. An output file holds the events of one
+/// protocol, rolled back all together, so it has a single cursor. Files written
+/// before the protocol was recorded have none.
+#[derive(Serialize, Deserialize)]
+struct StateFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protocol: Option<String>,
+    #[serde(flatten)]
+    cursor: Cursor,
+}
+
+fn read_file(output: &str) -> Result<Option<StateFile>> {
+    match fs::read(file_path(output)) {
+        Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// the cursor of the state file, which fails if the output is indexed for
+/// another protocol: its blocks below the cursor weren't decoded for `protocol`
+pub fn load_file(output: &str, protocol: &str) -> Result<Option<Cursor>> {
+    let Some(state) = read_file(output)? else {
+        return Ok(None);
+    };
+    match state.protocol {
+        Some(indexed) if indexed != protocol => {
+            bail!("{output} is indexed for {indexed:?}, not {protocol:?}, use another output")
+        }
+        _ => Ok(Some(state.cursor)),
+    }
+}
+
+/// the height of the state file, whatever the protocol
+pub fn file_height(output: &str) -> Result<Option<u64>> {
+    Ok(read_file(output)?.map(|state| state.cursor.height))
+}
+
+/// replace the state file, through a rename so it is never half written
+pub fn save_file(output: &str, protocol: &str, cursor: &Cursor) -> Result<()> {
+    load_file(output, protocol)?;
+    let path = file_path(output);
+    let tmp = path.with_extension("cursor.tmp");
+    let state = StateFile {
+        protocol: Some(protocol.to_string()),
+        cursor: *cursor,
+    };
+    fs::write(&tmp, serde_json::to_vec(&state)?)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// the rollback of an indexer of every protocol deletes the rows of the
+/// per-protocol indexers, and theirs delete rows it wrote, so they can't share a database
+fn check_mixed(other: Option<String>, protocol: &str) -> Result<()> {
+    match other {
+        Some(other) => bail!(
+            "the database is indexed for {other:?}, it can't be indexed for {protocol:?} too: \
+            {ALL_PROTOCOLS:?} and single protocols need separate databases"
+        ),
+        None => Ok(()),
+    }
+}
+
+async fn check_mixed_pg<'e>(executor: impl Executor<'e, Database = Postgres>, protocol: &str) -> Result<()> {
+    let other = sqlx::query_scalar(
+        "SELECT protocol FROM public.inscan_cursor WHERE protocol <> $1 AND (protocol = $2 OR $1 = $2) LIMIT 1",
+    )
+    .bind(protocol)
+    .bind(ALL_PROTOCOLS)
+    .fetch_optional(executor)
+    .await?;
+    check_mixed(other, protocol)
+}
+
+async fn check_mixed_sqlite<'e>(executor: impl Executor<'e, Database = Sqlite>, protocol: &str) -> Result<()> {
+    let other = sqlx::query_scalar(
+        "SELECT protocol FROM inscan_cursor WHERE protocol <> ?1 AND (protocol = ?2 OR ?1 = ?2) LIMIT 1",
+    )
+    .bind(protocol)
+    .bind(ALL_PROTOCOLS)
+    .fetch_optional(executor)
+    .await?;
+    check_mixed(other, protocol)
+}
+
+/// the cursor of the indexer extracting `protocol`, from `public.inscan_cursor`
+pub async fn load_pg(pool: &PgPool, protocol: &str) -> Result<Option<Cursor>> {
+    check_mixed_pg(pool, protocol).await?;
+    let row = sqlx::query("SELECT height, block_hash FROM public.inscan_cursor WHERE protocol = $1")
+        .bind(protocol)
+        .fetch_optional(pool)
+        .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    Ok(Some(Cursor {
+        height: row.try_get::<i32, _>("height")? as u64,
+        block_hash: row.try_get::<&str, _>("block_hash")?.parse()?,
+    }))
+}
+
+/// move the cursor of `protocol` within the transaction writing the events of its block
+pub async fn save_pg(tx: &mut Transaction<'_, Postgres>, protocol: &str, cursor: &Cursor) -> Result<()> {
+    check_mixed_pg(&mut **tx, protocol).await?;
+    sqlx::query(
+        "INSERT INTO public.inscan_cursor (protocol, height, block_hash) VALUES ($1, $2, $3)
+        ON CONFLICT (protocol) DO UPDATE SET height = EXCLUDED.height, block_hash = EXCLUDED.block_hash",
+    )
+    .bind(protocol)
+    .bind(cursor.height as i32)
+    .bind(cursor.block_hash.to_string())
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// the cursor of the indexer extracting `protocol`, from the `inscan_cursor` table of a sqlite output
+pub async fn load_sqlite(pool: &SqlitePool, protocol: &str) -> Result<Option<Cursor>> {
+    check_mixed_sqlite(pool, protocol).await?;
+    let row = sqlx::query("SELECT height, block_hash FROM inscan_cursor WHERE protocol = ?")
+        .bind(protocol)
+        .fetch_optional(pool)
+        .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    Ok(Some(Cursor {
+        height: row.try_get::<i64, _>("height")? as u64,
+        block_hash: row.try_get::<&str, _>("block_hash")?.parse()?,
+    }))
+}
+
+pub async fn save_sqlite(tx: &mut Transaction<'_, Sqlite>, protocol: &str, cursor: &Cursor) -> Result<()> {
+    check_mixed_sqlite(&mut **tx, protocol).await?;
+    sqlx::query(
+        "INSERT INTO inscan_cursor (protocol, height, block_hash) VALUES (?, ?, ?)
+        ON CONFLICT (protocol) DO UPDATE SET height = excluded.height, block_hash = excluded.block_hash",
+    )
+    .bind(protocol)
+    .bind(cursor.height as i64)
+    .bind(cursor.block_hash.to_string())
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, bitcoin::hashes::Hash};
+
+    #[test]
+    fn file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("events.jsonl");
+        let output = output.to_str().unwrap();
+        assert_eq!(load_file(output, "ord-brc20").unwrap(), None);
+
+        let cursor = Cursor {
+            height: 840000,
+            block_hash: BlockHash::from_byte_array([7; 32]),
+        };
+        save_file(output, "ord-brc20", &cursor).unwrap();
+        assert_eq!(load_file(output, "ord-brc20").unwrap(), Some(cursor));
+        assert_eq!(file_height(output).unwrap(), Some(840000));
+        assert!(file_path(output).to_str().unwrap().ends_with("events.jsonl.cursor"));
+
+        // one protocol per output
+        assert!(load_file(output, "ord-sns").is_err());
+        assert!(save_file(output, "ord-sns", &cursor).is_err());
+
+        // state files of former versions, without protocol
+        fs::write(file_path(output), serde_json::to_vec(&cursor).unwrap()).unwrap();
+        assert_eq!(load_file(output, "ord-sns").unwrap(), Some(cursor));
+    }
+}
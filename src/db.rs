@@ -0,0 +1,137 @@
+use {
+    anyhow::{bail, Result},
+    sqlx::{
+        migrate::{Migrate, Migrator},
+        sqlite::{SqliteConnectOptions, SqlitePool},
+        Database, Executor, Pool,
+    },
+    std::{collections::BTreeSet, str::FromStr},
+};
+
+/// the schema of the postgres output, embedded from `sql/migrations/postgres`
+pub static PG_MIGRATOR: Migrator = sqlx::migrate!("./sql/migrations/postgres");
+
+/// the schema of the sqlite output, embedded from `sql/migrations/sqlite`
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./sql/migrations/sqlite");
+
+/// every table inscan creates, dropped by [`drop`]
+const TABLES: &[&str] = &["inscan_events", "inscan_cursor", "_sqlx_migrations"];
+
+/// a migration and whether it is applied to the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// open a `sqlite://` database, creating the file if needed
+pub async fn connect_sqlite(url: &str) -> Result<SqlitePool> {
+    let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+    Ok(SqlitePool::connect_with(options).await?)
+}
+
+async fn applied<DB: Database>(pool: &Pool<DB>) -> Result<BTreeSet<i64>>
+where
+    DB::Connection: Migrate,
+{
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect())
+}
+
+/// create the schema in a database inscan never wrote to
+pub async fn init<DB: Database>(pool: &Pool<DB>, migrator: &Migrator) -> Result<()>
+where
+    DB::Connection: Migrate,
+{
+    if !applied(pool).await?.is_empty() {
+        bail!("database is already initialized, run `inscan db migrate` to update it");
+    }
+    migrator.run(pool).await?;
+    Ok(())
+}
+
+/// apply the pending migrations. The first postgres ones only create what is missing,
+/// so tables created by hand with the former `sql/db_init.sql` are picked up.
+pub async fn migrate<DB: Database>(pool: &Pool<DB>, migrator: &Migrator) -> Result<()>
+where
+    DB::Connection: Migrate,
+{
+    migrator.run(pool).await?;
+    Ok(())
+}
+
+pub async fn status<DB: Database>(pool: &Pool<DB>, migrator: &Migrator) -> Result<Vec<MigrationStatus>>
+where
+    DB::Connection: Migrate,
+{
+    let applied = applied(pool).await?;
+    Ok(migrator
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains(&migration.version),
+        })
+        .collect())
+}
+
+/// fail unless every migration is applied, so nothing is written into a missing or outdated table
+pub async fn check<DB: Database>(pool: &Pool<DB>, migrator: &Migrator) -> Result<()>
+where
+    DB::Connection: Migrate,
+{
+    let pending: Vec<String> = status(pool, migrator)
+        .await?
+        .into_iter()
+        .filter(|migration| !migration.applied)
+        .map(|migration| format!("{} {}", migration.version, migration.description))
+        .collect();
+    if !pending.is_empty() {
+        bail!(
+            "database schema is out of date, run `inscan db migrate` (pending: {})",
+            pending.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// drop every inscan table, with the events in it
+pub async fn drop<DB: Database>(pool: &Pool<DB>) -> Result<()>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    for table in TABLES {
+        pool.execute(format!("DROP TABLE IF EXISTS {table}").as_str()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqlite_schema_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("events.db").display());
+        futures::executor::block_on(async {
+            let pool = connect_sqlite(&url).await.unwrap();
+            assert!(check(&pool, &SQLITE_MIGRATOR).await.is_err());
+
+            init(&pool, &SQLITE_MIGRATOR).await.unwrap();
+            check(&pool, &SQLITE_MIGRATOR).await.unwrap();
+            assert!(init(&pool, &SQLITE_MIGRATOR).await.is_err());
+            assert!(status(&pool, &SQLITE_MIGRATOR).await.unwrap().iter().all(|migration| migration.applied));
+
+            drop(&pool).await.unwrap();
+            assert!(check(&pool, &SQLITE_MIGRATOR).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,107 @@
+use {
+    super::DecodeError,
+    crate::ord::Inscription,
+    anyhow::Result,
+    base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+    serde_json::Value,
+};
+
+/// the cbor payload of an atomicals operation as json
+fn decode_payload(inscription: &Inscription) -> Result<Value> {
+    let body = inscription.body().ok_or(DecodeError::ContentBodyNull)?;
+    let cbor_value: ciborium::Value = ciborium::de::from_reader(body)?; //TODO: get the diagnostic notation result
+    Ok(cbor_to_json(cbor_value))
+}
+
+/// whether the payload requests a realm or subrealm
+fn is_realm(payload: &Value) -> bool {
+    !payload["args"]["request_realm"].is_null() || !payload["args"]["request_subrealm"].is_null()
+}
+
+pub(crate) fn decode_atom_arc20(inscription: Inscription) -> Result<Value> {
+    let op = inscription.content_type().ok_or(DecodeError::ContentTypeNull)?;
+    if op != "dft" //TODO: add other op
+        && op != "ft"
+        && op != "dmt"
+        && op != "y"
+    {
+        return Err(DecodeError::ContentTypeNotValid.into());
+    }
+    decode_payload(&inscription)
+}
+
+fn cbor_into_string(cbor: ciborium::Value) -> Option<String> {
+    match cbor {
+        ciborium::Value::Text(string) => Some(string),
+        _ => None,
+    }
+}
+
+pub fn cbor_to_json(cbor: ciborium::Value) -> Value {
+    match cbor {
+        ciborium::Value::Null => Value::Null,
+        ciborium::Value::Bool(boolean) => Value::Bool(boolean),
+        ciborium::Value::Text(string) => Value::String(string),
+        ciborium::Value::Integer(int) => Value::Number({
+            let int: i128 = int.into();
+            if let Ok(int) = u64::try_from(int) {
+                serde_json::Number::from(int)
+            } else if let Ok(int) = i64::try_from(int) {
+                serde_json::Number::from(int)
+            } else {
+                serde_json::Number::from_f64(int as f64).unwrap()
+            }
+        }),
+        ciborium::Value::Float(float) => Value::Number(serde_json::Number::from_f64(float).unwrap()),
+        ciborium::Value::Array(vec) => Value::Array(vec.into_iter().map(cbor_to_json).collect()),
+        ciborium::Value::Map(map) => Value::Object(map.into_iter().map(|(k, v)| (cbor_into_string(k).unwrap(), cbor_to_json(v))).collect()),
+        ciborium::Value::Bytes(byte) => Value::String(BASE64.encode(byte)),
+        ciborium::Value::Tag(_, _) => unimplemented!(),
+        _ => unimplemented!(),
+    }
+}
+
+// TODO:  atom should be decoded in one place, and let the application decide which one to use
+pub(crate) fn decode_atom_relam(inscription: Inscription) -> Result<Value> {
+    let op = inscription.content_type().ok_or(DecodeError::ContentTypeNull)?;
+    if op != "nft" {
+        return Err(DecodeError::ContentTypeNotValid.into());
+    }
+    let jsons = decode_payload(&inscription)?;
+    if is_realm(&jsons) {
+        Ok(jsons)
+    } else {
+        Err(DecodeError::ContentTypeNotValid.into())
+    }
+}
+
+pub(crate) fn decode_atom_nft(inscription: Inscription) -> Result<Value> {
+    let op = inscription.content_type().ok_or(DecodeError::ContentTypeNull)?;
+    if op != "nft" {
+        return Err(DecodeError::ContentTypeNotValid.into());
+    }
+    let jsons = decode_payload(&inscription)?;
+    if is_realm(&jsons) {
+        Err(DecodeError::ContentTypeNotValid.into())
+    } else {
+        Ok(jsons)
+    }
+}
+
+pub(crate) fn decode_atom_others(inscription: Inscription) -> Result<Value> {
+    let op = inscription.content_type().ok_or(DecodeError::ContentTypeNull)?;
+    if op != "mod"
+       && op != "evt"
+       && op != "dat"
+       && op != "sl"
+       && op != "x"
+    {
+        return Err(DecodeError::ContentTypeNotValid.into());
+    }
+    let jsons = decode_payload(&inscription)?;
+    if is_realm(&jsons) {
+        Err(DecodeError::ContentTypeNotValid.into())
+    } else {
+        Ok(jsons)
+    }
+}
@@ -0,0 +1,569 @@
+use {
+    crate::ord::{Inscription, ParsedEnvelope},
+    anyhow::Result,
+    bitcoin::{Block, Transaction},
+    lazy_static::lazy_static,
+    serde::{Deserialize, Serialize},
+    std::collections::BTreeMap,
+    thiserror,
+};
+
+pub use {
+    self::{
+        atomicals::cbor_to_json,
+        ord::{CompactInscription, CompactOutput, Ord},
+        stamps::decode_stamp_src20,
+    },
+    crate::event::Event,
+};
+
+mod atomicals;
+pub(crate) mod ord;
+mod runes;
+mod stamps;
+
+/// envelope protocol id of ordinals inscriptions
+pub const ORD_ENVELOPE: &[u8] = b"ord";
+/// envelope protocol id of atomicals operations
+pub const ATOM_ENVELOPE: &[u8] = b"atom";
+
+/// protocol name that selects every registered decoder
+pub const ALL_PROTOCOLS: &str = "all";
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum DecodeError {
+    #[error("ContentTypeNull")]
+    ContentTypeNull,
+    #[error("ContentTypeNotValid")]
+    ContentTypeNotValid,
+    #[error("ContentBodyNull")]
+    ContentBodyNull,
+    #[error("ContentBodyNotJson")]
+    ContentBodyNotJson,
+}
+
+/// where an event was found in its transaction: the input and the offset of its
+/// envelope in the witness, or zero for events read from the transaction itself
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    pub input: u32,
+    pub offset: u32,
+}
+
+impl From<&ParsedEnvelope> for Location {
+    fn from(envelope: &ParsedEnvelope) -> Self {
+        Self {
+            input: envelope.input,
+            offset: envelope.offset,
+        }
+    }
+}
+
+/// an event with the transaction and block it was decoded from, one line of the jsonl output
+#[derive(Serialize, Eq, PartialEq, Deserialize, Debug, Clone)]
+pub struct DecodedEvent {
+    pub height: Option<i64>, //if decode a single txid event this will be None
+    pub blocktime: Option<i32>,
+    pub txhash: String,
+    pub txindex: Option<i32>,
+    #[serde(default)]
+    pub input: u32,
+    #[serde(default)]
+    pub offset: u32,
+    /// the position of the event among the events of its protocol at the same location
+    #[serde(default)]
+    pub ordinal: u32,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl DecodedEvent {
+    /// the identity of the event, the same however many times its transaction is decoded
+    pub fn key(&self) -> EventKey {
+        EventKey {
+            txhash: self.txhash.clone(),
+            input: self.input,
+            offset: self.offset,
+            protocol: self.event.protocol().to_string(),
+            ordinal: self.ordinal,
+        }
+    }
+}
+
+/// the unique key of a [`DecodedEvent`]. It deserializes from a jsonl line too.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventKey {
+    pub txhash: String,
+    #[serde(default)]
+    pub input: u32,
+    #[serde(default)]
+    pub offset: u32,
+    pub protocol: String,
+    #[serde(default)]
+    pub ordinal: u32,
+}
+
+/// what [`decode`] and [`decode_block`] extract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// a protocol name such as `ord-brc20`, or `all`. Unknown names decode nothing.
+    pub protocol: String,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self::new(ALL_PROTOCOLS)
+    }
+}
+
+impl DecodeOptions {
+    pub fn new(protocol: &str) -> Self {
+        Self { protocol: protocol.to_lowercase() }
+    }
+}
+
+lazy_static! {
+    static ref BUILTIN: Registry = Registry::default();
+}
+
+/// decode a transaction with the bundled protocols, no bitcoin node required
+pub fn decode(tx: &Transaction, options: &DecodeOptions) -> Vec<Event> {
+    BUILTIN.decode(tx, &options.protocol)
+}
+
+/// decode every transaction of the block at `height` with the bundled protocols
+pub fn decode_block(block: &Block, height: u64, options: &DecodeOptions) -> Vec<DecodedEvent> {
+    BUILTIN.decode_block(block, height, &options.protocol)
+}
+
+/// decodes the events of one protocol from a transaction.
+///
+/// Implement this in a downstream crate and add it to a [`Registry`] to index
+/// an in-house protocol without touching the scanner.
+pub trait ProtocolDecoder: Send + Sync {
+    /// protocol name used by `--protocol` and the `protocol` field of events, e.g. `ord-brc20`
+    fn name(&self) -> &str;
+
+    /// envelope protocol id whose parsed envelopes are handed to `decode`, which is
+    /// then only called for transactions carrying at least one such envelope.
+    /// `None` if the decoder reads the raw transaction only
+    fn envelope(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// decoders with a higher priority run first. Once a decoder produced events
+    /// from an envelope protocol, decoders of a lower priority on the same
+    /// envelope protocol are skipped for that transaction.
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// `envelopes` holds the envelopes of `self.envelope()`, or is empty when it is `None`.
+    /// Each event comes with the location it was found at, part of its identity.
+    fn decode(&self, tx: &Transaction, envelopes: &[ParsedEnvelope]) -> Vec<(Location, Event)>;
+}
+
+/// decodes each envelope of one envelope protocol on its own
+pub struct EnvelopeDecoder {
+    name: &'static str,
+    envelope: &'static [u8],
+    decode: fn(Inscription) -> Result<serde_json::Value>,
+}
+
+impl EnvelopeDecoder {
+    pub fn new(
+        name: &'static str,
+        envelope: &'static [u8],
+        decode: fn(Inscription) -> Result<serde_json::Value>,
+    ) -> Self {
+        Self { name, envelope, decode }
+    }
+}
+
+impl ProtocolDecoder for EnvelopeDecoder {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn envelope(&self) -> Option<&[u8]> {
+        Some(self.envelope)
+    }
+
+    fn decode(&self, _tx: &Transaction, envelopes: &[ParsedEnvelope]) -> Vec<(Location, Event)> {
+        envelopes
+            .iter()
+            .filter_map(|envelope| {
+                let payload = (self.decode)(envelope.payload.clone()).ok()?;
+                Some((envelope.into(), Event::from_parts(self.name, payload)))
+            })
+            .collect()
+    }
+}
+
+/// decodes a protocol stored outside of envelopes, e.g. in outputs
+pub struct TransactionDecoder {
+    name: &'static str,
+    decode: fn(&Transaction) -> Result<serde_json::Value>,
+}
+
+impl TransactionDecoder {
+    pub fn new(name: &'static str, decode: fn(&Transaction) -> Result<serde_json::Value>) -> Self {
+        Self { name, decode }
+    }
+}
+
+impl ProtocolDecoder for TransactionDecoder {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn decode(&self, tx: &Transaction, _envelopes: &[ParsedEnvelope]) -> Vec<(Location, Event)> {
+        (self.decode)(tx)
+            .map(|payload| vec![(Location::default(), Event::from_parts(self.name, payload))])
+            .unwrap_or_default()
+    }
+}
+
+/// the set of decoders a scan runs, ordered by priority
+pub struct Registry {
+    decoders: Vec<Box<dyn ProtocolDecoder>>,
+}
+
+impl Default for Registry {
+    /// a registry with every protocol bundled with inscan
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register(EnvelopeDecoder::new("atom-arc20", ATOM_ENVELOPE, atomicals::decode_atom_arc20))
+            .register(EnvelopeDecoder::new("atom-relam", ATOM_ENVELOPE, atomicals::decode_atom_relam))
+            .register(EnvelopeDecoder::new("atom-nft", ATOM_ENVELOPE, atomicals::decode_atom_nft))
+            .register(EnvelopeDecoder::new("atom-others", ATOM_ENVELOPE, atomicals::decode_atom_others))
+            .register(TransactionDecoder::new("stamp-src20", stamps::decode_stamp_src20))
+            .register(TransactionDecoder::new("rune-stone", runes::decode_rune_stone))
+            .register(TransactionDecoder::new("rune-alpha", runes::decode_rune_alpha))
+            .register(EnvelopeDecoder::new("ord-brc20", ORD_ENVELOPE, ord::decode_ord_brc20))
+            .register(EnvelopeDecoder::new("ord-brc100", ORD_ENVELOPE, ord::decode_ord_brc100))
+            .register(EnvelopeDecoder::new("ord-brc420", ORD_ENVELOPE, ord::decode_ord_brc420))
+            .register(EnvelopeDecoder::new("ord-bitmap", ORD_ENVELOPE, ord::decode_ord_bitmap))
+            .register(EnvelopeDecoder::new("ord-sns", ORD_ENVELOPE, ord::decode_ord_sns))
+            .register(EnvelopeDecoder::new("ord-tap", ORD_ENVELOPE, ord::decode_ord_tap))
+            .register(Ord);
+        registry
+    }
+}
+
+impl Registry {
+    /// a registry without any decoder
+    pub fn empty() -> Self {
+        Self { decoders: Vec::new() }
+    }
+
+    /// add a decoder, replacing a registered decoder of the same name
+    pub fn register(&mut self, decoder: impl ProtocolDecoder + 'static) -> &mut Self {
+        let decoder: Box<dyn ProtocolDecoder> = Box::new(decoder);
+        match self.decoders.iter().position(|item| item.name() == decoder.name()) {
+            Some(idx) => self.decoders[idx] = decoder,
+            None => self.decoders.push(decoder),
+        }
+        // stable, so decoders of the same priority keep their registration order
+        self.decoders.sort_by_key(|decoder| std::cmp::Reverse(decoder.priority()));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn ProtocolDecoder> {
+        self.decoders
+            .iter()
+            .find(|decoder| decoder.name() == name)
+            .map(|decoder| decoder.as_ref())
+    }
+
+    /// names of the registered decoders in the order they run
+    pub fn names(&self) -> Vec<&str> {
+        self.decoders.iter().map(|decoder| decoder.name()).collect()
+    }
+
+    /// whether `protocol` is `all` or the name of a registered decoder
+    pub fn supports(&self, protocol: &str) -> bool {
+        protocol == ALL_PROTOCOLS || self.get(protocol).is_some()
+    }
+
+    /// decode the events of `protocol` (a decoder name or `all`) from a transaction
+    pub fn decode(&self, tx: &Transaction, protocol: &str) -> Vec<Event> {
+        self.decode_located(tx, protocol)
+            .into_iter()
+            .map(|(_, event)| event)
+            .collect()
+    }
+
+    /// like [`Registry::decode`], with the location of each event
+    pub fn decode_located(&self, tx: &Transaction, protocol: &str) -> Vec<(Location, Event)> {
+        let selected: Vec<&dyn ProtocolDecoder> = if protocol == ALL_PROTOCOLS {
+            self.decoders.iter().map(|decoder| decoder.as_ref()).collect()
+        } else {
+            self.get(protocol).into_iter().collect()
+        };
+
+        // envelopes are parsed once per envelope protocol and shared by its decoders
+        let mut envelopes: BTreeMap<&[u8], Vec<ParsedEnvelope>> = BTreeMap::new();
+        // envelope protocols claimed by a decoder, with the priority it ran at
+        let mut claimed: BTreeMap<&[u8], i32> = BTreeMap::new();
+        let mut events = Vec::new();
+
+        for decoder in selected {
+            let decoded = match decoder.envelope() {
+                Some(envelope) => {
+                    if claimed.get(envelope).is_some_and(|priority| *priority > decoder.priority()) {
+                        continue;
+                    }
+                    let parsed = envelopes
+                        .entry(envelope)
+                        .or_insert_with(|| ParsedEnvelope::from_transaction(tx, envelope));
+                    if parsed.is_empty() {
+                        continue;
+                    }
+                    let decoded = decoder.decode(tx, parsed);
+                    if !decoded.is_empty() {
+                        claimed.entry(envelope).or_insert(decoder.priority());
+                    }
+                    decoded
+                }
+                None => decoder.decode(tx, &[]),
+            };
+            events.extend(decoded);
+        }
+        events
+    }
+
+    /// decode the events of `protocol` from a transaction, without block context
+    pub fn decode_events(&self, tx: &Transaction, protocol: &str) -> Vec<DecodedEvent> {
+        located_events(tx, None, self.decode_located(tx, protocol))
+    }
+
+    /// decode the events of `protocol` from every transaction of the block at `height`
+    pub fn decode_block(&self, block: &Block, height: u64, protocol: &str) -> Vec<DecodedEvent> {
+        let mut events = Vec::new();
+        for (idx, tx) in block.txdata.iter().enumerate() {
+            events.extend(located_events(tx, Some((block, height, idx)), self.decode_located(tx, protocol)));
+        }
+        events
+    }
+}
+
+/// the events of a transaction with their key, and the block at a height and
+/// the index of the transaction in it when `block` is given. Events of a
+/// protocol at the same location are told apart by their order, their `ordinal`.
+pub fn located_events(tx: &Transaction, block: Option<(&Block, u64, usize)>, events: Vec<(Location, Event)>) -> Vec<DecodedEvent> {
+    if events.is_empty() {
+        return Vec::new();
+    }
+    let txhash = tx.txid().to_string();
+    let mut ordinals: BTreeMap<(Location, String), u32> = BTreeMap::new();
+    let mut decoded = Vec::with_capacity(events.len());
+    for (location, event) in events {
+        let ordinal = ordinals.entry((location, event.protocol().to_string())).or_default();
+        decoded.push(DecodedEvent {
+            height: block.map(|(_, height, _)| height as i64),
+            blocktime: block.map(|(block, _, _)| block.header.time as i32),
+            txhash: txhash.clone(),
+            txindex: block.map(|(_, _, txindex)| txindex as i32),
+            input: location.input,
+            offset: location.offset,
+            ordinal: *ordinal,
+            event,
+        });
+        *ordinal += 1;
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        bitcoin::{
+            block::{Header, Version},
+            hashes::Hash,
+            locktime::absolute::LockTime,
+            hash_types::TxMerkleNode,
+            BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence, TxIn,
+        },
+    };
+
+    fn inscription(content_type: &str, body: &str) -> Inscription {
+        Inscription::new(Some(content_type.into()), Some(body.into()))
+    }
+
+    fn transaction(inscriptions: &[Inscription]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: inscriptions
+                .iter()
+                .map(|inscription| TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: inscription.to_witness(),
+                })
+                .collect(),
+            output: Vec::new(),
+        }
+    }
+
+    fn protocols(events: &[Event]) -> Vec<&str> {
+        events.iter().map(|event| event.protocol()).collect()
+    }
+
+    const BRC20_MINT: &str = r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"1000"}"#;
+
+    struct Claim;
+
+    impl ProtocolDecoder for Claim {
+        fn name(&self) -> &str {
+            "ord-claim"
+        }
+
+        fn envelope(&self) -> Option<&[u8]> {
+            Some(ORD_ENVELOPE)
+        }
+
+        fn priority(&self) -> i32 {
+            1
+        }
+
+        fn decode(&self, _tx: &Transaction, envelopes: &[ParsedEnvelope]) -> Vec<(Location, Event)> {
+            envelopes
+                .iter()
+                .map(|envelope| (envelope.into(), Event::Other { protocol: self.name().into(), payload: serde_json::Value::Null }))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn builtin_protocols_run_in_registration_order_with_ord_last() {
+        assert_eq!(
+            Registry::default().names(),
+            [
+                "atom-arc20", "atom-relam", "atom-nft", "atom-others",
+                "stamp-src20",
+                "rune-stone", "rune-alpha",
+                "ord-brc20", "ord-brc100", "ord-brc420", "ord-bitmap", "ord-sns", "ord-tap", "ord",
+            ],
+        );
+    }
+
+    #[test]
+    fn supports_all_and_registered_names() {
+        let registry = Registry::default();
+        assert!(registry.supports("all"));
+        assert!(registry.supports("ord-brc20"));
+        assert!(!registry.supports("ord-brc21"));
+        assert!(!Registry::empty().supports("ord"));
+    }
+
+    #[test]
+    fn specific_protocol_claims_envelopes_from_ord() {
+        let tx = transaction(&[inscription("text/plain;charset=utf-8", BRC20_MINT)]);
+        let events = Registry::default().decode(&tx, "all");
+        assert!(matches!(&events[..], [Event::Brc20Mint(mint)] if mint.tick == "ordi" && mint.amt == "1000"));
+    }
+
+    #[test]
+    fn unclaimed_inscriptions_fall_back_to_ord() {
+        let tx = transaction(&[inscription("image/png", "png")]);
+        assert_eq!(protocols(&Registry::default().decode(&tx, "all")), ["ord"]);
+    }
+
+    #[test]
+    fn single_protocol_ignores_claims_of_unselected_decoders() {
+        let tx = transaction(&[inscription("text/plain;charset=utf-8", BRC20_MINT)]);
+        let registry = Registry::default();
+        assert_eq!(protocols(&registry.decode(&tx, "ord")), ["ord"]);
+        assert!(registry.decode(&tx, "ord-sns").is_empty());
+    }
+
+    #[test]
+    fn registered_decoder_runs_by_priority() {
+        let tx = transaction(&[inscription("text/plain;charset=utf-8", BRC20_MINT)]);
+        let mut registry = Registry::default();
+        registry.register(Claim);
+        assert_eq!(registry.names()[0], "ord-claim");
+        assert_eq!(protocols(&registry.decode(&tx, "all")), ["ord-claim"]);
+    }
+
+    #[test]
+    fn register_replaces_decoder_of_the_same_name() {
+        let mut registry = Registry::default();
+        let count = registry.names().len();
+        registry.register(EnvelopeDecoder::new("ord-brc20", ORD_ENVELOPE, |_| Ok(serde_json::Value::Null)));
+        assert_eq!(registry.names().len(), count);
+
+        let tx = transaction(&[inscription("image/png", "png")]);
+        assert_eq!(protocols(&registry.decode(&tx, "all")), ["ord-brc20"]);
+    }
+
+    #[test]
+    fn decode_with_options() {
+        let tx = transaction(&[inscription("text/plain;charset=utf-8", BRC20_MINT)]);
+        assert_eq!(protocols(&decode(&tx, &DecodeOptions::default())), ["ord-brc20"]);
+        assert_eq!(protocols(&decode(&tx, &DecodeOptions::new("ORD"))), ["ord"]);
+        assert!(decode(&tx, &DecodeOptions::new("unknown")).is_empty());
+    }
+
+    #[test]
+    fn decode_block_reports_block_context() {
+        let block = Block {
+            header: Header {
+                version: Version::ONE,
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 1712693506,
+                bits: CompactTarget::from_consensus(0),
+                nonce: 0,
+            },
+            txdata: vec![
+                transaction(&[]),
+                transaction(&[inscription("text/plain;charset=utf-8", BRC20_MINT)]),
+            ],
+        };
+        let events = decode_block(&block, 838501, &DecodeOptions::default());
+        assert_eq!(
+            events,
+            [DecodedEvent {
+                height: Some(838501),
+                blocktime: Some(1712693506),
+                txhash: block.txdata[1].txid().to_string(),
+                txindex: Some(1),
+                input: 0,
+                offset: 0,
+                ordinal: 0,
+                event: Event::from_parts("ord-brc20", serde_json::from_str(BRC20_MINT).unwrap()),
+            }],
+        );
+    }
+
+    #[test]
+    fn events_are_keyed_by_their_location() {
+        let tx = transaction(&[
+            inscription("text/plain;charset=utf-8", BRC20_MINT),
+            inscription("image/png", "png"),
+            inscription("text/plain;charset=utf-8", BRC20_MINT),
+        ]);
+        let events = Registry::default().decode_events(&tx, "all");
+        let keys: Vec<(u32, u32, &str, u32)> = events
+            .iter()
+            .map(|event| (event.input, event.offset, event.event.protocol(), event.ordinal))
+            .collect();
+        assert_eq!(keys, [(0, 0, "ord-brc20", 0), (2, 0, "ord-brc20", 0)]);
+        assert_ne!(events[0].key(), events[1].key());
+        assert_eq!(Registry::default().decode_events(&tx, "all"), events);
+
+        let mut registry = Registry::empty();
+        registry.register(TransactionDecoder::new("first", |_| Ok(serde_json::Value::Null)));
+        registry.register(TransactionDecoder::new("second", |_| Ok(serde_json::Value::Null)));
+        let events = registry.decode_events(&tx, "all");
+        assert_eq!((events[1].input, events[1].offset, events[1].ordinal), (0, 0, 0));
+        assert_ne!(events[0].key(), events[1].key());
+    }
+}
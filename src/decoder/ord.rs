@@ -0,0 +1,180 @@
+use {
+    super::{DecodeError, Event, Location, ProtocolDecoder, ORD_ENVELOPE},
+    crate::ord::{Inscription, InscriptionId, ParsedEnvelope},
+    anyhow::{Error, Result},
+    bitcoin::Transaction,
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    std::str,
+};
+
+#[derive(Serialize, Eq, PartialEq, Deserialize, Debug, Clone)]
+pub struct CompactOutput {
+    pub inscriptions: Vec<CompactInscription>,
+}
+
+#[derive(Serialize, Eq, PartialEq, Deserialize, Debug, Clone)]
+pub struct CompactInscription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate_field: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub incomplete_field: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metaprotocol: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<InscriptionId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unrecognized_even_field: bool,
+}
+
+impl TryFrom<Inscription> for CompactInscription {
+    type Error = Error;
+
+    fn try_from(inscription: Inscription) -> Result<Self> {
+        Ok(Self {
+            content_encoding: inscription
+                .content_encoding()
+                .map(|header_value| header_value.to_str().map(str::to_string))
+                .transpose()?,
+            content_type: inscription.content_type().map(str::to_string),
+            metaprotocol: inscription.metaprotocol().map(str::to_string),
+            parent: inscription.parent(),
+            pointer: inscription.pointer(),
+            body: inscription.body.map(hex::encode),
+            duplicate_field: inscription.duplicate_field,
+            incomplete_field: inscription.incomplete_field,
+            metadata: inscription.metadata.map(hex::encode),
+            unrecognized_even_field: inscription.unrecognized_even_field,
+        })
+    }
+}
+
+/// every inscription of a transaction as one `ord` event. It runs last, so
+/// transactions matched by an `ord-*` protocol are not reported again.
+pub struct Ord;
+
+impl ProtocolDecoder for Ord {
+    fn name(&self) -> &str {
+        "ord"
+    }
+
+    fn envelope(&self) -> Option<&[u8]> {
+        Some(ORD_ENVELOPE)
+    }
+
+    fn priority(&self) -> i32 {
+        -1
+    }
+
+    fn decode(&self, _tx: &Transaction, envelopes: &[ParsedEnvelope]) -> Vec<(Location, Event)> {
+        match decode_ord(envelopes) {
+            // the event covers every envelope, it is found at the first one
+            Ok(output) => vec![(Location::from(&envelopes[0]), Event::Inscription(output))],
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+fn decode_ord(envelopes: &[ParsedEnvelope]) -> Result<CompactOutput> {
+    let result = CompactOutput {
+        inscriptions: envelopes
+            .iter()
+            .map(|inscription| inscription.payload.clone().try_into())
+            .collect::<Result<Vec<CompactInscription>>>()?,
+    };
+    if result.inscriptions.is_empty() {
+        return Err(DecodeError::ContentTypeNotValid.into());
+    }
+    Ok(result)
+}
+
+fn parse_json(json_str: &str) -> Option<Value> {
+    serde_json::from_str(json_str).ok()
+}
+
+fn is_text_content_type(content_type: &str) -> bool {
+    content_type == "text/plain"
+        || content_type == "text/plain;charset=utf-8"
+        || content_type == "text/plain;charset=UTF-8"
+        || content_type == "application/json"
+        || content_type.starts_with("text/plain;")
+}
+
+/// the json body of a text inscription whose `p` field is one of `protocols`
+fn decode_json_protocol(inscription: &Inscription, protocols: &[&str]) -> Result<Value> {
+    let content_type = inscription.content_type().ok_or(DecodeError::ContentTypeNull)?;
+    if !is_text_content_type(content_type) {
+        return Err(DecodeError::ContentTypeNotValid.into());
+    }
+
+    let content_body = str::from_utf8(inscription.body().ok_or(DecodeError::ContentBodyNull)?)?;
+    let value = parse_json(content_body).ok_or(DecodeError::ContentBodyNotJson)?;
+    let protocol = value.get("p").ok_or(DecodeError::ContentBodyNotJson)?;
+    if protocols.iter().any(|name| protocol == name) {
+        Ok(value)
+    } else {
+        Err(DecodeError::ContentTypeNotValid.into())
+    }
+}
+
+pub(crate) fn decode_ord_brc20(inscription: Inscription) -> Result<Value> {
+    decode_json_protocol(&inscription, &["brc-20"])
+}
+
+pub(crate) fn decode_ord_brc100(inscription: Inscription) -> Result<Value> {
+    decode_json_protocol(&inscription, &["BRC-100", "BRC-101", "BRC-102"])
+}
+
+pub(crate) fn decode_ord_brc420(inscription: Inscription) -> Result<Value> {
+    let content_type = inscription.content_type().ok_or(DecodeError::ContentTypeNull)?;
+    if !is_text_content_type(content_type) && content_type != "text/html;charset=utf-8" {
+        return Err(DecodeError::ContentTypeNotValid.into());
+    }
+
+    let content_body = str::from_utf8(inscription.body().ok_or(DecodeError::ContentBodyNull)?)?;
+    if content_body.starts_with("/content/") {
+        return Ok(serde_json::json!({"mint":content_body.to_string()}));
+    }
+    // deploy new collection
+    let value = parse_json(content_body).ok_or(DecodeError::ContentBodyNotJson)?;
+    let protocol = value.get("p").ok_or(DecodeError::ContentBodyNotJson)?;
+    if protocol == "brc-420" {
+        Ok(value)
+    } else {
+        Err(DecodeError::ContentTypeNotValid.into())
+    }
+}
+
+pub(crate) fn decode_ord_sns(inscription: Inscription) -> Result<Value> {
+    decode_json_protocol(&inscription, &["sns"])
+}
+
+// https://github.com/BennyTheDev/tap-protocol-specs
+pub(crate) fn decode_ord_tap(inscription: Inscription) -> Result<Value> {
+    decode_json_protocol(&inscription, &["tap"])
+}
+
+//TODO nft transfer is bind with ordinals number, not the nft self?
+pub(crate) fn decode_ord_bitmap(inscription: Inscription) -> Result<Value> {
+    let content_type = inscription.content_type().ok_or(DecodeError::ContentTypeNull)?;
+    if !is_text_content_type(content_type) {
+        return Err(DecodeError::ContentTypeNotValid.into());
+    }
+
+    let content_body = str::from_utf8(inscription.body().ok_or(DecodeError::ContentBodyNull)?)?;
+    if content_body.ends_with(".bitmap") {
+        Ok(serde_json::json!({"mint":content_body.to_string()}))
+    } else {
+        Err(DecodeError::ContentTypeNotValid.into())
+    }
+}
@@ -0,0 +1,18 @@
+use {
+    super::DecodeError,
+    crate::runealpha::Runestone as Runealpha,
+    anyhow::Result,
+    bitcoin::Transaction,
+    ordinals::Runestone,
+    serde_json::Value,
+};
+
+pub(crate) fn decode_rune_stone(rawtx: &Transaction) -> Result<Value> {
+    let runestone = Runestone::decipher(rawtx).ok_or(DecodeError::ContentBodyNull)?;
+    Ok(serde_json::to_value(runestone)?)
+}
+
+pub(crate) fn decode_rune_alpha(rawtx: &Transaction) -> Result<Value> {
+    let rune = Runealpha::from_transaction(rawtx).ok_or(DecodeError::ContentTypeNull)?;
+    Ok(serde_json::to_value(&rune)?)
+}
@@ -0,0 +1,63 @@
+use {
+    super::DecodeError,
+    anyhow::Result,
+    bitcoin::{
+        blockdata::{opcodes::all::OP_CHECKMULTISIG, script::Instruction},
+        Transaction,
+    },
+    crypto::{rc4::Rc4, symmetriccipher::SynchronousStreamCipher},
+    serde_json::Value,
+    std::str,
+};
+
+// 1. find all the multisig ouput in ouputs
+// 2. take the first two ouput
+pub fn decode_stamp_src20(rawtx: &Transaction) -> Result<Value> {
+    let tx_input0 = rawtx.input.first().ok_or(DecodeError::ContentBodyNull)?.previous_output.txid.to_string();
+    let signing_key = hex::decode(tx_input0)?;
+
+    let mut payload = Vec::new();
+
+    let mut pubkeys_idx: u8 = 1;
+    for output in rawtx.output.iter() {
+        // check if output is multisig
+        if pubkeys_idx > 2 { continue; }
+        let encoded_script = &output.script_pubkey;
+
+        let is_multisig = encoded_script.instructions().any(|instruction| {
+            matches!(&instruction, Ok(Instruction::Op(OP_CHECKMULTISIG)))
+        });
+
+        if !is_multisig { continue; }
+
+        let mut instructions = encoded_script.instructions().peekable();
+        let mut insctrction_idx: u8 = 1;
+        while let Some(insctrction) = instructions.next().transpose()? {
+            if insctrction_idx > 2 { break; }
+            // TODO: check whether opcode is MULTISIG, // Take the first two pubkeys from all present multisig scripts
+            if let Some(pushed_bytes) = insctrction.push_bytes() {
+                let value = pushed_bytes.as_bytes().to_vec();
+                payload.extend_from_slice(&value[1..value.len() - 1]);
+                insctrction_idx += 1;
+            }
+        }
+        pubkeys_idx += 1;
+    }
+    // 2 bytes length prefix and the `stamp:` prefix
+    if payload.len() < 2 + 6 {
+        return Err(DecodeError::ContentBodyNull.into());
+    }
+
+    let mut rc4 = Rc4::new(&signing_key);
+    let mut decode_result: Vec<u8> = vec![0; payload.len()];
+    rc4.process(&payload, &mut decode_result);
+
+    // strip the 2 bytes length prefix and decode hex to string
+    let protocol = str::from_utf8(&decode_result[2..2 + 6])?;
+    if protocol == "stamp:" {
+        let result = str::from_utf8(&decode_result[2 + 6..])?;
+        Ok(serde_json::from_str(result.trim_end_matches('\0'))?)
+    } else {
+        Err(DecodeError::ContentBodyNull.into())
+    }
+}
@@ -0,0 +1,368 @@
+use {
+    crate::{
+        atomicals::{Arc20Dft, Arc20Dmt, Arc20Ft, Arc20Split, AtomRealm},
+        brc20::{self as brc20_ledger, Brc20BalanceChange, Brc20Operation},
+        decoder::CompactOutput,
+        ord::brcX::{
+            bitmap::Bitmap,
+            brc20::{self, Brc20Deploy, Brc20Mint, Brc20Transfer},
+            brc420::Brc420Mint,
+            sns::{self, SnsNamespace, SnsRegister},
+            tap::{self, TapDeploy, TapMint, TapTransfer},
+        },
+        runes::{self as rune_state, RuneBalanceChange, RuneOperation},
+        stamps::{self, Src20Deploy, Src20Mint, Src20Transfer},
+        transfer::{InscriptionTransfer, TRANSFER_PROTOCOL},
+    },
+    ordinals::{Artifact, Cenotaph, Runestone},
+    serde::{de::DeserializeOwned, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer},
+    serde_json::Value,
+};
+
+/// a decoded protocol event of one transaction, without block context.
+///
+/// It serializes to `{"protocol": ..., "payload": ...}` with the payload as
+/// inscribed, and [`Event::from_parts`] turns that json back into the typed
+/// variant, so existing jsonl files and database rows stay readable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// `ord`, every inscription of the transaction
+    Inscription(CompactOutput),
+    Brc20Deploy(Brc20Deploy),
+    Brc20Mint(Brc20Mint),
+    Brc20Transfer(Brc20Transfer),
+    /// `brc20-ledger`, an operation with the verdict of the brc-20 ledger
+    Brc20Operation(Brc20Operation),
+    /// `brc20-balance`, a balance changed by the brc-20 ledger
+    Brc20BalanceChange(Brc20BalanceChange),
+    /// `ord-transfer`, an inscription moved by the inscription tracker
+    InscriptionTransfer(InscriptionTransfer),
+    Brc420Mint(Brc420Mint),
+    Bitmap(Bitmap),
+    SnsRegister(SnsRegister),
+    SnsNamespace(SnsNamespace),
+    TapDeploy(TapDeploy),
+    TapMint(TapMint),
+    TapTransfer(TapTransfer),
+    Arc20Dft(Arc20Dft),
+    Arc20Dmt(Arc20Dmt),
+    Arc20Ft(Arc20Ft),
+    Arc20Split(Arc20Split),
+    AtomRealm(AtomRealm),
+    Src20Deploy(Src20Deploy),
+    Src20Mint(Src20Mint),
+    Src20Transfer(Src20Transfer),
+    /// a runestone with an etching, which may mint and transfer too
+    RuneEtching(Runestone),
+    /// a runestone minting without etching
+    RuneMint(Runestone),
+    /// a runestone only transferring with edicts
+    RuneEdicts(Runestone),
+    RuneCenotaph(Cenotaph),
+    /// `rune-ledger`, an etching, mint or burn applied by the rune indexer
+    RuneOperation(RuneOperation),
+    /// `rune-balance`, the runes of an output credited or debited by the rune indexer
+    RuneBalanceChange(RuneBalanceChange),
+    /// protocols and operations without a typed model (brc-100, brc-420 deploys,
+    /// atomicals nft, rune-alpha, in-house decoders...) and payloads that don't
+    /// fit the typed model of their operation
+    Other { protocol: String, payload: Value },
+}
+
+impl Event {
+    /// the event of `payload` decoded by the `protocol` decoder
+    pub fn from_parts(protocol: &str, payload: Value) -> Self {
+        Self::typed(protocol, &payload).unwrap_or_else(|| Self::Other {
+            protocol: protocol.to_string(),
+            payload,
+        })
+    }
+
+    /// the name of the decoder emitting this event, e.g. `ord-brc20`
+    pub fn protocol(&self) -> &str {
+        match self {
+            Self::Inscription(_) => "ord",
+            Self::Brc20Deploy(_) | Self::Brc20Mint(_) | Self::Brc20Transfer(_) => "ord-brc20",
+            Self::Brc20Operation(_) => brc20_ledger::LEDGER_PROTOCOL,
+            Self::Brc20BalanceChange(_) => brc20_ledger::BALANCE_PROTOCOL,
+            Self::InscriptionTransfer(_) => TRANSFER_PROTOCOL,
+            Self::Brc420Mint(_) => "ord-brc420",
+            Self::Bitmap(_) => "ord-bitmap",
+            Self::SnsRegister(_) | Self::SnsNamespace(_) => "ord-sns",
+            Self::TapDeploy(_) | Self::TapMint(_) | Self::TapTransfer(_) => "ord-tap",
+            Self::Arc20Dft(_) | Self::Arc20Dmt(_) | Self::Arc20Ft(_) | Self::Arc20Split(_) => "atom-arc20",
+            Self::AtomRealm(_) => "atom-relam",
+            Self::Src20Deploy(_) | Self::Src20Mint(_) | Self::Src20Transfer(_) => "stamp-src20",
+            Self::RuneEtching(_) | Self::RuneMint(_) | Self::RuneEdicts(_) | Self::RuneCenotaph(_) => "rune-stone",
+            Self::RuneOperation(_) => rune_state::LEDGER_PROTOCOL,
+            Self::RuneBalanceChange(_) => rune_state::BALANCE_PROTOCOL,
+            Self::Other { protocol, .. } => protocol,
+        }
+    }
+
+    /// the payload as json, the way it was inscribed
+    pub fn payload(&self) -> serde_json::Result<Value> {
+        serde_json::to_value(Payload(self))
+    }
+
+    fn typed(protocol: &str, payload: &Value) -> Option<Self> {
+        let event = match protocol {
+            "ord" => Self::Inscription(parse(payload.clone())?),
+            "ord-brc20" => match operation(payload, brc20::PROTOCOL)? {
+                ("deploy", body) => Self::Brc20Deploy(parse(body)?),
+                ("mint", body) => Self::Brc20Mint(parse(body)?),
+                ("transfer", body) => Self::Brc20Transfer(parse(body)?),
+                _ => return None,
+            },
+            brc20_ledger::LEDGER_PROTOCOL => Self::Brc20Operation(parse(payload.clone())?),
+            brc20_ledger::BALANCE_PROTOCOL => Self::Brc20BalanceChange(parse(payload.clone())?),
+            rune_state::LEDGER_PROTOCOL => Self::RuneOperation(parse(payload.clone())?),
+            rune_state::BALANCE_PROTOCOL => Self::RuneBalanceChange(parse(payload.clone())?),
+            TRANSFER_PROTOCOL => Self::InscriptionTransfer(parse(payload.clone())?),
+            "ord-brc420" if payload.get("p").is_none() => Self::Brc420Mint(parse(payload.clone())?),
+            "ord-bitmap" => Self::Bitmap(parse(payload.clone())?),
+            "ord-sns" => match operation(payload, sns::PROTOCOL)? {
+                ("reg", body) => Self::SnsRegister(parse(body)?),
+                ("ns", body) => Self::SnsNamespace(parse(body)?),
+                _ => return None,
+            },
+            "ord-tap" => match operation(payload, tap::PROTOCOL)? {
+                ("token-deploy", body) => Self::TapDeploy(parse(body)?),
+                ("token-mint", body) => Self::TapMint(parse(body)?),
+                ("token-transfer", body) => Self::TapTransfer(parse(body)?),
+                _ => return None,
+            },
+            "atom-arc20" => {
+                // the operation is the envelope's content type, which isn't part of the
+                // payload, so it is told apart by the args each operation requires
+                let payload = payload.clone();
+                match payload.get("args") {
+                    Some(args) if args.get("mint_ticker").is_some() => Self::Arc20Dmt(parse(payload)?),
+                    Some(args) if args.get("mint_amount").is_some() => Self::Arc20Dft(parse(payload)?),
+                    Some(args) if args.get("request_ticker").is_some() => Self::Arc20Ft(parse(payload)?),
+                    Some(_) => return None,
+                    None => Self::Arc20Split(parse(payload)?),
+                }
+            }
+            "atom-relam" => Self::AtomRealm(parse(payload.clone())?),
+            "stamp-src20" => match operation(payload, stamps::PROTOCOL)? {
+                ("deploy", body) => Self::Src20Deploy(parse(body)?),
+                ("mint", body) => Self::Src20Mint(parse(body)?),
+                ("transfer", body) => Self::Src20Transfer(parse(body)?),
+                _ => return None,
+            },
+            "rune-stone" => match parse(payload.clone())? {
+                Artifact::Runestone(runestone) if runestone.etching.is_some() => Self::RuneEtching(runestone),
+                Artifact::Runestone(runestone) if runestone.mint.is_some() => Self::RuneMint(runestone),
+                Artifact::Runestone(runestone) => Self::RuneEdicts(runestone),
+                Artifact::Cenotaph(cenotaph) => Self::RuneCenotaph(cenotaph),
+            },
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+fn parse<T: DeserializeOwned>(value: Value) -> Option<T> {
+    serde_json::from_value(value).ok()
+}
+
+/// the `op` of a `{"p": p, "op": ...}` payload and its other fields
+fn operation<'a>(payload: &'a Value, p: &str) -> Option<(&'a str, Value)> {
+    let object = payload.as_object()?;
+    if object.get("p")?.as_str()? != p {
+        return None;
+    }
+    let op = object.get("op")?.as_str()?;
+    let mut body = object.clone();
+    body.remove("p");
+    body.remove("op");
+    Some((op, Value::Object(body)))
+}
+
+/// a typed operation serialized with its `p` and `op` fields
+#[derive(Serialize)]
+struct Operation<'a, T> {
+    p: &'a str,
+    op: &'a str,
+    #[serde(flatten)]
+    body: &'a T,
+}
+
+#[derive(Serialize)]
+enum ArtifactRef<'a> {
+    Runestone(&'a Runestone),
+    Cenotaph(&'a Cenotaph),
+}
+
+struct Payload<'a>(&'a Event);
+
+impl Serialize for Payload<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        fn op<'a, T>(p: &'a str, op: &'a str, body: &'a T) -> Operation<'a, T> {
+            Operation { p, op, body }
+        }
+
+        match self.0 {
+            Event::Inscription(output) => output.serialize(serializer),
+            Event::Brc20Deploy(body) => op(brc20::PROTOCOL, "deploy", body).serialize(serializer),
+            Event::Brc20Mint(body) => op(brc20::PROTOCOL, "mint", body).serialize(serializer),
+            Event::Brc20Transfer(body) => op(brc20::PROTOCOL, "transfer", body).serialize(serializer),
+            Event::Brc20Operation(operation) => operation.serialize(serializer),
+            Event::Brc20BalanceChange(change) => change.serialize(serializer),
+            Event::InscriptionTransfer(transfer) => transfer.serialize(serializer),
+            Event::Brc420Mint(mint) => mint.serialize(serializer),
+            Event::Bitmap(bitmap) => bitmap.serialize(serializer),
+            Event::SnsRegister(body) => op(sns::PROTOCOL, "reg", body).serialize(serializer),
+            Event::SnsNamespace(body) => op(sns::PROTOCOL, "ns", body).serialize(serializer),
+            Event::TapDeploy(body) => op(tap::PROTOCOL, "token-deploy", body).serialize(serializer),
+            Event::TapMint(body) => op(tap::PROTOCOL, "token-mint", body).serialize(serializer),
+            Event::TapTransfer(body) => op(tap::PROTOCOL, "token-transfer", body).serialize(serializer),
+            Event::Arc20Dft(operation) => operation.serialize(serializer),
+            Event::Arc20Dmt(operation) => operation.serialize(serializer),
+            Event::Arc20Ft(operation) => operation.serialize(serializer),
+            Event::Arc20Split(split) => split.serialize(serializer),
+            Event::AtomRealm(operation) => operation.serialize(serializer),
+            Event::Src20Deploy(body) => op(stamps::PROTOCOL, "deploy", body).serialize(serializer),
+            Event::Src20Mint(body) => op(stamps::PROTOCOL, "mint", body).serialize(serializer),
+            Event::Src20Transfer(body) => op(stamps::PROTOCOL, "transfer", body).serialize(serializer),
+            Event::RuneEtching(runestone) | Event::RuneMint(runestone) | Event::RuneEdicts(runestone) => {
+                ArtifactRef::Runestone(runestone).serialize(serializer)
+            }
+            Event::RuneCenotaph(cenotaph) => ArtifactRef::Cenotaph(cenotaph).serialize(serializer),
+            Event::RuneOperation(operation) => operation.serialize(serializer),
+            Event::RuneBalanceChange(change) => change.serialize(serializer),
+            Event::Other { payload, .. } => payload.serialize(serializer),
+        }
+    }
+}
+
+impl Serialize for Event {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Event", 2)?;
+        state.serialize_field("protocol", self.protocol())?;
+        state.serialize_field("payload", &Payload(self))?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct RawEvent {
+            protocol: String,
+            payload: Value,
+        }
+
+        let raw = RawEvent::deserialize(deserializer)?;
+        Ok(Self::from_parts(&raw.protocol, raw.payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::decoder::DecodedEvent, ordinals::RuneId, serde_json::json};
+
+    #[track_caller]
+    fn round_trip(protocol: &str, payload: Value) -> Event {
+        let event = Event::from_parts(protocol, payload.clone());
+        assert_eq!(event.protocol(), protocol);
+        assert_eq!(event.payload().unwrap(), payload);
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"protocol": protocol, "payload": payload}),
+        );
+        assert_eq!(serde_json::from_value::<Event>(serde_json::to_value(&event).unwrap()).unwrap(), event);
+        event
+    }
+
+    #[test]
+    fn brc20() {
+        let deploy = round_trip("ord-brc20", json!({"p":"brc-20","op":"deploy","tick":"ordi","max":"21000000","lim":"1000"}));
+        assert!(matches!(deploy, Event::Brc20Deploy(Brc20Deploy { lim: Some(Some(_)), dec: None, .. })));
+        let deploy = round_trip("ord-brc20", json!({"p":"brc-20","op":"deploy","tick":"ordi","max":"21000000","lim":null}));
+        assert!(matches!(deploy, Event::Brc20Deploy(ref deploy) if deploy.lim.is_some() && deploy.lim().is_none()));
+        let mint = round_trip("ord-brc20", json!({"p":"brc-20","op":"mint","tick":"ordi","amt":"1000"}));
+        assert!(matches!(mint, Event::Brc20Mint(Brc20Mint { ref tick, .. }) if tick == "ordi"));
+        let transfer = round_trip("ord-brc20", json!({"p":"brc-20","op":"transfer","tick":"ordi","amt":"1","to":"x"}));
+        assert!(matches!(transfer, Event::Brc20Transfer(Brc20Transfer { ref extra, .. }) if extra["to"] == "x"));
+    }
+
+    #[test]
+    fn payloads_outside_the_typed_model_are_kept_as_other() {
+        for (protocol, payload) in [
+            ("ord-brc20", json!({"p":"brc-20","op":"mint","tick":"ordi","amt":1000})),
+            ("ord-brc20", json!({"p":"brc-20","op":"burn","tick":"ordi","amt":"1"})),
+            ("ord-brc100", json!({"p":"BRC-100","op":"deploy","tick":"bos"})),
+            ("ord-brc420", json!({"p":"brc-420","op":"deploy","id":"abc"})),
+            ("my-protocol", json!({"anything": true})),
+        ] {
+            assert!(matches!(round_trip(protocol, payload), Event::Other { .. }));
+        }
+    }
+
+    #[test]
+    fn ord_metaprotocols() {
+        assert!(matches!(round_trip("ord-bitmap", json!({"mint":"838000.bitmap"})), Event::Bitmap(_)));
+        assert!(matches!(round_trip("ord-brc420", json!({"mint":"/content/abci0"})), Event::Brc420Mint(_)));
+        assert!(matches!(round_trip("ord-bitmap", json!({"mint":"838000.bitmap","note":null})), Event::Bitmap(_)));
+        assert!(matches!(round_trip("ord-brc420", json!({"mint":"/content/abci0","by":"x"})), Event::Brc420Mint(_)));
+        assert!(matches!(round_trip("ord-sns", json!({"p":"sns","op":"reg","name":"satoshi.sats"})), Event::SnsRegister(_)));
+        assert!(matches!(round_trip("ord-sns", json!({"p":"sns","op":"ns","ns":"sats"})), Event::SnsNamespace(_)));
+        assert!(matches!(round_trip("ord-tap", json!({"p":"tap","op":"token-mint","tick":"-tap","amt":"1"})), Event::TapMint(_)));
+        assert!(matches!(
+            round_trip("ord", json!({"inscriptions":[{"body":"6f7264","content_type":"text/plain"}]})),
+            Event::Inscription(_),
+        ));
+    }
+
+    #[test]
+    fn arc20_operations_are_told_apart_by_their_args() {
+        let dft = json!({"args":{"request_ticker":"atom","mint_amount":1000,"mint_height":0,"max_mints":21000,"bitworkc":"aabbcc"},"meta":{"name":"atom"}});
+        assert!(matches!(round_trip("atom-arc20", dft), Event::Arc20Dft(_)));
+        let dmt = json!({"args":{"mint_ticker":"atom","nonce":1,"time":2}});
+        assert!(matches!(round_trip("atom-arc20", dmt), Event::Arc20Dmt(_)));
+        let ft = json!({"args":{"request_ticker":"direct","time":2}});
+        assert!(matches!(round_trip("atom-arc20", ft), Event::Arc20Ft(_)));
+        let split = json!({"8b1ca4ae08d0c1cb80b5e4a6dfdee6ff2ca3d4a8e4d3b1e2d7c0d8e0f1a2b3c4i0":1000});
+        assert!(matches!(round_trip("atom-arc20", split), Event::Arc20Split(_)));
+        let realm = json!({"args":{"request_realm":"satoshi","nonce":1}});
+        assert!(matches!(round_trip("atom-relam", realm), Event::AtomRealm(_)));
+    }
+
+    #[test]
+    fn src20() {
+        let deploy = json!({"p":"src-20","op":"deploy","tick":"kevin","max":"69000000","lim":"100000"});
+        assert!(matches!(round_trip("stamp-src20", deploy), Event::Src20Deploy(_)));
+        let transfer = json!({"p":"src-20","op":"transfer","tick":"kevin","amt":"1"});
+        assert!(matches!(round_trip("stamp-src20", transfer), Event::Src20Transfer(_)));
+    }
+
+    #[test]
+    fn runestones_are_classified_by_their_primary_action() {
+        let id = RuneId::new(840000, 3).unwrap();
+        let mint = Event::RuneMint(Runestone { mint: Some(id), ..Default::default() });
+        assert_eq!(round_trip("rune-stone", mint.payload().unwrap()), mint);
+        let edicts = Event::RuneEdicts(Runestone::default());
+        assert_eq!(round_trip("rune-stone", edicts.payload().unwrap()), edicts);
+        let cenotaph = Event::RuneCenotaph(Cenotaph { flaws: 1, ..Default::default() });
+        assert_eq!(round_trip("rune-stone", cenotaph.payload().unwrap()), cenotaph);
+        assert_eq!(mint.payload().unwrap()["Runestone"]["mint"], "840000:3");
+    }
+
+    #[test]
+    fn decoded_event_keeps_the_jsonl_shape() {
+        let line = r#"{"height":838501,"blocktime":1712693506,"txhash":"ade3","txindex":855,"input":1,"offset":0,"ordinal":0,"protocol":"ord-brc20","payload":{"amt":"1000","op":"mint","p":"brc-20","tick":"ombi"}}"#;
+        let event: DecodedEvent = serde_json::from_str(line).unwrap();
+        assert!(matches!(event.event, Event::Brc20Mint(_)));
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::from_str::<Value>(line).unwrap(),
+        );
+
+        // lines written before events had a location
+        let line = r#"{"height":838501,"blocktime":1712693506,"txhash":"ade3","txindex":855,"protocol":"ord-brc20","payload":{"amt":"1000","op":"mint","p":"brc-20","tick":"ombi"}}"#;
+        let event: DecodedEvent = serde_json::from_str(line).unwrap();
+        assert_eq!((event.input, event.offset, event.ordinal), (0, 0, 0));
+    }
+}
@@ -1,6 +1,32 @@
 pub mod atomicals; 
+pub mod blk;
+pub mod brc20;
+pub mod cursor;
+pub mod db;
+pub mod decoder;
+pub mod event;
+pub mod mempool;
+pub mod notify;
+pub mod pipeline;
+pub mod reorg;
+pub mod sat_flow;
+pub mod replay;
 pub mod scan;
+pub mod sink;
+pub mod transfer;
+pub mod source;
 pub mod cli;
 pub mod ord;
 pub mod runealpha;
-pub mod deserialize_from_str;
\ No newline at end of file
+pub mod runes;
+pub mod stamps;
+pub mod deserialize_from_str;
+
+// helpers shared by the tests of the indexers and of the sinks
+#[cfg(test)]
+mod test_util;
+
+pub use {
+    decoder::{decode, decode_block, DecodeOptions, DecodedEvent, EventKey, Location},
+    event::Event,
+};
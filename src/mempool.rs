@@ -0,0 +1,320 @@
+use {
+    crate::{decoder::{DecodedEvent, Registry}, source::BlockSource},
+    anyhow::{Context, Result},
+    bitcoin::{BlockHash, OutPoint, Txid},
+    chrono::Local,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{HashMap, HashSet},
+        fs::OpenOptions,
+        io::Write,
+        thread,
+        time::Duration,
+    },
+};
+
+/// one line of the output of `inscan mempool`: an event of a transaction entering
+/// the mempool, or what became of such a transaction once it left the mempool
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum MempoolUpdate {
+    /// an event of an unconfirmed transaction, `confirmed` is always false
+    Pending {
+        confirmed: bool,
+        #[serde(flatten)]
+        event: Box<DecodedEvent>,
+    },
+    /// the transaction was mined
+    Confirmed { txhash: String, height: u64, block_hash: String },
+    /// a transaction spending the same outputs took its place, in the mempool or in a block
+    Replaced { txhash: String, replaced_by: String },
+    /// the transaction left the mempool otherwise: expired, evicted by a full mempool...
+    Evicted { txhash: String },
+}
+
+impl MempoolUpdate {
+    pub fn txhash(&self) -> &str {
+        match self {
+            Self::Pending { event, .. } => &event.txhash,
+            Self::Confirmed { txhash, .. } | Self::Replaced { txhash, .. } | Self::Evicted { txhash } => txhash,
+        }
+    }
+}
+
+/// the transactions of the blocks read by a poll, and the outputs they spend
+#[derive(Default)]
+struct Mined {
+    txs: HashMap<Txid, (u64, BlockHash)>,
+    spends: HashMap<OutPoint, Txid>,
+}
+
+/// follows the mempool from poll to poll. Only the transactions with events are
+/// kept, with the outputs they spend, to tell a replacement from an eviction.
+#[derive(Default)]
+pub struct MempoolTracker {
+    /// the tip at the last poll, the blocks above it are read by the next one
+    tip: Option<u64>,
+    /// the transactions of the mempool at the last poll
+    known: HashSet<Txid>,
+    /// the transactions with events, with the outputs they spend
+    tracked: HashMap<Txid, Vec<OutPoint>>,
+    /// the blocks read by the last poll, a transaction mined after the mempool was
+    /// listed leaves it in the next poll
+    mined: Mined,
+}
+
+impl MempoolTracker {
+    /// the transactions with events still in the mempool
+    pub fn pending(&self) -> usize {
+        self.tracked.len()
+    }
+
+    /// list the mempool and read the new blocks, and return the events of the new
+    /// transactions, after the updates of the transactions which left the mempool.
+    /// The transactions already in the mempool at the first poll are new too.
+    pub fn poll(&mut self, source: &dyn BlockSource, registry: &Registry, protocol: &str) -> Result<Vec<MempoolUpdate>> {
+        let txids = source.mempool_txids()?;
+
+        let mut mined = Mined::default();
+        let tip = source.tip_height()?;
+        for height in self.tip.map_or(tip + 1, |last| last + 1)..=tip {
+            let block = source.block_at(height)?;
+            let block_hash = block.block_hash();
+            for tx in &block.txdata {
+                let txid = tx.txid();
+                mined.txs.insert(txid, (height, block_hash));
+                for input in &tx.input {
+                    mined.spends.insert(input.previous_output, txid);
+                }
+            }
+        }
+        self.tip = Some(tip);
+
+        let mut spends = HashMap::new();
+        let mut pending = Vec::new();
+        for txid in txids.iter().filter(|txid| !self.known.contains(*txid)) {
+            // the transaction may have left the mempool since it was listed
+            let Ok(tx) = source.raw_tx(txid) else {
+                continue;
+            };
+            for input in &tx.input {
+                spends.insert(input.previous_output, *txid);
+            }
+            let events = registry.decode_events(&tx, protocol);
+            if !events.is_empty() {
+                self.tracked.insert(*txid, tx.input.iter().map(|input| input.previous_output).collect());
+                pending.extend(events);
+            }
+        }
+        let mempool = txids.into_iter().collect::<HashSet<_>>();
+
+        let mut gone = self.tracked.keys().filter(|txid| !mempool.contains(*txid)).copied().collect::<Vec<_>>();
+        gone.sort();
+        let mut updates = Vec::new();
+        for txid in gone {
+            let inputs = self.tracked.remove(&txid).expect("gone transactions are tracked");
+            let txhash = txid.to_string();
+            let confirmed = mined.txs.get(&txid).or_else(|| self.mined.txs.get(&txid));
+            let replaced_by = inputs.iter().find_map(|outpoint| {
+                [&mined.spends, &self.mined.spends, &spends]
+                    .into_iter()
+                    .find_map(|spends| spends.get(outpoint))
+                    .filter(|spender| **spender != txid)
+            });
+            updates.push(match (confirmed, replaced_by) {
+                (Some((height, block_hash)), _) => MempoolUpdate::Confirmed {
+                    txhash,
+                    height: *height,
+                    block_hash: block_hash.to_string(),
+                },
+                (None, Some(replaced_by)) => MempoolUpdate::Replaced {
+                    txhash,
+                    replaced_by: replaced_by.to_string(),
+                },
+                (None, None) => MempoolUpdate::Evicted { txhash },
+            });
+        }
+        updates.extend(pending.into_iter().map(|event| MempoolUpdate::Pending {
+            confirmed: false,
+            event: Box::new(event),
+        }));
+
+        self.known = mempool;
+        self.mined = mined;
+        Ok(updates)
+    }
+}
+
+/// the `--out-file` outputs of `inscan mempool`, appended to, `-` for stdout
+pub fn open_outputs(paths: &[String]) -> Result<Vec<Box<dyn Write>>> {
+    paths
+        .iter()
+        .map(|path| -> Result<Box<dyn Write>> {
+            if path == "-" {
+                return Ok(Box::new(std::io::stdout()));
+            }
+            let file = OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("failed to open {path}"))?;
+            Ok(Box::new(file))
+        })
+        .collect()
+}
+
+/// poll the mempool every `interval` and write the updates to the outputs, one
+/// json line each. Events are not written to event outputs, whose rows would
+/// stay once their transaction is replaced or evicted.
+pub fn watch(source: &dyn BlockSource, registry: &Registry, protocol: &str, interval: Duration, outputs: &mut [Box<dyn Write>]) -> Result<()> {
+    let mut tracker = MempoolTracker::default();
+    loop {
+        let updates = tracker.poll(source, registry, protocol)?;
+        if !updates.is_empty() {
+            for output in outputs.iter_mut() {
+                for update in &updates {
+                    writeln!(output, "{}", serde_json::to_string(update)?)?;
+                }
+                output.flush()?;
+            }
+            eprintln!("{} | {} mempool updates, {} pending transactions with events", Local::now().format("%Y-%m-%d %H:%M:%S"), updates.len(), tracker.pending());
+        }
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::ord::Inscription,
+        anyhow::anyhow,
+        bitcoin::{
+            block::Header, blockdata::constants::genesis_block, locktime::absolute::LockTime, Block, Network, ScriptBuf, Sequence,
+            Transaction, TxIn,
+        },
+        std::sync::Mutex,
+    };
+
+    #[derive(Default)]
+    struct Chain {
+        blocks: Vec<Block>,
+        mempool: Vec<Transaction>,
+    }
+
+    #[derive(Default)]
+    struct FakeSource(Mutex<Chain>);
+
+    impl BlockSource for FakeSource {
+        fn tip_height(&self) -> Result<u64> {
+            Ok(self.0.lock().unwrap().blocks.len() as u64 - 1)
+        }
+
+        fn block_hash(&self, height: u64) -> Result<BlockHash> {
+            Ok(self.0.lock().unwrap().blocks[height as usize].block_hash())
+        }
+
+        fn block(&self, hash: &BlockHash) -> Result<Block> {
+            let chain = self.0.lock().unwrap();
+            chain.blocks.iter().find(|block| block.block_hash() == *hash).cloned().ok_or_else(|| anyhow!("no block {hash}"))
+        }
+
+        fn block_header(&self, hash: &BlockHash) -> Result<Header> {
+            Ok(self.block(hash)?.header)
+        }
+
+        fn raw_tx(&self, txid: &Txid) -> Result<Transaction> {
+            let chain = self.0.lock().unwrap();
+            chain.mempool.iter().find(|tx| tx.txid() == *txid).cloned().ok_or_else(|| anyhow!("no transaction {txid}"))
+        }
+
+        fn mempool_txids(&self) -> Result<Vec<Txid>> {
+            Ok(self.0.lock().unwrap().mempool.iter().map(Transaction::txid).collect())
+        }
+
+        fn tx_height(&self, _txid: &Txid) -> Result<Option<u64>> {
+            Ok(None)
+        }
+
+        fn block_fees(&self, _hash: &BlockHash) -> Result<Vec<u64>> {
+            unimplemented!()
+        }
+    }
+
+    /// a brc-20 mint spending `vout` of a fixed output, or a transaction without events
+    fn transaction(vout: u32, amount: Option<&str>) -> Transaction {
+        let witness = match amount {
+            Some(amount) => Inscription::new(
+                Some("text/plain;charset=utf-8".into()),
+                Some(format!(r#"{{"p":"brc-20","op":"mint","tick":"ordi","amt":"{amount}"}}"#).into_bytes()),
+            )
+            .to_witness(),
+            None => Default::default(),
+        };
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: Txid::from_raw_hash(bitcoin::hashes::Hash::all_zeros()), vout },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness,
+            }],
+            output: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn transactions_are_followed_until_they_leave_the_mempool() {
+        let source = FakeSource::default();
+        let (mint, other) = (transaction(0, Some("1000")), transaction(1, None));
+        // spending the same output, with another txid than the mint's
+        let mut replacement = transaction(0, Some("2000"));
+        replacement.lock_time = LockTime::from_consensus(1);
+        let evicted = transaction(2, Some("3000"));
+        source.0.lock().unwrap().blocks.push(genesis_block(Network::Regtest));
+        source.0.lock().unwrap().mempool = vec![mint.clone(), other];
+
+        let (mut tracker, registry) = (MempoolTracker::default(), Registry::default());
+        let updates = tracker.poll(&source, &registry, "all").unwrap();
+        assert_eq!(updates.len(), 1);
+        let MempoolUpdate::Pending { confirmed: false, event } = &updates[0] else {
+            panic!("unexpected update {:?}", updates[0]);
+        };
+        assert_eq!((event.txhash.as_str(), event.height), (mint.txid().to_string().as_str(), None));
+        let line = serde_json::to_string(&updates[0]).unwrap();
+        assert!(line.starts_with(r#"{"status":"pending","confirmed":false,"height":null"#), "{line}");
+        assert_eq!(serde_json::from_str::<MempoolUpdate>(&line).unwrap(), updates[0]);
+        assert_eq!(tracker.poll(&source, &registry, "all").unwrap(), []);
+
+        source.0.lock().unwrap().mempool = vec![replacement.clone(), evicted.clone()];
+        let updates = tracker.poll(&source, &registry, "all").unwrap();
+        assert_eq!(
+            updates[0],
+            MempoolUpdate::Replaced {
+                txhash: mint.txid().to_string(),
+                replaced_by: replacement.txid().to_string(),
+            }
+        );
+        assert_eq!(updates.iter().skip(1).map(MempoolUpdate::txhash).collect::<Vec<_>>(), [replacement.txid().to_string(), evicted.txid().to_string()]);
+
+        // the replacement is mined, the other transaction dropped
+        let mut block = genesis_block(Network::Regtest);
+        block.header.prev_blockhash = block.block_hash();
+        block.txdata.push(replacement.clone());
+        let block_hash = block.block_hash();
+        source.0.lock().unwrap().blocks.push(block);
+        source.0.lock().unwrap().mempool.clear();
+        let mut updates = tracker.poll(&source, &registry, "all").unwrap();
+        let mut expected = vec![
+            MempoolUpdate::Confirmed {
+                txhash: replacement.txid().to_string(),
+                height: 1,
+                block_hash: block_hash.to_string(),
+            },
+            MempoolUpdate::Evicted {
+                txhash: evicted.txid().to_string(),
+            },
+        ];
+        updates.sort_by(|a, b| a.txhash().cmp(b.txhash()));
+        expected.sort_by(|a, b| a.txhash().cmp(b.txhash()));
+        assert_eq!(updates, expected);
+        assert_eq!(tracker.pending(), 0);
+    }
+}
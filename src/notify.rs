@@ -0,0 +1,90 @@
+use {
+    anyhow::{Context, Result},
+    std::{thread, time::Duration},
+};
+
+/// how often the tip is polled when zmq notifications are used, in case some are missed
+pub const ZMQ_FALLBACK_POLL: Duration = Duration::from_secs(30);
+
+/// how `index` waits for the next block: a poll of the tip every interval, or
+/// bitcoind's `zmqpubhashblock`/`zmqpubrawblock` notifications, with polling as
+/// fallback
+pub struct BlockNotifier {
+    socket: Option<zmq::Socket>,
+    interval: Duration,
+}
+
+impl BlockNotifier {
+    pub fn polling(interval: Duration) -> Self {
+        Self { socket: None, interval }
+    }
+
+    /// subscribe to the block notifications published at `endpoint`, e.g.
+    /// `tcp://127.0.0.1:28332`. The tip is still polled every `fallback`, a
+    /// notification may be lost while bitcoind restarts.
+    pub fn zmq(endpoint: &str, fallback: Duration) -> Result<Self> {
+        let socket = zmq::Context::new().socket(zmq::SUB)?;
+        socket.set_subscribe(b"hashblock")?;
+        socket.set_subscribe(b"rawblock")?;
+        socket.connect(endpoint).with_context(|| format!("failed to connect to zmq endpoint {endpoint}"))?;
+        Ok(Self {
+            socket: Some(socket),
+            interval: fallback,
+        })
+    }
+
+    /// wait for a block notification, or the end of the interval. Returns
+    /// whether a block was notified.
+    pub fn wait(&self) -> Result<bool> {
+        let Some(socket) = &self.socket else {
+            thread::sleep(self.interval);
+            return Ok(false);
+        };
+        if socket.poll(zmq::POLLIN, self.interval.as_millis().try_into().unwrap_or(i64::MAX))? == 0 {
+            return Ok(false);
+        }
+        // several blocks may be notified at once, one look at the tip is enough for all
+        loop {
+            match socket.recv_multipart(zmq::DONTWAIT) {
+                Ok(_) => {}
+                Err(zmq::Error::EAGAIN) => return Ok(true),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::time::Instant};
+
+    #[test]
+    fn notifications_wake_the_waiter() {
+        let publisher = zmq::Context::new().socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = publisher.get_last_endpoint().unwrap().unwrap();
+        let notifier = BlockNotifier::zmq(&endpoint, Duration::from_secs(10)).unwrap();
+
+        // the subscription takes a moment to reach the publisher, which drops
+        // the messages published until then, as bitcoind's would
+        let publishing = thread::spawn(move || {
+            for sequence in 0..100u32 {
+                publisher
+                    .send_multipart([b"hashblock".to_vec(), vec![0; 32], sequence.to_le_bytes().to_vec()], 0)
+                    .unwrap();
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+        let start = Instant::now();
+        assert!(notifier.wait().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        publishing.join().unwrap();
+    }
+
+    #[test]
+    fn polling_waits_for_the_interval() {
+        let start = Instant::now();
+        assert!(!BlockNotifier::polling(Duration::from_millis(50)).wait().unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
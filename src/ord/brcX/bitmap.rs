@@ -0,0 +1,14 @@
+use {
+    serde::{Deserialize, Serialize},
+    serde_json::{Map, Value},
+};
+
+/// an inscription claiming a bitmap district
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    /// `<height>.bitmap`
+    pub mint: String,
+    /// fields outside of the spec, kept as inscribed
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
@@ -0,0 +1,16 @@
+use {
+    serde::{Deserialize, Serialize},
+    serde_json::{Map, Value},
+};
+
+pub const PROTOCOL: &str = "brc-420";
+
+/// an inscription whose body references the content of a collection item
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Brc420Mint {
+    /// `/content/<inscription id>`
+    pub mint: String,
+    /// fields outside of the spec, kept as inscribed
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
@@ -1 +1,5 @@
-pub mod brc20;
\ No newline at end of file
+pub mod bitmap;
+pub mod brc20;
+pub mod brc420;
+pub mod sns;
+pub mod tap;
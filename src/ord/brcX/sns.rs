@@ -0,0 +1,22 @@
+use {
+    serde::{Deserialize, Serialize},
+    serde_json::{Map, Value},
+};
+
+pub const PROTOCOL: &str = "sns";
+
+/// `{"p":"sns","op":"reg","name":"satoshi.sats"}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnsRegister {
+    pub name: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `{"p":"sns","op":"ns","ns":"sats"}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnsNamespace {
+    pub ns: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
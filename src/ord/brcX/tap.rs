@@ -0,0 +1,11 @@
+use super::brc20::{Brc20Deploy, Brc20Mint, Brc20Transfer};
+
+// https://github.com/BennyTheDev/tap-protocol-specs
+pub const PROTOCOL: &str = "tap";
+
+/// `{"p":"tap","op":"token-deploy",...}`, same fields as brc-20
+pub type TapDeploy = Brc20Deploy;
+/// `{"p":"tap","op":"token-mint",...}`
+pub type TapMint = Brc20Mint;
+/// `{"p":"tap","op":"token-transfer",...}`
+pub type TapTransfer = Brc20Transfer;
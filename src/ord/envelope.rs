@@ -25,7 +25,7 @@ pub(crate) const BODY_TAG: [u8; 0] = [];
 
 type Result<T> = std::result::Result<T, script::Error>;
 type RawEnvelope = Envelope<Vec<Vec<u8>>>;
-pub(crate) type ParsedEnvelope = Envelope<Inscription>;
+pub type ParsedEnvelope = Envelope<Inscription>;
 
 #[derive(Default, PartialEq, Clone, Serialize, Deserialize, Debug, Eq)]
 pub struct Envelope<T> {
@@ -99,7 +99,7 @@ impl From<RawEnvelope> for ParsedEnvelope {
 }
 
 impl ParsedEnvelope {
-  pub(crate) fn from_transaction(transaction: &Transaction, protocol_id:&[u8]) -> Vec<Self> {
+  pub fn from_transaction(transaction: &Transaction, protocol_id: &[u8]) -> Vec<Self> {
     let aa = RawEnvelope::from_transaction(transaction, protocol_id);
     let bb = aa.into_iter();
     let cc = bb.map(|envelope| envelope.into());
@@ -337,7 +337,7 @@ mod tests {
         })
         .collect(),
       output: Vec::new(),
-    }, b"brc-20")
+    }, &PROTOCOL_ID)
   }
 
   #[test]
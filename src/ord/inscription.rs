@@ -230,7 +230,7 @@ impl Inscription {
     content_type.parse().unwrap_or(Media::Unknown)
   }
 
-  pub(crate) fn body(&self) -> Option<&[u8]> {
+  pub fn body(&self) -> Option<&[u8]> {
     Some(self.body.as_ref()?)
   }
 
@@ -242,7 +242,7 @@ impl Inscription {
     Some(self.body()?.len())
   }
 
-  pub(crate) fn content_type(&self) -> Option<&str> {
+  pub fn content_type(&self) -> Option<&str> {
     str::from_utf8(self.content_type.as_ref()?).ok()
   }
 
@@ -250,7 +250,7 @@ impl Inscription {
     HeaderValue::from_str(str::from_utf8(self.content_encoding.as_ref()?).unwrap_or_default()).ok()
   }
 
-  pub(crate) fn delegate(&self) -> Option<InscriptionId> {
+  pub fn delegate(&self) -> Option<InscriptionId> {
     Self::inscription_id_field(&self.delegate)
   }
 
@@ -258,15 +258,15 @@ impl Inscription {
     ciborium::from_reader(Cursor::new(self.metadata.as_ref()?)).ok()
   }
 
-  pub(crate) fn metaprotocol(&self) -> Option<&str> {
+  pub fn metaprotocol(&self) -> Option<&str> {
     str::from_utf8(self.metaprotocol.as_ref()?).ok()
   }
 
-  pub(crate) fn parent(&self) -> Option<InscriptionId> {
+  pub fn parent(&self) -> Option<InscriptionId> {
     Self::inscription_id_field(&self.parent)
   }
 
-  pub(crate) fn pointer(&self) -> Option<u64> {
+  pub fn pointer(&self) -> Option<u64> {
     let value = self.pointer.as_ref()?;
 
     if value.iter().skip(8).copied().any(|byte| byte != 0) {
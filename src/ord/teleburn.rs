@@ -52,7 +52,7 @@ fn create_address_with_checksum(address: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-  use super::*;
+  use {super::*, std::str::FromStr};
 
   #[test]
   fn test_eth_checksum_generation() {
@@ -0,0 +1,194 @@
+use {
+    crate::decoder::{DecodedEvent, Registry},
+    anyhow::{Context, Result},
+    bitcoin::{block::Header, Block, BlockHash},
+    crossbeam_channel::bounded,
+    std::{
+        collections::BTreeMap,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    },
+};
+
+/// how many threads fetch and decode blocks, and how many blocks may be
+/// between fetching and committing at once, which bounds memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineOptions {
+    pub fetchers: usize,
+    pub workers: usize,
+    pub max_blocks: usize,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        Self {
+            fetchers: 4,
+            workers: thread::available_parallelism().map_or(4, |workers| workers.get()),
+            max_blocks: 64,
+        }
+    }
+}
+
+/// a block decoded by the pipeline, handed to the committer in height order
+#[derive(Debug, Clone)]
+pub struct DecodedBlock {
+    pub height: u64,
+    pub hash: BlockHash,
+    pub header: Header,
+    /// ordered by transaction index
+    pub events: Vec<DecodedEvent>,
+    /// for committers keeping state from the transactions, like the brc-20 ledger
+    pub block: Block,
+}
+
+/// fetch the blocks at `heights` with the fetchers, each on its own thread
+/// (e.g. with its own rpc connection, kept from one run to the next), decode them on `options.workers`
+/// threads and hand them to `commit` in the order of `heights`.
+///
+/// `commit` returns whether to go on. The first error, fetching in the order of
+/// `heights` or committing, stops the pipeline and is returned.
+pub fn run<F>(
+    heights: &[u64],
+    fetchers: &mut [F],
+    registry: &Registry,
+    protocol: &str,
+    options: &PipelineOptions,
+    mut commit: impl FnMut(DecodedBlock) -> Result<bool>,
+) -> Result<()>
+where
+    F: FnMut(u64) -> Result<Block> + Send,
+{
+    let max_blocks = options.max_blocks.max(1);
+    let next = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        // a fetcher takes a slot before fetching a block, the committer frees it
+        // once the block is committed
+        let (slot_tx, slot_rx) = bounded::<()>(max_blocks);
+        for _ in 0..max_blocks {
+            slot_tx.send(()).expect("slots fit the channel");
+        }
+        let (block_tx, block_rx) = bounded::<(usize, Result<Block>)>(max_blocks);
+        let (decoded_tx, decoded_rx) = bounded::<(usize, Result<DecodedBlock>)>(max_blocks);
+
+        for fetch in fetchers.iter_mut() {
+            let (slot_rx, block_tx, next) = (slot_rx.clone(), block_tx.clone(), &next);
+            scope.spawn(move || {
+                while slot_rx.recv().is_ok() {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(&height) = heights.get(index) else {
+                        break;
+                    };
+                    let block = fetch(height).with_context(|| format!("failed to fetch block {height}"));
+                    if block_tx.send((index, block)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(block_tx);
+
+        for _ in 0..options.workers.max(1) {
+            let (block_rx, decoded_tx) = (block_rx.clone(), decoded_tx.clone());
+            scope.spawn(move || {
+                for (index, block) in block_rx {
+                    let height = heights[index];
+                    let decoded = block.map(|block| DecodedBlock {
+                        height,
+                        hash: block.block_hash(),
+                        header: block.header,
+                        events: registry.decode_block(&block, height, protocol),
+                        block,
+                    });
+                    if decoded_tx.send((index, decoded)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(decoded_tx);
+
+        // blocks decoded ahead of the next one to commit
+        let mut pending = BTreeMap::new();
+        let mut committed = 0;
+        while committed < heights.len() {
+            let Ok((index, decoded)) = decoded_rx.recv() else {
+                break;
+            };
+            pending.insert(index, decoded);
+            while let Some(decoded) = pending.remove(&committed) {
+                if !commit(decoded?)? {
+                    return Ok(());
+                }
+                committed += 1;
+                let _ = slot_tx.send(());
+            }
+        }
+        // dropping the channels stops the threads still running
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        anyhow::anyhow,
+        bitcoin::{blockdata::constants::genesis_block, Network},
+        std::time::Duration,
+    };
+
+    fn fetcher(delay: u64) -> impl FnMut(u64) -> Result<Block> + Send {
+        move |height| {
+            // later blocks come first, the committer puts them back in order
+            thread::sleep(Duration::from_millis(delay * (10 - height % 10)));
+            if height == 666 {
+                return Err(anyhow!("no such block"));
+            }
+            Ok(genesis_block(Network::Bitcoin))
+        }
+    }
+
+    #[test]
+    fn blocks_are_committed_in_order() {
+        let heights = (0..40).collect::<Vec<u64>>();
+        let options = PipelineOptions {
+            fetchers: 4,
+            workers: 3,
+            max_blocks: 8,
+        };
+        let mut committed = Vec::new();
+        run(&heights, &mut [fetcher(1), fetcher(2), fetcher(1), fetcher(3)], &Registry::default(), "all", &options, |block| {
+            committed.push(block.height);
+            Ok(true)
+        })
+        .unwrap();
+        assert_eq!(committed, heights);
+    }
+
+    #[test]
+    fn the_first_error_stops_the_pipeline() {
+        let heights = [664, 665, 666, 667, 668];
+        let options = PipelineOptions {
+            fetchers: 2,
+            workers: 2,
+            max_blocks: 2,
+        };
+        let mut committed = Vec::new();
+        let err = run(&heights, &mut [fetcher(1), fetcher(1)], &Registry::default(), "all", &options, |block| {
+            committed.push(block.height);
+            Ok(true)
+        })
+        .unwrap_err();
+        assert_eq!(committed, [664, 665]);
+        assert_eq!(err.to_string(), "failed to fetch block 666");
+
+        committed.clear();
+        run(&heights, &mut [fetcher(1)], &Registry::default(), "all", &options, |block| {
+            committed.push(block.height);
+            Ok(block.height < 665)
+        })
+        .unwrap();
+        assert_eq!(committed, [664, 665]);
+    }
+}
@@ -0,0 +1,145 @@
+use {
+    anyhow::{bail, Result},
+    bitcoin::{block::Header, BlockHash},
+    std::collections::VecDeque,
+};
+
+/// how many blocks below the tip are remembered to find the fork of a reorg
+pub const MAX_REORG_DEPTH: usize = 100;
+
+/// the hashes of the most recently indexed blocks, to notice when the chain
+/// the events were extracted from is replaced by another branch
+pub struct BlockHistory {
+    depth: usize,
+    blocks: VecDeque<(u64, BlockHash)>,
+}
+
+impl Default for BlockHistory {
+    fn default() -> Self {
+        Self::new(MAX_REORG_DEPTH)
+    }
+}
+
+impl BlockHistory {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            blocks: VecDeque::with_capacity(depth),
+        }
+    }
+
+    /// remember `hash` as the block indexed at `height`, on top of the previous ones
+    pub fn push(&mut self, height: u64, hash: BlockHash) {
+        if self.blocks.len() == self.depth {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back((height, hash));
+    }
+
+    /// the remembered hash of the block at `height`
+    pub fn hash(&self, height: u64) -> Option<BlockHash> {
+        let (first, _) = self.blocks.front()?;
+        let (_, hash) = self.blocks.get(height.checked_sub(*first)? as usize)?;
+        Some(*hash)
+    }
+
+    /// the highest remembered block
+    pub fn tip(&self) -> Option<(u64, BlockHash)> {
+        self.blocks.back().copied()
+    }
+
+    /// whether the block with `header` at `height` doesn't extend the remembered chain
+    pub fn is_reorg(&self, height: u64, header: &Header) -> bool {
+        match height.checked_sub(1).and_then(|parent| self.hash(parent)) {
+            Some(parent) => parent != header.prev_blockhash,
+            None => false,
+        }
+    }
+
+    /// the height of the highest remembered block still in the chain whose block
+    /// hashes `chain` returns, the last block to keep the events of
+    pub fn fork_point(&self, mut chain: impl FnMut(u64) -> Result<BlockHash>) -> Result<u64> {
+        for (height, hash) in self.blocks.iter().rev() {
+            if chain(*height)? == *hash {
+                return Ok(*height);
+            }
+        }
+        bail!("reorg deeper than the {} remembered blocks", self.blocks.len())
+    }
+
+    /// forget the blocks above `height`
+    pub fn rollback(&mut self, height: u64) {
+        while matches!(self.blocks.back(), Some((tip, _)) if *tip > height) {
+            self.blocks.pop_back();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        bitcoin::{hashes::Hash, hash_types::TxMerkleNode, block::Version, CompactTarget},
+    };
+
+    fn hash(n: u8) -> BlockHash {
+        BlockHash::from_byte_array([n; 32])
+    }
+
+    fn header(prev: BlockHash) -> Header {
+        Header {
+            version: Version::ONE,
+            prev_blockhash: prev,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 0,
+            bits: CompactTarget::from_consensus(0),
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn remembers_the_last_blocks() {
+        let mut history = BlockHistory::new(3);
+        for height in 10..15 {
+            history.push(height, hash(height as u8));
+        }
+        assert_eq!(history.hash(11), None);
+        assert_eq!(history.hash(12), Some(hash(12)));
+        assert_eq!(history.hash(14), Some(hash(14)));
+        assert_eq!(history.hash(15), None);
+        assert_eq!(history.tip(), Some((14, hash(14))));
+    }
+
+    #[test]
+    fn detects_blocks_not_extending_the_tip() {
+        let mut history = BlockHistory::default();
+        assert!(!history.is_reorg(10, &header(hash(1))));
+        history.push(9, hash(9));
+        assert!(!history.is_reorg(10, &header(hash(9))));
+        assert!(history.is_reorg(10, &header(hash(1))));
+    }
+
+    #[test]
+    fn finds_the_fork_point() {
+        let mut history = BlockHistory::default();
+        for height in 10..15 {
+            history.push(height, hash(height as u8));
+        }
+        // blocks 13 and 14 were replaced
+        let chain = |height| Ok(if height >= 13 { hash(100 + height as u8) } else { hash(height as u8) });
+        assert_eq!(history.fork_point(chain).unwrap(), 12);
+
+        history.rollback(12);
+        assert_eq!(history.tip(), Some((12, hash(12))));
+        history.push(13, hash(113));
+        assert_eq!(history.hash(13), Some(hash(113)));
+    }
+
+    #[test]
+    fn fails_on_reorgs_deeper_than_the_history() {
+        let mut history = BlockHistory::new(2);
+        history.push(10, hash(10));
+        history.push(11, hash(11));
+        assert!(history.fork_point(|_| Ok(hash(0))).is_err());
+    }
+}
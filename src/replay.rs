@@ -0,0 +1,199 @@
+use {
+    crate::{
+        decoder::{DecodedEvent, EventKey, ALL_PROTOCOLS},
+        sink::{Compression, EventSink},
+    },
+    anyhow::{bail, Context, Result},
+    std::{
+        collections::{hash_map::Entry, HashMap},
+        fs::File,
+        io::{BufRead, BufReader},
+        path::{Path, PathBuf},
+    },
+};
+
+/// what a replay pushed to the sinks
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayStats {
+    pub files: usize,
+    pub blocks: usize,
+    pub events: usize,
+    /// lines which are not events, skipped
+    pub invalid: usize,
+}
+
+/// the files of `--input` values, each a path or a glob pattern, in the given order
+pub fn expand_inputs(inputs: &[String]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        let matches = glob::glob(input)
+            .with_context(|| format!("invalid input pattern {input:?}"))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if matches.is_empty() {
+            bail!("no file matches {input:?}");
+        }
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+/// the events of a block being read, with the ordinals given to legacy lines
+struct Block {
+    height: Option<i64>,
+    events: Vec<DecodedEvent>,
+    /// the position of each key in `events`
+    keys: HashMap<EventKey, usize>,
+    /// the next ordinal of each txhash, location and protocol, for lines written
+    /// before events had an ordinal
+    ordinals: HashMap<(String, u32, u32, String), u32>,
+}
+
+impl Block {
+    fn new(height: Option<i64>) -> Self {
+        Self {
+            height,
+            events: Vec::new(),
+            keys: HashMap::new(),
+            ordinals: HashMap::new(),
+        }
+    }
+
+    /// add the event, replacing an earlier one with the same key, as a sink would
+    fn push(&mut self, mut event: DecodedEvent, has_ordinal: bool) {
+        if !has_ordinal {
+            let next = self
+                .ordinals
+                .entry((event.txhash.clone(), event.input, event.offset, event.event.protocol().to_string()))
+                .or_default();
+            event.ordinal = *next;
+            *next += 1;
+        }
+        match self.keys.entry(event.key()) {
+            Entry::Occupied(key) => self.events[*key.get()] = event,
+            Entry::Vacant(key) => {
+                key.insert(self.events.len());
+                self.events.push(event);
+            }
+        }
+    }
+
+    fn write(self, sink: &mut dyn EventSink, stats: &mut ReplayStats) -> Result<()> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+        sink.write_block(self.height.map(|height| height as u64), &self.events, None)?;
+        stats.blocks += 1;
+        stats.events += self.events.len();
+        Ok(())
+    }
+}
+
+/// push the events of jsonl files, plain or compressed as `.zst` or `.gz`, into
+/// the sink, one block of consecutive lines of the same height at a time. Only
+/// the events of `protocol` are kept, unless it is `all`. Lines which are not
+/// events are reported and skipped. The caller finishes the sink.
+pub fn replay(paths: &[PathBuf], protocol: &str, sink: &mut dyn EventSink) -> Result<ReplayStats> {
+    let mut stats = ReplayStats::default();
+    for path in paths {
+        replay_file(path, protocol, sink, &mut stats).with_context(|| format!("failed to replay {}", path.display()))?;
+        stats.files += 1;
+    }
+    Ok(stats)
+}
+
+fn replay_file(path: &Path, protocol: &str, sink: &mut dyn EventSink, stats: &mut ReplayStats) -> Result<()> {
+    let file = File::open(path)?;
+    let reader: Box<dyn BufRead> = match Compression::of_path(path) {
+        Some(compression) => compression.reader(file)?,
+        None => Box::new(BufReader::new(file)),
+    };
+
+    let mut block = Block::new(None);
+    for (number, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let parsed = serde_json::from_slice::<serde_json::Value>(&line).and_then(|value| {
+            let has_ordinal = value.get("ordinal").is_some();
+            serde_json::from_value::<DecodedEvent>(value).map(|event| (event, has_ordinal))
+        });
+        let (event, has_ordinal) = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                eprintln!("WARN: {}:{}: not an event, skipped: {err}", path.display(), number + 1);
+                stats.invalid += 1;
+                continue;
+            }
+        };
+        if protocol != ALL_PROTOCOLS && event.event.protocol() != protocol {
+            continue;
+        }
+        if event.height != block.height {
+            std::mem::replace(&mut block, Block::new(event.height)).write(sink, stats)?;
+        }
+        block.push(event, has_ordinal);
+    }
+    block.write(sink, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::sink::JsonlSink};
+
+    #[test]
+    fn lines_are_grouped_in_blocks_and_legacy_lines_get_ordinals() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("archive.jsonl");
+        let output = dir.path().join("events.jsonl");
+        std::fs::write(
+            &input,
+            [
+                r#"{"height":1,"txhash":"aa","protocol":"ord-bitmap","payload":{"mint":"1.bitmap"}}"#,
+                r#"{"height":1,"txhash":"aa","protocol":"ord-bitmap","payload":{"mint":"2.bitmap"}}"#,
+                "not json",
+                r#"{"height":2,"txhash":"bb","input":0,"offset":0,"ordinal":0,"protocol":"ord-bitmap","payload":{"mint":"3.bitmap"}}"#,
+                r#"{"height":2,"txhash":"bb","input":0,"offset":0,"ordinal":0,"protocol":"ord-bitmap","payload":{"mint":"4.bitmap"}}"#,
+                r#"{"height":2,"txhash":"cc","protocol":"ord-sns","payload":{"p":"sns","op":"reg","name":"a.sats"}}"#,
+                "",
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        let mut sink = JsonlSink::open(output.to_str().unwrap()).unwrap();
+        let paths = expand_inputs(&[dir.path().join("*.jsonl").to_string_lossy().into_owned()]).unwrap();
+        assert_eq!(paths, [input]);
+        let stats = replay(&paths, "ord-bitmap", &mut sink).unwrap();
+        assert_eq!(
+            stats,
+            ReplayStats {
+                files: 1,
+                blocks: 2,
+                events: 3,
+                invalid: 1,
+            }
+        );
+
+        let written = std::fs::read_to_string(&output).unwrap();
+        let events = written
+            .lines()
+            .map(|line| serde_json::from_str::<DecodedEvent>(line).unwrap())
+            .map(|event| (event.txhash, event.ordinal, event.event.payload().unwrap()["mint"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                ("aa".to_string(), 0, "1.bitmap".into()),
+                ("aa".to_string(), 1, "2.bitmap".into()),
+                ("bb".to_string(), 0, "4.bitmap".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unmatched_inputs_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(expand_inputs(&[dir.path().join("*.jsonl").to_string_lossy().into_owned()]).is_err());
+    }
+}
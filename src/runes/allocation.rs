@@ -0,0 +1,151 @@
+use {
+    bitcoin::Transaction,
+    ordinals::{Artifact, Edict, RuneId},
+    std::collections::BTreeMap,
+};
+
+/// where the runes of a transaction go, by output
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    /// the runes received by each output, none for OP_RETURN outputs
+    pub outputs: Vec<BTreeMap<RuneId, u128>>,
+    /// burned by a cenotaph, sent to OP_RETURN outputs, or left without output
+    pub burned: BTreeMap<RuneId, u128>,
+}
+
+/// allocate the runes of a transaction as the runes protocol does: the edicts
+/// in order, then what is left to the `pointer` output, else the first output
+/// which isn't OP_RETURN. A cenotaph burns everything.
+///
+/// `unallocated` holds the runes of the inputs with the minted amount and the
+/// premine of `etched`, the rune etched by the transaction, which edicts of id
+/// `0:0` refer to.
+pub fn allocate(tx: &Transaction, artifact: Option<&Artifact>, etched: Option<RuneId>, mut unallocated: BTreeMap<RuneId, u128>) -> Allocation {
+    let mut allocation = Allocation {
+        outputs: vec![BTreeMap::new(); tx.output.len()],
+        burned: BTreeMap::new(),
+    };
+    let runestone = match artifact {
+        Some(Artifact::Cenotaph(_)) => {
+            allocation.burned = unallocated.into_iter().filter(|(_, amount)| *amount > 0).collect();
+            return allocation;
+        }
+        Some(Artifact::Runestone(runestone)) => Some(runestone),
+        None => None,
+    };
+
+    let mut give = |unallocated: &mut BTreeMap<RuneId, u128>, id: RuneId, amount: u128, output: usize| {
+        if amount > 0 {
+            *unallocated.get_mut(&id).expect("allocated runes are unallocated first") -= amount;
+            *allocation.outputs[output].entry(id).or_default() += amount;
+        }
+    };
+    for Edict { id, amount, output } in runestone.map(|runestone| runestone.edicts.clone()).unwrap_or_default() {
+        let id = match (id == RuneId::default(), etched) {
+            (true, Some(etched)) => etched,
+            (true, None) => continue,
+            (false, _) => id,
+        };
+        let Some(balance) = unallocated.get(&id).copied() else {
+            continue;
+        };
+        if output as usize == tx.output.len() {
+            // an edict to every output which isn't OP_RETURN
+            let destinations = (0..tx.output.len()).filter(|vout| !tx.output[*vout].script_pubkey.is_op_return()).collect::<Vec<_>>();
+            if destinations.is_empty() {
+                continue;
+            }
+            if amount == 0 {
+                let share = balance / destinations.len() as u128;
+                let remainder = (balance % destinations.len() as u128) as usize;
+                for (index, vout) in destinations.into_iter().enumerate() {
+                    give(&mut unallocated, id, share + u128::from(index < remainder), vout);
+                }
+            } else {
+                for vout in destinations {
+                    let left = unallocated[&id];
+                    give(&mut unallocated, id, amount.min(left), vout);
+                }
+            }
+        } else {
+            let amount = if amount == 0 { balance } else { amount.min(balance) };
+            give(&mut unallocated, id, amount, output as usize);
+        }
+    }
+
+    let default_output = runestone
+        .and_then(|runestone| runestone.pointer)
+        .map(|pointer| pointer as usize)
+        .or_else(|| (0..tx.output.len()).find(|vout| !tx.output[*vout].script_pubkey.is_op_return()));
+    for (id, amount) in unallocated.into_iter().filter(|(_, amount)| *amount > 0) {
+        match default_output {
+            Some(vout) => *allocation.outputs[vout].entry(id).or_default() += amount,
+            None => *allocation.burned.entry(id).or_default() += amount,
+        }
+    }
+
+    for (vout, output) in tx.output.iter().enumerate() {
+        if output.script_pubkey.is_op_return() {
+            for (id, amount) in std::mem::take(&mut allocation.outputs[vout]) {
+                *allocation.burned.entry(id).or_default() += amount;
+            }
+        }
+    }
+    allocation
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        bitcoin::{locktime::absolute::LockTime, ScriptBuf, TxOut},
+        ordinals::Runestone,
+    };
+
+    fn tx(runestone: &Runestone, outputs: usize) -> Transaction {
+        let mut output = vec![TxOut { value: 0, script_pubkey: runestone.encipher() }];
+        output.extend((0..outputs).map(|_| TxOut { value: 546, script_pubkey: ScriptBuf::new() }));
+        Transaction { version: 2, lock_time: LockTime::ZERO, input: Vec::new(), output }
+    }
+
+    fn runes(balances: &[(RuneId, u128)]) -> BTreeMap<RuneId, u128> {
+        balances.iter().copied().collect()
+    }
+
+    #[test]
+    fn edicts_then_pointer_then_first_output() {
+        let (a, b) = (RuneId { block: 1, tx: 1 }, RuneId { block: 2, tx: 1 });
+        let unallocated = runes(&[(a, 1001), (b, 50)]);
+
+        // split between the outputs which aren't OP_RETURN, the remainder to the first ones
+        let runestone = Runestone { edicts: vec![Edict { id: a, amount: 0, output: 4 }], ..Default::default() };
+        let tx3 = tx(&runestone, 3);
+        let allocation = allocate(&tx3, Runestone::decipher(&tx3).as_ref(), None, unallocated.clone());
+        assert_eq!(allocation.outputs, [runes(&[]), runes(&[(a, 334), (b, 50)]), runes(&[(a, 334)]), runes(&[(a, 333)])]);
+        assert!(allocation.burned.is_empty());
+
+        // a fixed amount to each, capped by what is left; the rest to the pointer
+        let runestone = Runestone {
+            edicts: vec![Edict { id: a, amount: 600, output: 3 }, Edict { id: RuneId::default(), amount: 1, output: 1 }],
+            pointer: Some(2),
+            ..Default::default()
+        };
+        let tx2 = tx(&runestone, 2);
+        let allocation = allocate(&tx2, Runestone::decipher(&tx2).as_ref(), None, unallocated.clone());
+        assert_eq!(allocation.outputs, [runes(&[]), runes(&[(a, 600)]), runes(&[(a, 401), (b, 50)])]);
+
+        // to the OP_RETURN output, then burned
+        let runestone = Runestone { edicts: vec![Edict { id: b, amount: 10, output: 0 }], ..Default::default() };
+        let tx0 = tx(&runestone, 0);
+        let allocation = allocate(&tx0, Runestone::decipher(&tx0).as_ref(), None, unallocated.clone());
+        assert_eq!(allocation.outputs, [runes(&[])]);
+        assert_eq!(allocation.burned, unallocated);
+
+        // a cenotaph burns everything
+        let runestone = Runestone { edicts: vec![Edict { id: a, amount: 1, output: 9 }], ..Default::default() };
+        let cenotaph = tx(&runestone, 1);
+        let artifact = Runestone::decipher(&cenotaph);
+        assert!(matches!(artifact, Some(Artifact::Cenotaph(_))));
+        assert_eq!(allocate(&cenotaph, artifact.as_ref(), None, unallocated.clone()).burned, unallocated);
+    }
+}
@@ -0,0 +1,538 @@
+use {
+    crate::{
+        brc20::Verdict,
+        decoder::{located_events, DecodedEvent, Location},
+        event::Event,
+        sat_flow,
+        source::BlockSource,
+    },
+    anyhow::Result,
+    bitcoin::{script::Instruction, Block, Network, OutPoint, Transaction, Txid},
+    ordinals::{Artifact, Height, Rune, RuneId, Runestone, SpacedRune, Terms},
+    serde::{Deserialize, Serialize},
+    std::collections::{BTreeMap, HashMap},
+};
+
+pub use self::{
+    allocation::{allocate, Allocation},
+    simulate::{simulate, Simulation},
+};
+
+mod allocation;
+mod simulate;
+
+/// the protocol of the etchings, mints and burns of the indexer
+pub const LEDGER_PROTOCOL: &str = "rune-ledger";
+/// the protocol of the rune balance changes of outputs
+pub const BALANCE_PROTOCOL: &str = "rune-balance";
+
+/// a rune operation as applied by the indexer: `etch`, `mint`, or `burn`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RuneOperation {
+    pub op: String,
+    /// `None` for an invalid etching, which gets no id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<RuneId>,
+    /// `None` when minting a rune which isn't etched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rune: Option<SpacedRune>,
+    /// the premine, the amount minted or burned, in the smallest unit of the rune
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<String>,
+    #[serde(flatten)]
+    pub verdict: Verdict,
+}
+
+/// the change of the balance of a rune of an output, credited when the output
+/// is created and debited when it is spent
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RuneBalanceChange {
+    pub id: RuneId,
+    pub rune: SpacedRune,
+    pub outpoint: OutPoint,
+    /// the address of the output, or the hex of its script without address
+    pub owner: String,
+    /// signed, in the smallest unit of the rune, e.g. `-1000`
+    pub change: String,
+}
+
+/// an etched rune
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuneEntry {
+    pub spaced_rune: SpacedRune,
+    pub etching: Txid,
+    pub divisibility: u8,
+    pub symbol: Option<char>,
+    pub premine: u128,
+    pub terms: Option<Terms>,
+    pub turbo: bool,
+    pub mints: u128,
+    pub burned: u128,
+}
+
+impl RuneEntry {
+    /// the amount of a mint at `height`, or why the rune can't be minted
+    pub fn mintable(&self, id: RuneId, height: u64) -> Result<u128, String> {
+        let terms = self.terms.as_ref().ok_or("rune is not mintable")?;
+        if let Some(start) = Self::window(terms.offset.0.map(|offset| id.block.saturating_add(offset)), terms.height.0, u64::max) {
+            if height < start {
+                return Err("mint has not started".into());
+            }
+        }
+        if let Some(end) = Self::window(terms.offset.1.map(|offset| id.block.saturating_add(offset)), terms.height.1, u64::min) {
+            if height >= end {
+                return Err("mint has ended".into());
+            }
+        }
+        if self.mints >= terms.cap.unwrap_or_default() {
+            return Err("mint cap reached".into());
+        }
+        Ok(terms.amount.unwrap_or_default())
+    }
+
+    /// the premine and the amount minted so far, burned runes included
+    pub fn supply(&self) -> u128 {
+        let amount = self.terms.and_then(|terms| terms.amount).unwrap_or_default();
+        self.premine.saturating_add(self.mints.saturating_mul(amount))
+    }
+
+    /// the bound of a mint window set by an offset from the etching block and
+    /// an absolute height, the stricter of the two
+    fn window(relative: Option<u64>, absolute: Option<u64>, stricter: fn(u64, u64) -> u64) -> Option<u64> {
+        match (relative, absolute) {
+            (Some(relative), Some(absolute)) => Some(stricter(relative, absolute)),
+            (relative, absolute) => relative.or(absolute),
+        }
+    }
+}
+
+/// the runes in an unspent output
+struct RuneOutput {
+    owner: String,
+    balances: Vec<(RuneId, u128)>,
+}
+
+/// the events of a transaction being indexed, with their location
+struct TxEvents(Vec<(Location, Event)>);
+
+impl TxEvents {
+    fn operation(&mut self, op: &str, id: Option<RuneId>, rune: Option<SpacedRune>, amount: Option<u128>, verdict: Verdict) {
+        self.0.push((
+            Location::default(),
+            Event::RuneOperation(RuneOperation {
+                op: op.into(),
+                id,
+                rune,
+                amount: amount.map(|amount| amount.to_string()),
+                verdict,
+            }),
+        ));
+    }
+
+    fn change(&mut self, location: Location, id: RuneId, rune: SpacedRune, outpoint: OutPoint, owner: &str, change: String) {
+        self.0.push((
+            location,
+            Event::RuneBalanceChange(RuneBalanceChange {
+                id,
+                rune,
+                outpoint,
+                owner: owner.to_string(),
+                change,
+            }),
+        ));
+    }
+}
+
+/// the runes state built from the runestones of the blocks, in order: the
+/// etched runes by id and name, and the runes held by every unspent output.
+///
+/// Mints are checked against the `cap` and the `height` and `offset` windows
+/// of the terms of their rune. The runes of the inputs, minted and premined are
+/// allocated by the edicts, then to the `pointer` output or the first output
+/// which isn't OP_RETURN. Cenotaphs burn them, and etch their rune without
+/// terms nor premine.
+///
+/// A named etching is invalid below the minimum name of its height, with a
+/// reserved name, or without an input revealing the commitment to its name in
+/// its tapscript, spending a taproot output confirmed
+/// [`Runestone::COMMIT_CONFIRMATIONS`] blocks earlier.
+pub struct RuneIndexer {
+    network: Network,
+    entries: HashMap<RuneId, RuneEntry>,
+    ids: BTreeMap<Rune, RuneId>,
+    outputs: HashMap<OutPoint, RuneOutput>,
+}
+
+impl RuneIndexer {
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            entries: HashMap::new(),
+            ids: BTreeMap::new(),
+            outputs: HashMap::new(),
+        }
+    }
+
+    pub fn entry(&self, id: RuneId) -> Option<&RuneEntry> {
+        self.entries.get(&id)
+    }
+
+    /// the id of an etched rune
+    pub fn id(&self, rune: Rune) -> Option<RuneId> {
+        self.ids.get(&rune).copied()
+    }
+
+    /// the runes of an unspent output
+    pub fn balances(&self, outpoint: &OutPoint) -> &[(RuneId, u128)] {
+        self.outputs.get(outpoint).map_or(&[], |output| &output.balances)
+    }
+
+    /// apply the block at `height` and return its etchings, mints and burns, and
+    /// the balance changes of the outputs it spends and creates. `source` gives
+    /// the outputs committing to the names of etchings.
+    pub fn index_block(&mut self, block: &Block, height: u64, source: &dyn BlockSource) -> Result<Vec<DecodedEvent>> {
+        let mut decoded = Vec::new();
+        for (txindex, tx) in block.txdata.iter().enumerate() {
+            let mut tx_events = TxEvents(Vec::new());
+            self.index_tx(tx, height, txindex as u32, source, &mut tx_events)?;
+
+            decoded.extend(located_events(tx, Some((block, height, txindex)), tx_events.0));
+        }
+        Ok(decoded)
+    }
+
+    fn index_tx(&mut self, tx: &Transaction, height: u64, txindex: u32, source: &dyn BlockSource, events: &mut TxEvents) -> Result<()> {
+        let mut unallocated: BTreeMap<RuneId, u128> = BTreeMap::new();
+        for (input, txin) in tx.input.iter().enumerate() {
+            let Some(output) = self.outputs.remove(&txin.previous_output) else {
+                continue;
+            };
+            for (id, amount) in output.balances {
+                *unallocated.entry(id).or_default() += amount;
+                let location = Location { input: input as u32, offset: 0 };
+                events.change(location, id, self.entries[&id].spaced_rune, txin.previous_output, &output.owner, format!("-{amount}"));
+            }
+        }
+
+        let artifact = Runestone::decipher(tx);
+        if let Some(id) = artifact.as_ref().and_then(Artifact::mint) {
+            let entry = self.entries.get_mut(&id);
+            let rune = entry.as_ref().map(|entry| entry.spaced_rune);
+            match entry.ok_or_else(|| "rune not etched".to_string()).and_then(|entry| entry.mintable(id, height).map(|amount| (entry, amount))) {
+                Ok((entry, amount)) => {
+                    entry.mints += 1;
+                    *unallocated.entry(id).or_default() += amount;
+                    events.operation("mint", Some(id), rune, Some(amount), Verdict::Valid);
+                }
+                Err(reason) => events.operation("mint", Some(id), rune, None, Verdict::Invalid(reason)),
+            }
+        }
+
+        let etched = match &artifact {
+            Some(artifact) => self.etch(tx, artifact, height, txindex, source, events)?,
+            None => None,
+        };
+        if let (Some(id), Some(Artifact::Runestone(runestone))) = (etched, &artifact) {
+            let premine = runestone.etching.and_then(|etching| etching.premine).unwrap_or_default();
+            *unallocated.entry(id).or_default() += premine;
+        }
+
+        let allocation = allocate(tx, artifact.as_ref(), etched, unallocated);
+        for (id, amount) in allocation.burned {
+            let entry = self.entries.get_mut(&id).expect("burned runes are etched");
+            entry.burned = entry.burned.saturating_add(amount);
+            events.operation("burn", Some(id), Some(entry.spaced_rune), Some(amount), Verdict::Valid);
+        }
+        let txid = tx.txid();
+        for (vout, balances) in allocation.outputs.into_iter().enumerate() {
+            if balances.is_empty() {
+                continue;
+            }
+            let outpoint = OutPoint::new(txid, vout as u32);
+            let owner = sat_flow::owner(&tx.output[vout].script_pubkey, self.network);
+            for (id, amount) in &balances {
+                events.change(Location::default(), *id, self.entries[id].spaced_rune, outpoint, &owner, amount.to_string());
+            }
+            self.outputs.insert(
+                outpoint,
+                RuneOutput {
+                    owner,
+                    balances: balances.into_iter().collect(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// etch the rune of the artifact, named or reserved, and return its id
+    fn etch(&mut self, tx: &Transaction, artifact: &Artifact, height: u64, txindex: u32, source: &dyn BlockSource, events: &mut TxEvents) -> Result<Option<RuneId>> {
+        let (rune, spacers, etching) = match artifact {
+            Artifact::Runestone(Runestone { etching: Some(etching), .. }) => (etching.rune, etching.spacers.unwrap_or_default(), Some(*etching)),
+            Artifact::Cenotaph(cenotaph) if cenotaph.etching.is_some() => (cenotaph.etching, 0, None),
+            _ => return Ok(None),
+        };
+        let rune = match rune {
+            Some(rune) => {
+                let reason = if rune < Rune::minimum_at_height(self.network, Height(height as u32)) {
+                    Some("rune name is too short at this height".to_string())
+                } else if rune.is_reserved() {
+                    Some("rune is reserved".to_string())
+                } else if self.ids.contains_key(&rune) {
+                    Some("rune already etched".to_string())
+                } else if !commits_to_rune(tx, rune, height, source)? {
+                    Some(format!("no input reveals a commitment to the rune confirmed {} blocks earlier", Runestone::COMMIT_CONFIRMATIONS))
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    events.operation("etch", None, Some(SpacedRune::new(rune, spacers)), None, Verdict::Invalid(reason));
+                    return Ok(None);
+                }
+                rune
+            }
+            None => Rune::reserved(height, txindex),
+        };
+        let id = RuneId { block: height, tx: txindex };
+        let entry = RuneEntry {
+            spaced_rune: SpacedRune::new(rune, spacers),
+            etching: tx.txid(),
+            divisibility: etching.and_then(|etching| etching.divisibility).unwrap_or_default(),
+            symbol: etching.and_then(|etching| etching.symbol),
+            premine: etching.and_then(|etching| etching.premine).unwrap_or_default(),
+            terms: etching.and_then(|etching| etching.terms),
+            turbo: etching.is_some_and(|etching| etching.turbo),
+            mints: 0,
+            burned: 0,
+        };
+        events.operation("etch", Some(id), Some(entry.spaced_rune), Some(entry.premine), Verdict::Valid);
+        self.entries.insert(id, entry);
+        self.ids.insert(rune, id);
+        Ok(Some(id))
+    }
+}
+
+/// whether an input of the transaction reveals the commitment to `rune` in its
+/// tapscript, spending a taproot output confirmed at least
+/// [`Runestone::COMMIT_CONFIRMATIONS`] blocks before `height`, the block of the
+/// transaction included
+fn commits_to_rune(tx: &Transaction, rune: Rune, height: u64, source: &dyn BlockSource) -> Result<bool> {
+    let commitment = rune.commitment();
+    for input in &tx.input {
+        let Some(tapscript) = input.witness.tapscript() else {
+            continue;
+        };
+        for instruction in tapscript.instructions() {
+            let Ok(instruction) = instruction else {
+                break;
+            };
+            if !matches!(instruction, Instruction::PushBytes(bytes) if bytes.as_bytes() == commitment.as_slice()) {
+                continue;
+            }
+            let commit = source.raw_tx(&input.previous_output.txid)?;
+            let taproot = commit
+                .output
+                .get(input.previous_output.vout as usize)
+                .is_some_and(|output| output.script_pubkey.is_v1_p2tr());
+            if !taproot {
+                continue;
+            }
+            let Some(commit_height) = source.tx_height(&input.previous_output.txid)? else {
+                continue;
+            };
+            if height.saturating_sub(commit_height) + 1 >= u64::from(Runestone::COMMIT_CONFIRMATIONS) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::test_util::{self, block, script},
+        anyhow::anyhow,
+        bitcoin::{block::Header, hashes::Hash, locktime::absolute::LockTime, script, BlockHash, ScriptBuf, TxOut, Witness},
+        ordinals::{Edict, Etching},
+    };
+
+    /// the commit transactions, with their height
+    #[derive(Default)]
+    struct Commits(HashMap<Txid, (Transaction, u64)>);
+
+    impl BlockSource for Commits {
+        fn tip_height(&self) -> Result<u64> {
+            unimplemented!()
+        }
+
+        fn block_hash(&self, _height: u64) -> Result<BlockHash> {
+            unimplemented!()
+        }
+
+        fn block(&self, _hash: &BlockHash) -> Result<Block> {
+            unimplemented!()
+        }
+
+        fn block_header(&self, _hash: &BlockHash) -> Result<Header> {
+            unimplemented!()
+        }
+
+        fn raw_tx(&self, txid: &Txid) -> Result<Transaction> {
+            self.0.get(txid).map(|(tx, _)| tx.clone()).ok_or_else(|| anyhow!("no transaction {txid}"))
+        }
+
+        fn mempool_txids(&self) -> Result<Vec<Txid>> {
+            unimplemented!()
+        }
+
+        fn tx_height(&self, txid: &Txid) -> Result<Option<u64>> {
+            Ok(self.0.get(txid).map(|(_, height)| *height))
+        }
+
+        fn block_fees(&self, _hash: &BlockHash) -> Result<Vec<u64>> {
+            unimplemented!()
+        }
+    }
+
+    impl Commits {
+        /// a taproot output committed at `height`, and the witness revealing the commitment to `rune`
+        fn commit(&mut self, rune: Rune, height: u64) -> (OutPoint, Witness) {
+            let mut taproot = vec![0x51, 0x20];
+            taproot.extend([self.0.len() as u8 + 1; 32]);
+            let commit = Transaction {
+                version: 2,
+                lock_time: LockTime::ZERO,
+                input: Vec::new(),
+                output: vec![TxOut { value: 10000, script_pubkey: ScriptBuf::from(taproot) }],
+            };
+            let outpoint = OutPoint::new(commit.txid(), 0);
+            self.0.insert(commit.txid(), (commit, height));
+            let commitment = script::PushBytesBuf::try_from(rune.commitment()).unwrap();
+            let tapscript = script::Builder::new().push_slice(commitment).push_opcode(bitcoin::opcodes::OP_TRUE).into_script();
+            (outpoint, Witness::from_slice(&[tapscript.as_bytes(), &[0xc0; 33]]))
+        }
+    }
+
+    /// a transaction with the runestone first, then an output to each owner
+    fn tx(previous_output: OutPoint, runestone: &Runestone, owners: &[u8]) -> Transaction {
+        reveal((previous_output, Witness::new()), runestone, owners)
+    }
+
+    fn reveal(input: (OutPoint, Witness), runestone: &Runestone, owners: &[u8]) -> Transaction {
+        let mut tx = test_util::tx(&[input], &owners.iter().map(|owner| (*owner, 546)).collect::<Vec<_>>());
+        tx.output.insert(0, TxOut { value: 0, script_pubkey: runestone.encipher() });
+        tx
+    }
+
+    fn index(indexer: &mut RuneIndexer, commits: &Commits, height: u64, txdata: Vec<Transaction>) -> Vec<Event> {
+        indexer.index_block(&block(txdata), height, commits).unwrap().into_iter().map(|event| event.event).collect()
+    }
+
+    fn operations(events: &[Event]) -> Vec<(&str, Verdict)> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::RuneOperation(operation) => Some((operation.op.as_str(), operation.verdict.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn funding(vout: u32) -> OutPoint {
+        OutPoint::new(Txid::all_zeros(), vout)
+    }
+
+    #[test]
+    fn runes_are_etched_minted_and_burned() {
+        let rune = "RUNESTATETEST".parse::<Rune>().unwrap();
+        let etching = Runestone {
+            etching: Some(Etching {
+                rune: Some(rune),
+                spacers: Some(0b1),
+                premine: Some(1000),
+                terms: Some(Terms { amount: Some(100), cap: Some(2), height: (None, Some(12)), offset: (None, None) }),
+                ..Default::default()
+            }),
+            edicts: vec![Edict { id: RuneId::default(), amount: 400, output: 2 }],
+            ..Default::default()
+        };
+        let mut commits = Commits::default();
+        let etch = reveal(commits.commit(rune, 5), &etching, &[1, 2]);
+        let id = RuneId { block: 10, tx: 0 };
+        let mint = Runestone { mint: Some(id), ..Default::default() };
+        let duplicate = Runestone { etching: Some(Etching { rune: Some(rune), ..Default::default() }), ..Default::default() };
+        let duplicate = reveal(commits.commit(rune, 1), &duplicate, &[3]);
+
+        let mut indexer = RuneIndexer::new(Network::Regtest);
+        let events = index(&mut indexer, &commits, 10, vec![etch.clone(), tx(funding(1), &mint, &[3]), duplicate]);
+        assert_eq!(operations(&events), [("etch", Verdict::Valid), ("mint", Verdict::Valid), ("etch", Verdict::Invalid("rune already etched".into()))]);
+        assert_eq!(indexer.id(rune), Some(id));
+        assert_eq!(indexer.entry(id).unwrap().spaced_rune.to_string(), "R•UNESTATETEST");
+        // the edict takes 400 of the premine, the rest goes to the first output
+        assert_eq!(indexer.balances(&OutPoint::new(etch.txid(), 1)), [(id, 600)]);
+        assert_eq!(indexer.balances(&OutPoint::new(etch.txid(), 2)), [(id, 400)]);
+        let Event::RuneBalanceChange(change) = &events[1] else {
+            panic!("unexpected event {:?}", events[1]);
+        };
+        assert_eq!((change.change.as_str(), change.owner.clone()), ("600", sat_flow::owner(&script(1), Network::Regtest)));
+
+        // the cap is reached, then a cenotaph burns the runes of its input
+        let cenotaph = Runestone { edicts: vec![Edict { id, amount: 1, output: 9 }], ..Default::default() };
+        let events = index(
+            &mut indexer,
+            &commits,
+            11,
+            vec![tx(funding(3), &mint, &[3]), tx(funding(4), &mint, &[3]), tx(OutPoint::new(etch.txid(), 1), &cenotaph, &[4])],
+        );
+        assert_eq!(operations(&events), [("mint", Verdict::Valid), ("mint", Verdict::Invalid("mint cap reached".into())), ("burn", Verdict::Valid)]);
+        assert!(events.iter().any(|event| matches!(event, Event::RuneBalanceChange(change) if change.change == "-600")));
+        assert_eq!(indexer.balances(&OutPoint::new(etch.txid(), 1)), []);
+        let entry = indexer.entry(id).unwrap();
+        assert_eq!((entry.mints, entry.burned, entry.supply()), (2, 600, 1200));
+
+        let events = index(&mut indexer, &commits, 12, vec![tx(funding(5), &mint, &[3])]);
+        assert_eq!(operations(&events), [("mint", Verdict::Invalid("mint has ended".into()))]);
+        for event in &events {
+            assert_eq!(Event::from_parts(event.protocol(), event.payload().unwrap()), *event);
+        }
+    }
+    #[test]
+    fn etchings_need_a_confirmed_commitment_and_a_long_enough_name() {
+        let etching = |rune: Rune| Runestone {
+            etching: Some(Etching { rune: Some(rune), ..Default::default() }),
+            ..Default::default()
+        };
+        let (rune, short) = ("COMMITTEDRUNE".parse::<Rune>().unwrap(), "SHORT".parse::<Rune>().unwrap());
+        let mut commits = Commits::default();
+        let (recent, short_commit) = (commits.commit(rune, 96), commits.commit(short, 90));
+
+        let mut indexer = RuneIndexer::new(Network::Regtest);
+        let events = index(
+            &mut indexer,
+            &commits,
+            100,
+            vec![
+                tx(funding(0), &etching(rune), &[1]),
+                reveal(recent.clone(), &etching(rune), &[1]),
+                reveal(short_commit, &etching(short), &[1]),
+                tx(funding(1), &etching(Rune::reserved(1, 1)), &[1]),
+            ],
+        );
+        let uncommitted = Verdict::Invalid("no input reveals a commitment to the rune confirmed 6 blocks earlier".into());
+        assert_eq!(
+            operations(&events),
+            [
+                ("etch", uncommitted.clone()),
+                ("etch", uncommitted),
+                ("etch", Verdict::Invalid("rune name is too short at this height".into())),
+                ("etch", Verdict::Invalid("rune is reserved".into())),
+            ]
+        );
+        assert_eq!(indexer.id(rune), None);
+
+        // committed 6 blocks earlier, the block of the reveal included
+        index(&mut indexer, &commits, 101, vec![reveal(recent, &etching(rune), &[1])]);
+        assert_eq!(indexer.id(rune), Some(RuneId { block: 101, tx: 0 }));
+    }
+}
@@ -0,0 +1,164 @@
+use {
+    super::allocation::allocate,
+    anyhow::{bail, Result},
+    bitcoin::Transaction,
+    ordinals::{Artifact, Pile, RuneId, Runestone, SpacedRune},
+    serde::{Deserialize, Serialize},
+    std::collections::BTreeMap,
+};
+
+/// what a transaction would do with the runes of its inputs, without index.
+/// The rune etched by the transaction has no id until it is mined, it is `0:0`
+/// in the balances.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Simulation {
+    /// whether the runestone is a cenotaph, which burns every rune
+    pub cenotaph: bool,
+    /// why it is a cenotaph
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flaws: Vec<String>,
+    /// the rune etched, `None` for a reserved name given once mined
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etching: Option<SpacedRune>,
+    /// the runes of each output, none for OP_RETURN outputs
+    pub outputs: Vec<BTreeMap<RuneId, Pile>>,
+    /// burned by a cenotaph, sent to OP_RETURN outputs, or left without output
+    pub burned: BTreeMap<RuneId, Pile>,
+}
+
+/// simulate the allocation of the runes of a transaction: `inputs` holds the
+/// runes of its inputs, in order, and `mint` what its mint would mint if the
+/// rune is still mintable, which only an index knows. The premine of an
+/// etching, edicts, pointer and the default output apply as they do on chain.
+pub fn simulate(tx: &Transaction, inputs: &[BTreeMap<RuneId, Pile>], mint: Option<Pile>) -> Result<Simulation> {
+    if inputs.len() > tx.input.len() {
+        bail!("{} input balances for a transaction of {} inputs", inputs.len(), tx.input.len());
+    }
+    let artifact = Runestone::decipher(tx);
+
+    // the divisibility and symbol of each rune, as first given
+    let mut piles: BTreeMap<RuneId, Pile> = BTreeMap::new();
+    let mut unallocated: BTreeMap<RuneId, u128> = BTreeMap::new();
+    let mut add = |id: RuneId, pile: &Pile| -> Result<()> {
+        let amount = unallocated.entry(id).or_default();
+        *amount = amount.checked_add(pile.amount).ok_or_else(|| anyhow::anyhow!("the balance of {id} overflows"))?;
+        piles.entry(id).or_insert(Pile { amount: 0, ..*pile });
+        Ok(())
+    };
+    for (id, pile) in inputs.iter().flatten() {
+        add(*id, pile)?;
+    }
+    match (artifact.as_ref().and_then(Artifact::mint), mint) {
+        (Some(id), Some(pile)) => add(id, &pile)?,
+        (None, Some(_)) => bail!("the transaction doesn't mint"),
+        (_, None) => {}
+    }
+
+    let (etching, etched) = match &artifact {
+        Some(Artifact::Runestone(runestone)) => match runestone.etching {
+            Some(etching) => {
+                let pile = Pile {
+                    amount: etching.premine.unwrap_or_default(),
+                    divisibility: etching.divisibility.unwrap_or_default(),
+                    symbol: etching.symbol,
+                };
+                add(RuneId::default(), &pile)?;
+                let spaced = etching.rune.map(|rune| SpacedRune::new(rune, etching.spacers.unwrap_or_default()));
+                (spaced, Some(RuneId::default()))
+            }
+            None => (None, None),
+        },
+        Some(Artifact::Cenotaph(cenotaph)) => (cenotaph.etching.map(|rune| SpacedRune::new(rune, 0)), None),
+        None => (None, None),
+    };
+
+    let allocation = allocate(tx, artifact.as_ref(), etched, unallocated);
+    let to_piles = |balances: BTreeMap<RuneId, u128>| {
+        balances
+            .into_iter()
+            .map(|(id, amount)| (id, Pile { amount, ..piles[&id] }))
+            .collect::<BTreeMap<_, _>>()
+    };
+    Ok(Simulation {
+        cenotaph: matches!(artifact, Some(Artifact::Cenotaph(_))),
+        flaws: match &artifact {
+            Some(Artifact::Cenotaph(cenotaph)) => cenotaph.flaws().iter().map(ToString::to_string).collect(),
+            _ => Vec::new(),
+        },
+        etching,
+        outputs: allocation.outputs.into_iter().map(to_piles).collect(),
+        burned: to_piles(allocation.burned),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        bitcoin::{locktime::absolute::LockTime, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Witness},
+        ordinals::{Edict, Etching, Rune},
+    };
+
+    fn tx(runestone: &Runestone, inputs: usize, outputs: usize) -> Transaction {
+        let mut output = vec![TxOut { value: 0, script_pubkey: runestone.encipher() }];
+        output.extend((0..outputs).map(|_| TxOut { value: 546, script_pubkey: ScriptBuf::new() }));
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: (0..inputs)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint { vout: vout as u32, ..OutPoint::null() },
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output,
+        }
+    }
+
+    fn pile(amount: u128) -> Pile {
+        Pile { amount, divisibility: 2, symbol: Some('x') }
+    }
+
+    #[test]
+    fn transactions_are_simulated() {
+        let id = RuneId { block: 840000, tx: 1 };
+        let inputs = [BTreeMap::from([(id, pile(700))]), BTreeMap::from([(id, pile(300))])];
+
+        // split all with an amount, the pointer takes the rest, and the mint
+        let runestone = Runestone {
+            edicts: vec![Edict { id, amount: 100, output: 3 }],
+            mint: Some(id),
+            pointer: Some(1),
+            ..Default::default()
+        };
+        let simulation = simulate(&tx(&runestone, 2, 2), &inputs, Some(pile(50))).unwrap();
+        assert!(!simulation.cenotaph);
+        assert_eq!(simulation.outputs, [BTreeMap::new(), BTreeMap::from([(id, pile(950))]), BTreeMap::from([(id, pile(100))])]);
+        assert!(simulation.burned.is_empty());
+        assert!(simulate(&tx(&Runestone::default(), 2, 2), &inputs, Some(pile(50))).is_err());
+        assert!(simulate(&tx(&runestone, 1, 2), &inputs, None).is_err());
+
+        // the premine of an etching goes by the edicts of `0:0`
+        let rune = "SIMULATED".parse::<Rune>().unwrap();
+        let runestone = Runestone {
+            etching: Some(Etching { rune: Some(rune), premine: Some(10), divisibility: Some(1), ..Default::default() }),
+            edicts: vec![Edict { id: RuneId::default(), amount: 0, output: 0 }],
+            ..Default::default()
+        };
+        let simulation = simulate(&tx(&runestone, 2, 1), &inputs, None).unwrap();
+        assert_eq!(simulation.etching, Some(SpacedRune::new(rune, 0)));
+        assert_eq!(simulation.burned, BTreeMap::from([(RuneId::default(), Pile { amount: 10, divisibility: 1, symbol: None })]));
+        assert_eq!(simulation.outputs[1], BTreeMap::from([(id, pile(1000))]));
+
+        // a cenotaph burns the inputs
+        let runestone = Runestone { edicts: vec![Edict { id, amount: 1, output: 5 }], ..Default::default() };
+        let simulation = simulate(&tx(&runestone, 2, 1), &inputs, None).unwrap();
+        assert_eq!((simulation.cenotaph, simulation.flaws.clone()), (true, vec!["edict output greater than transaction output count".to_string()]));
+        assert_eq!(simulation.burned, BTreeMap::from([(id, pile(1000))]));
+        let json = serde_json::to_string(&simulation).unwrap();
+        assert!(json.contains(r#""burned":{"840000:1":{"amount":1000,"divisibility":2,"symbol":"x"}}"#), "{json}");
+        assert_eq!(serde_json::from_str::<Simulation>(&json).unwrap(), simulation);
+    }
+}
@@ -0,0 +1,143 @@
+use {
+    crate::source::BlockSource,
+    anyhow::{anyhow, Context, Result},
+    bitcoin::{Address, Network, OutPoint, Script, Transaction},
+    ordinals::SatPoint,
+    std::collections::HashMap,
+};
+
+/// the values of the outputs spent by transactions, which the sats of their
+/// inputs are numbered from: the outputs of the transactions added so far, else
+/// looked up in the source
+pub struct Prevouts<'a> {
+    source: Option<&'a dyn BlockSource>,
+    values: HashMap<OutPoint, u64>,
+}
+
+impl<'a> Prevouts<'a> {
+    /// without a source, only the outputs of the added transactions are known
+    pub fn new(source: Option<&'a dyn BlockSource>) -> Self {
+        Self {
+            source,
+            values: HashMap::new(),
+        }
+    }
+
+    /// remember the outputs of a transaction, spent by the next ones
+    pub fn add(&mut self, tx: &Transaction) {
+        let txid = tx.txid();
+        for (vout, output) in tx.output.iter().enumerate() {
+            self.values.insert(OutPoint::new(txid, vout as u32), output.value);
+        }
+    }
+
+    /// where the values of the outputs not added are looked up
+    pub fn source(&self) -> Option<&'a dyn BlockSource> {
+        self.source
+    }
+
+    /// forget the added outputs, e.g. at the end of a block
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    pub fn value(&mut self, outpoint: &OutPoint) -> Result<u64> {
+        if let Some(value) = self.values.get(outpoint) {
+            return Ok(*value);
+        }
+        let source = self.source.ok_or_else(|| anyhow!("the value of {outpoint} is unknown without a source"))?;
+        let tx = source
+            .raw_tx(&outpoint.txid)
+            .with_context(|| format!("failed to look up the output {outpoint}"))?;
+        let value = tx
+            .output
+            .get(outpoint.vout as usize)
+            .ok_or_else(|| anyhow!("no output {outpoint}"))?
+            .value;
+        Ok(value)
+    }
+
+    /// the offset of the first sat of an input among the sats of the transaction,
+    /// the sum of the values of the inputs before it
+    pub fn input_offset(&mut self, tx: &Transaction, input: usize) -> Result<u64> {
+        let mut offset = 0;
+        for input in &tx.input[..input] {
+            offset += self.value(&input.previous_output)?;
+        }
+        Ok(offset)
+    }
+}
+
+/// where the sat at `offset` among the sats of the inputs of a transaction goes,
+/// first in first out: the output it lands in, or `None` if it is paid as fee
+pub fn locate(tx: &Transaction, offset: u64) -> Option<SatPoint> {
+    let txid = tx.txid();
+    let mut start = 0;
+    for (vout, output) in tx.output.iter().enumerate() {
+        if offset < start + output.value {
+            return Some(SatPoint {
+                outpoint: OutPoint::new(txid, vout as u32),
+                offset: offset - start,
+            });
+        }
+        start += output.value;
+    }
+    None
+}
+
+/// the owner of an output: its address, or the hex of its script if it has none
+pub fn owner(script: &Script, network: Network) -> String {
+    match Address::from_script(script, network) {
+        Ok(address) => address.to_string(),
+        Err(_) => hex::encode(script.as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        bitcoin::{locktime::absolute::LockTime, ScriptBuf, Sequence, TxIn, TxOut, Witness},
+    };
+
+    fn tx(inputs: &[OutPoint], outputs: &[u64]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|previous_output| TxIn {
+                    previous_output: *previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|value| TxOut {
+                    value: *value,
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn sats_flow_first_in_first_out() {
+        let funding = tx(&[], &[1000, 500]);
+        let spend = tx(&[OutPoint::new(funding.txid(), 1), OutPoint::new(funding.txid(), 0)], &[600, 800]);
+        let mut prevouts = Prevouts::new(None);
+        prevouts.add(&funding);
+
+        assert_eq!(prevouts.input_offset(&spend, 0).unwrap(), 0);
+        assert_eq!(prevouts.input_offset(&spend, 1).unwrap(), 500);
+        assert_eq!(locate(&spend, 500), Some(SatPoint { outpoint: OutPoint::new(spend.txid(), 0), offset: 500 }));
+        assert_eq!(locate(&spend, 600), Some(SatPoint { outpoint: OutPoint::new(spend.txid(), 1), offset: 0 }));
+        // the last 100 sats are the fee
+        assert_eq!(locate(&spend, 1400), None);
+
+        prevouts.clear();
+        assert!(prevouts.input_offset(&spend, 1).is_err());
+    }
+}
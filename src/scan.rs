@@ -1,884 +1,389 @@
 use {
-    crate::ord::{self, Inscription, InscriptionId, ParsedEnvelope},
-    crate::runealpha::{self, Runestone as Runealpha},
-    ordinals::Runestone,
-    anyhow::{Error, Ok, Result},
-    base64,
-    bitcoin::{
-        block,
-        blockdata::{opcodes::all::OP_CHECKMULTISIG, script::Instruction},
-        Transaction, Txid,
+    crate::{
+        blk::BlkIndex,
+        brc20::{self, Brc20Ledger},
+        cursor::Cursor,
+        decoder::{Event, Registry, ALL_PROTOCOLS},
+        notify::BlockNotifier,
+        pipeline::{self, PipelineOptions},
+        reorg::BlockHistory,
+        runes::RuneIndexer,
+        sat_flow::Prevouts,
+        transfer::{self, InscriptionTracker},
+        sink::EventSink,
+        source::BlockSource,
     },
-    bitcoincore_rpc::{Client, RpcApi},
-    ciborium,
-    crypto::{rc4::Rc4, symmetriccipher::SynchronousStreamCipher},
-    serde::{Deserialize, Serialize},
-    serde_json::{self, Value},
+    anyhow::{anyhow, bail, Context, Ok, Result},
+    bitcoin::{consensus::deserialize, Block, Network, Transaction, Txid},
+    ordinals::Rune,
     std::{
-        iter::repeat,
-        str::{self, FromStr},
-        fs::File,
-        io::{BufWriter, Write},
-        fs::OpenOptions,
-        thread,
-        time::Duration,
+        fs,
+        io::Read,
+        path::Path,
+        str::FromStr,
     },
-    thiserror,
-    sqlx::postgres::PgPoolOptions,
-    futures::executor::block_on,
     chrono::Local,
 };
 
-// note: the ord include brc20,brc420,stamp... so the we should iter those protocol first, if those protocol return value, then skip to next txid
-const SUPPORT_PROTOCOLS: [&str; 14] = [
-        "atom-arc20", "atom-relam", "atom-nft",  "atom-others",
-        "stamp-src20",
-        "rune-stone", "rune-alpha",
-        "ord-brc20", "ord-brc100", "ord-brc420", "ord-bitmap", "ord-sns", "ord-tap", "ord"
-    ];
-
-// type Result<T = (), E = Error> = std::result::Result<T, E>;
-
-#[derive(Serialize, Eq, PartialEq, Deserialize, Debug)]
-pub struct CompactOutput {
-    pub inscriptions: Vec<CompactInscription>,
-}
-
-#[derive(Serialize, Eq, PartialEq, Deserialize, Debug)]
-pub struct RawOutput {
-    pub inscriptions: Vec<ParsedEnvelope>,
-}
-
-#[derive(Serialize, Eq, PartialEq, Deserialize, Debug)]
-pub struct CompactInscription {
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub body: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub content_encoding: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub content_type: Option<String>,
-    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
-    pub duplicate_field: bool,
-    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
-    pub incomplete_field: bool,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub metadata: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub metaprotocol: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub parent: Option<InscriptionId>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub pointer: Option<u64>,
-    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
-    pub unrecognized_even_field: bool,
-}
-
-impl TryFrom<Inscription> for CompactInscription {
-    type Error = Error;
-
-    fn try_from(inscription: Inscription) -> Result<Self> {
-        Ok(Self {
-            content_encoding: inscription
-                .content_encoding()
-                .map(|header_value| header_value.to_str().map(str::to_string))
-                .transpose()?,
-            content_type: inscription.content_type().map(str::to_string),
-            metaprotocol: inscription.metaprotocol().map(str::to_string),
-            parent: inscription.parent(),
-            pointer: inscription.pointer(),
-            body: inscription.body.map(hex::encode),
-            duplicate_field: inscription.duplicate_field,
-            incomplete_field: inscription.incomplete_field,
-            metadata: inscription.metadata.map(hex::encode),
-            unrecognized_even_field: inscription.unrecognized_even_field,
-        })
-    }
-}
-
-fn parse_json(json_str: &str) -> Option<Value> {
-    match serde_json::from_str(json_str) {
-        std::result::Result::Ok(value) => Some(value),
-        Err(_) => None,
-    }
-}
-
-#[derive(Debug, thiserror::Error)]
-enum BRC20Error{
-    #[error("ContentTypeNull")]
-    ContentTypeNull,
-    #[error("ContentTypeNotValid")]
-    ContentTypeNotValid,
-    #[error("ContentBodyNull")]
-    ContentBodyNull,
-    #[error("ContentBodyNotJson")]
-    ContentBodyNotJson,
-}
-
-fn decode_ord(rawtx:Transaction)->Result<serde_json::Value>{
-    // let compact = true;
-    let parsed_inscriptions = ord::ParsedEnvelope::from_transaction(&rawtx, b"ord");
-    let result = Box::new(CompactOutput {
-        inscriptions: parsed_inscriptions
-            .clone()
-            .into_iter()
-            .map(|inscription| inscription.payload.try_into())
-            .collect::<Result<Vec<CompactInscription>>>()
-            .unwrap(),
-    });
-    if !result.inscriptions.is_empty(){
-        // println!("\n{:?}: {:?}", txid, result);
-        return Ok(serde_json::to_value(result)?);
-    }else{
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-}
-
-fn decode_ord_brc20(inscription: Inscription) ->Result<serde_json::Value> {
-    let content_type = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    if content_type != "text/plain"
-        && content_type != "text/plain;charset=utf-8"
-        && content_type != "text/plain;charset=UTF-8"
-        && content_type != "application/json"
-        && !content_type.starts_with("text/plain;")
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-
-    let content_body = std::str::from_utf8(inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?)?;
-    let value = parse_json(content_body).ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    // TODO: check if this is brc20
-    // Check if the key exists and if it is equal to "brc-20"
-    let protocol = value.get("p").ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    if protocol == "brc-20" {
-        // let brc20_event = serde_json::to_string(&value).map_err(|err| Error::from(err))?;
-        return Ok(value);
-    } else {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
+/// extract events of a protocol (a decoder name or `all`) from transaction id
+pub fn decode_tx(source: &dyn BlockSource, registry: &Registry, txid: &Txid, protocol: &str) -> Result<Vec<Event>> {
+    let rawtx = source.raw_tx(txid)?;
+    Ok(registry.decode(&rawtx, protocol))
 }
 
-fn decode_ord_brc100(inscription: Inscription) ->Result<serde_json::Value> {
-    let content_type = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    if content_type != "text/plain"
-        && content_type != "text/plain;charset=utf-8"
-        && content_type != "text/plain;charset=UTF-8"
-        && content_type != "application/json"
-        && !content_type.starts_with("text/plain;")
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
 
-    let content_body = std::str::from_utf8(inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?)?;
-    let value = parse_json(content_body).ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    // TODO: check if this is brc20
-    // Check if the key exists and if it is equal to "brc-20"
-    let protocol = value.get("p").ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    if protocol == "BRC-100" || protocol == "BRC-101" || protocol == "BRC-102" {
-        // let brc20_event = serde_json::to_string(&value).map_err(|err| Error::from(err))?;
-        return Ok(value);
+fn split_string(input_string: &str, delimiter: &str) -> Vec<String> {
+    if input_string.contains(delimiter) {
+        input_string.split(delimiter).map(|s| s.to_string()).collect()
     } else {
-        return Err(BRC20Error::ContentTypeNotValid.into());
+        vec![input_string.to_string()]
     }
 }
 
-fn decode_ord_brc420(inscription: Inscription) ->Result<serde_json::Value> {
-    let content_type = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    if content_type != "text/plain"
-        && content_type != "text/plain;charset=utf-8"
-        && content_type != "text/plain;charset=UTF-8"
-        && content_type != "application/json"
-        && content_type != "application/json"
-        && content_type != "text/html;charset=utf-8"
-        && !content_type.starts_with("text/plain;")
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-
-    let content_body = std::str::from_utf8(inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?)?;
-    if content_body.starts_with("/content/"){
-        return Ok(serde_json::json!({"mint":content_body.to_string()}));
-    }
-    let value = parse_json(content_body).ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    // deploy new collection
-    let protocol = value.get("p").ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    if protocol == "brc-420" {
-        // let brc20_event = serde_json::to_string(&value).map_err(|err| Error::from(err))?;
-        return Ok(value);
-    } else {
-        return Err(BRC20Error::ContentTypeNotValid.into());
+pub fn run_txs(source: &dyn BlockSource, registry: &Registry, txids: &str, protocol: &str, sink: &mut dyn EventSink) -> Result<()> {
+    let txs = split_string(txids, ",");
+    for tx in txs{
+        let txid = Txid::from_str(&tx).with_context(|| format!("invalid txid {tx:?}"))?;
+        let rawtx = source.raw_tx(&txid)?;
+        let events = registry.decode_events(&rawtx, protocol);
+        sink.write_block(None, &events, None)?;
     }
+    Ok(())
 }
 
-fn decode_ord_sns(inscription: Inscription) ->Result<serde_json::Value> {
-    let content_type = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    if content_type != "text/plain"
-        && content_type != "text/plain;charset=utf-8"
-        && content_type != "text/plain;charset=UTF-8"
-        && content_type != "application/json"
-        && !content_type.starts_with("text/plain;")
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-
-    let content_body = std::str::from_utf8(inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?)?;
-    let value = parse_json(content_body).ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    // TODO: check if this is brc20
-    // Check if the key exists and if it is equal to "brc-20"
-    let protocol = value.get("p").ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    if protocol == "sns" {
-        // let brc20_event = serde_json::to_string(&value).map_err(|err| Error::from(err))?;
-        return Ok(value);
+/// the bytes of a `--raw-tx` or `--raw-block` value: a file path, `@` followed by
+/// a file path, `-` for stdin, or else hex. Files and stdin hold hex or raw bytes.
+pub fn read_raw(value: &str) -> Result<Vec<u8>> {
+    let content = if value == "-" {
+        let mut content = Vec::new();
+        std::io::stdin().read_to_end(&mut content)?;
+        content
+    } else if let Some(path) = value.strip_prefix('@') {
+        fs::read(path).with_context(|| format!("failed to read {path}"))?
+    } else if Path::new(value).is_file() {
+        fs::read(value).with_context(|| format!("failed to read {value}"))?
     } else {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-}
-
-// https://github.com/BennyTheDev/tap-protocol-specs
-fn decode_ord_tap(inscription: Inscription) ->Result<serde_json::Value> {
-    let content_type = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    if content_type != "text/plain"
-        && content_type != "text/plain;charset=utf-8"
-        && content_type != "text/plain;charset=UTF-8"
-        && content_type != "application/json"
-        && !content_type.starts_with("text/plain;")
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-
-    let content_body = std::str::from_utf8(inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?)?;
-    let value = parse_json(content_body).ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    // TODO: check if this is brc20
-    // Check if the key exists and if it is equal to "brc-20"
-    let protocol = value.get("p").ok_or_else(|| BRC20Error::ContentBodyNotJson)?;
-    if protocol == "tap" {
-        // let brc20_event = serde_json::to_string(&value).map_err(|err| Error::from(err))?;
-        return Ok(value);
+        return hex::decode(value.trim()).with_context(|| format!("{value:?} is neither a file nor hex"));
+    };
+    let trimmed = content.trim_ascii();
+    if !trimmed.is_empty() && trimmed.iter().all(u8::is_ascii_hexdigit) {
+        Ok(hex::decode(trimmed)?)
     } else {
-        return Err(BRC20Error::ContentTypeNotValid.into());
+        Ok(content)
     }
 }
 
-//TODO nft transfer is bind with ordinals number, not the nft self?
-fn decode_ord_bitmap(inscription: Inscription) ->Result<serde_json::Value> {
-    let content_type = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    if content_type != "text/plain"
-        && content_type != "text/plain;charset=utf-8"
-        && content_type != "text/plain;charset=UTF-8"
-        && content_type != "application/json"
-        && !content_type.starts_with("text/plain;")
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-
-    let content_body = std::str::from_utf8(inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?)?;
-    if content_body.ends_with(".bitmap") {
-        return Ok(serde_json::json!({"mint":content_body.to_string()}));
-    } else {
-        return Err(BRC20Error::ContentTypeNotValid.into());
+/// decode serialized transactions, without rpc
+pub fn run_raw_txs(values: &[String], registry: &Registry, protocol: &str, sink: &mut dyn EventSink) -> Result<()> {
+    for value in values {
+        let tx: Transaction = deserialize(&read_raw(value)?).with_context(|| format!("{value:?} is not a transaction"))?;
+        let events = registry.decode_events(&tx, protocol);
+        sink.write_block(None, &events, None)?;
     }
+    Ok(())
 }
 
-fn decode_atom_arc20(inscription: Inscription)->Result<serde_json::Value>{
-    let op = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    // println!("operation: {:?}", op);
-    if op != "dft" //TODO: add other op
-        && op != "ft"
-        && op != "dmt"
-        && op != "y"
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
+/// decode serialized blocks, without rpc. Their height is the one of their coinbase (BIP34).
+pub fn run_raw_blocks(values: &[String], registry: &Registry, protocol: &str, sink: &mut dyn EventSink) -> Result<()> {
+    for value in values {
+        let block: Block = deserialize(&read_raw(value)?).with_context(|| format!("{value:?} is not a block"))?;
+        let height = block
+            .bip34_block_height()
+            .map_err(|err| anyhow!("no height in the coinbase of block {}: {err}", block.block_hash()))?;
+        let events = registry.decode_block(&block, height, protocol);
+        sink.write_block(Some(height), &events, None)?;
     }
-    let body = inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?;
-    let body_string = body.to_vec();
-    // println!("{:?}", body_string);
-    let cbor_value: ciborium::Value = ciborium::de::from_reader(&body_string[..])?; //TODO: get the diagnostic notation result
-    let json_value = cbor_to_json(cbor_value);
-    // let temp = payload_value.as_map().unwrap();
-    // println!("\n>>> atomicals inscription decoded: {:?}", temp);
-    Ok(json_value)
+    Ok(())
 }
 
-fn cbor_into_string(cbor: ciborium::Value) -> Option<String> {
-    match cbor {
-        ciborium::Value::Text(string) => Some(string),
-        _ => None,
-    }
+/// the heights of a `--block` value, single (`824958`), range (`820000:820010`) or list (`820000,820005`)
+fn parse_heights(block_number: &str) -> Result<Vec<u64>> {
+    let blocks = if block_number.contains(',') {
+        split_string(block_number, ",").iter().map(|s| s.parse::<u64>()).collect::<Result<_, _>>()?
+    } else if block_number.contains(':') {
+        let range = split_string(block_number, ":");
+        let start: u64 = range[0].parse()?;
+        let stop: u64 = range[1].parse()?;
+        (start..=stop).collect()
+    } else {
+        vec![block_number.parse::<u64>()?]
+    };
+    Ok(blocks)
 }
 
-fn cbor_to_json(cbor: ciborium::Value) -> serde_json::Value {
-    match cbor {
-        ciborium::Value::Null => serde_json::Value::Null,
-        ciborium::Value::Bool(boolean) => serde_json::Value::Bool(boolean),
-        ciborium::Value::Text(string) => serde_json::Value::String(string),
-        ciborium::Value::Integer(int) => serde_json::Value::Number({
-            let int: i128 = int.into();
-            if let std::result::Result::Ok(int) = u64::try_from(int) {
-                serde_json::Number::from(int)
-            } else if let std::result::Result::Ok(int) = i64::try_from(int) {
-                serde_json::Number::from(int)
-            } else {
-                serde_json::Number::from_f64(int as f64).unwrap()
-            }
-        }),
-        ciborium::Value::Float(float) => serde_json::Value::Number(serde_json::Number::from_f64(float).unwrap()),
-        ciborium::Value::Array(vec) => serde_json::Value::Array(vec.into_iter().map(cbor_to_json).collect()),
-        ciborium::Value::Map(map) => serde_json::Value::Object(map.into_iter().map(|(k, v)| (cbor_into_string(k).unwrap(), cbor_to_json(v))).collect()),
-        ciborium::Value::Bytes(byte) => serde_json::Value::String(base64::encode(byte)),
-        ciborium::Value::Tag(_, _) => unimplemented!(),
-        _ => unimplemented!(),
-    }
+/// one block fetcher of the pipeline per source, e.g. per rpc connection
+fn source_fetchers(connect: &dyn Fn() -> Result<Box<dyn BlockSource>>, count: usize) -> Result<Vec<impl FnMut(u64) -> Result<Block> + Send>> {
+    (0..count.max(1))
+        .map(|_| {
+            let source = connect()?;
+            Ok(move |height| source.block_at(height))
+        })
+        .collect()
 }
 
-// TODO:  atom should be decoded in one place, and let the application decide which one to use
-fn decode_atom_relam(inscription: Inscription)->Result<serde_json::Value>{
-    let op = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    // println!("operation: {:?}", op);
-    if op != "nft" //TODO: add other op
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-    let body = inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?;
-    let body_string = body.to_vec();
-    // println!("{:?}", body_string);
-    let payload_value: ciborium::Value = ciborium::de::from_reader(&body_string[..])?; //TODO: get the diagnostic notation result
-    // println!("\n>>> atomicals ciborium::Value: {:?}", payload_value);
-    let jsons = cbor_to_json(payload_value);
-    // check if request_realm and request_subrealm in keys
-    let request_realm = &jsons["args"]["request_realm"];
-    let request_subrealm = &jsons["args"]["request_subrealm"];
-    if request_realm.is_null() && request_subrealm.is_null() {
-        Err(BRC20Error::ContentTypeNotValid.into())
-    }
-    else{
-        Ok(jsons)
-    }
-    
-    // println!("\n>>> atomicals inscription decoded: {:?}", jsons.to_string());
-    // let temp = payload_value.as_map().unwrap();
-    // println!("\n>>> atomicals inscription decoded: {:?}", temp);
-    
+/// write the decoded blocks without cursor, stopping at the first error of the outputs
+fn write_blocks<F>(heights: &[u64], fetchers: &mut [F], registry: &Registry, protocol: &str, options: &PipelineOptions, sink: &mut dyn EventSink) -> Result<()>
+where
+    F: FnMut(u64) -> Result<Block> + Send,
+{
+    pipeline::run(heights, fetchers, registry, protocol, options, |block| {
+        sink.write_block(Some(block.height), &block.events, None)?;
+        Ok(true)
+    })
 }
 
-
-fn decode_atom_nft(inscription: Inscription)->Result<serde_json::Value>{
-    let op = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    if op != "nft" //TODO: add other op
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-    let body = inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?;
-    let body_string = body.to_vec();
-    // println!("{:?}", body_string);
-    let payload_value: ciborium::Value = ciborium::de::from_reader(&body_string[..])?; //TODO: get the diagnostic notation result
-    // println!("\n>>> atomicals ciborium::Value: {:?}", payload_value);
-    let jsons = cbor_to_json(payload_value);
-    // check if request_realm and request_subrealm in keys
-    let request_realm = &jsons["args"]["request_realm"];
-    let request_subrealm = &jsons["args"]["request_subrealm"];
-    if !request_realm.is_null() || !request_subrealm.is_null() {
-        Err(BRC20Error::ContentTypeNotValid.into())
-    }
-    else{
-        Ok(jsons)
-    }
-    
+/// decode the blocks of a `--block` value through the pipeline, the transactions
+/// are decoded from the blocks themselves, without any other rpc call
+pub fn run_blocks(connect: &dyn Fn() -> Result<Box<dyn BlockSource>>, registry: &Registry, block_number: &str, protocol: &str, options: &PipelineOptions, sink: &mut dyn EventSink) -> Result<()> {
+    let heights = parse_heights(block_number).with_context(|| format!("invalid block {block_number:?}"))?;
+    write_blocks(&heights, &mut source_fetchers(connect, options.fetchers)?, registry, protocol, options, sink)
 }
 
-fn decode_atom_others(inscription: Inscription)->Result<serde_json::Value>{
-    let op = inscription.content_type().ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    if op != "mod"
-       && op != "evt"
-       && op != "dat"
-       && op != "sl"
-       && op != "x"
-    {
-        return Err(BRC20Error::ContentTypeNotValid.into());
-    }
-    let body = inscription.body().ok_or_else(|| BRC20Error::ContentBodyNull)?;
-    let body_string = body.to_vec();
-    // println!("{:?}", body_string);
-    let payload_value: ciborium::Value = ciborium::de::from_reader(&body_string[..])?; //TODO: get the diagnostic notation result
-    // println!("\n>>> atomicals ciborium::Value: {:?}", payload_value);
-    let jsons = cbor_to_json(payload_value);
-    // println!("{:?}", jsons);
-    // check if request_realm and request_subrealm in keys
-    let request_realm = &jsons["args"]["request_realm"];
-    let request_subrealm = &jsons["args"]["request_subrealm"];
-    if !request_realm.is_null() || !request_subrealm.is_null() {
-        Err(BRC20Error::ContentTypeNotValid.into())
-    }
-    else{
-        Ok(jsons)
-    }
+/// decode the blocks of a `--block` value read from the `blk*.dat` files of a node,
+/// `options.fetchers` threads reading the files
+pub fn run_blk_files(index: &BlkIndex, registry: &Registry, block_number: &str, protocol: &str, options: &PipelineOptions, sink: &mut dyn EventSink) -> Result<()> {
+    let heights = parse_heights(block_number).with_context(|| format!("invalid block {block_number:?}"))?;
+    let mut fetchers: Vec<_> = (0..options.fetchers.max(1)).map(|_| |height| index.block(height)).collect();
+    write_blocks(&heights, &mut fetchers, registry, protocol, options, sink)
 }
 
-
-// 1. find all the multisig ouput in ouputs
-// 2. take the first two ouput  
-pub fn decode_stamp_src20(rawtx: &Transaction) ->Result<serde_json::Value> {
-    let tx_input0 = rawtx.input[0].previous_output.txid.to_string();
-    let signing_key = hex::decode(&tx_input0).unwrap();
-    // println!("signing_key: {:#?}", key);
-
-
-    let mut payload = Vec::new();
-
-    let mut pubkeys_idx:u8 = 1;
-    for (i, output) in rawtx.output.iter().enumerate(){
-        // check if output is multisig
-        if pubkeys_idx>2 { continue; }
-        let encoded_script = &output.script_pubkey;
-
-        let mut instructions_check  =  encoded_script.instructions().peekable();
-        let is_multisig = instructions_check.any(|instruction| {
-            matches!(&instruction, std::result::Result::Ok(Instruction::Op(OP_CHECKMULTISIG)))
-        });
-
-        if  !is_multisig{continue;}
-
-        let mut instructions  =  encoded_script.instructions().peekable();
-        let mut insctrction_idx:u8 = 1;
-        while let Some(insctrction) = instructions.next().transpose()?{
-            if insctrction_idx > 2 {break;}
-            // TODO: check whether opcode is MULTISIG, // Take the first two pubkeys from all present multisig scripts
-            // if let Some(pushed_op) = &insctrction.opcode(){
-            // }
-            if let Some(pushed_bytes) = insctrction.push_bytes() {
-                // payload.push(pushed_bytes);
-                let value = pushed_bytes.as_bytes().to_vec();
-                payload.extend_from_slice(&value[1..value.len()-1]);
-                insctrction_idx += 1;
-            }
-            // println!("{:?}", insctrction);
+/// the cursor if its block is still in the chain, else the block the chain
+/// forked from, for blocks reorganized out of the chain while inscan was stopped.
+/// Fails when even the genesis block differs, an output of another chain.
+fn in_chain(source: &dyn BlockSource, mut cursor: Cursor) -> Result<Cursor> {
+    while source.block_hash(cursor.height).ok() != Some(cursor.block_hash) {
+        if cursor.height == 0 {
+            bail!("the output was indexed from genesis block {}, which isn't the one of the node, another network?", cursor.block_hash);
         }
-        pubkeys_idx +=1;
-    }
-    if payload.is_empty(){
-        return Err(BRC20Error::ContentBodyNull.into());
-    }
-
-    let mut rc4 = Rc4::new(&signing_key);
-    let mut decode_result: Vec<u8> = repeat(0).take(payload.len()).collect();
-    rc4.process(&payload, &mut decode_result);
-
-    // strip and decode hex to string
-    let len = &decode_result[0..2];
-    let protocol = str::from_utf8(&decode_result[2..2+6])?;
-    if protocol == "stamp:" {
-        let result = str::from_utf8(&decode_result[2+6..])?;
-        return Ok(serde_json::from_str(result.trim_end_matches('\0'))?) //
-    }
-    else {
-        Err(BRC20Error::ContentBodyNull.into())
-    }
-    // println!("\nlens: {:?}, protocol: {:?}", len, );
-    // println!(">>> stamps(src20) inscription decoded: {:#?}", str::from_utf8(&decode_result[2+6..]).unwrap());
-
-    // Ok("aaa".to_string())
-}
-
-fn decode_rune_stone(rawtx: &Transaction)->Result<serde_json::Value>{
-    let runestone = Runestone::decipher(&rawtx);
-    if runestone.is_some(){
-        return Ok(serde_json::to_value(runestone)?)
-    }
-    else{
-        Err(BRC20Error::ContentBodyNull.into())
+        let header = source.block_header(&cursor.block_hash)?;
+        cursor = Cursor {
+            height: cursor.height - 1,
+            block_hash: header.prev_blockhash,
+        };
     }
+    Ok(cursor)
 }
 
-fn decode_rune_alpha(rawtx: &Transaction)->Result<serde_json::Value>{
-    // let rune = Runealpha::from_transaction(rawtx).ok_or("name");
-    let rune = Runealpha::from_transaction(rawtx).ok_or_else(|| BRC20Error::ContentTypeNull)?;
-    // let json_string = serde_json::to_string(&rune).expect("Serialization failed");
-    let json_value = serde_json::to_value(&rune)?;
-    // println!("rune alpha: {:?}",json_value);
-    Ok(json_value)
-}
-
-
-/// extract assets by protocol name from transaction id, should return Option<Vec<Value>>
-pub fn decode_tx(rpc: &Client, txid: &Txid, protocol: &str) -> Vec<serde_json::Value>{
-    let rawtx = rpc.get_raw_transaction(&txid, None).unwrap();
-    let mut events: Vec<serde_json::Value> = Vec::new();
-    
-    match protocol.to_lowercase().as_str() {
-        "ord" => {
-            let event = match decode_ord(rawtx) {
-                std::result::Result::Ok(event) => {
-                    // println!("{:?}: {:?}", txid, event);
-                    events.push(serde_json::json!({"protocol":"ord", "payload":event}));
-                },
-                Err(err) =>{},
-            } ;
-        }
-
-        "ord-bitmap" => {
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"ord");
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let event = match decode_ord_bitmap(inscription) {
-                    std::result::Result::Ok(event) => {
-                        events.push(serde_json::json!({"protocol":"ord-bitmap", "payload":event}));
-                        // println!("{:?}: {:?}", txid, event);
-                    },
-                    Err(err) =>{},
-                } ;
-            }
-        }
-
-        "ord-brc20" => {
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"ord");
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let event = match decode_ord_brc20(inscription) {
-                    std::result::Result::Ok(event) => {
-                        // println!("{:?}: {:?}", txid, event);
-                        events.push(serde_json::json!({"protocol":"ord-brc20", "payload":event}));
-
-                        // write_jsonl(event, "temp.jsonl");
-                    },
-                    Err(err) =>{},
-                } ;
-            }
-        }
-
-        "ord-brc100" => {
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"ord");
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let event = match decode_ord_brc100(inscription) {
-                    std::result::Result::Ok(event) => {
-                        // println!("{:?}: {:?}", txid, event);
-                        events.push(serde_json::json!({"protocol":"ord-brc100", "payload":event}));
-                    },
-                    Err(err) =>{},
-                } ;
-            }
-        }
-
-        "ord-brc420" => {
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"ord");
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let event = match decode_ord_brc420(inscription) {
-                    std::result::Result::Ok(event) => 
-                    {
-                        events.push(serde_json::json!({"protocol":"ord-brc420", "payload":event}));
-                        // println!("{:?}: {:?}", txid, event);
-                    },
-                    Err(err) =>{},
-                } ;
-            }
-        }
-
-        "ord-sns" =>{
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"ord");
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let event = match decode_ord_sns(inscription) {
-                    std::result::Result::Ok(event) => {
-                        events.push(serde_json::json!({"protocol":"ord-sns", "payload":event}));
-                        // println!("{:?}: {:?}", txid, event)
-                    },
-                    Err(err) =>{},
-                } ;
-            }
+/// index the blocks from `start_height` to the tip and keep following it. Without
+/// a start height, indexing resumes after the last block written to the output.
+/// At the tip, `notifier` tells when to look for the next block.
+pub fn index_realtime(connect: &dyn Fn() -> Result<Box<dyn BlockSource>>, registry: &Registry, start_height: Option<u64>, protocol: &str, options: &PipelineOptions, notifier: &BlockNotifier, sink: &mut dyn EventSink) -> Result<()> {
+    let source = connect()?;
+    let cursor = match start_height {
+        Some(0) => None,
+        Some(start_height) => Some(Cursor {
+            height: start_height - 1,
+            block_hash: source.block_hash(start_height - 1)?,
+        }),
+        None => {
+            let cursor = sink.load_cursor(protocol)?
+                .ok_or_else(|| anyhow!("no {protocol:?} indexing to resume, a start height is required"))?;
+            Some(in_chain(source.as_ref(), cursor)?)
         }
+    };
 
-        "ord-tap" => {
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"ord");
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let event = match decode_ord_tap(inscription) {
-                    std::result::Result::Ok(event) => {
-                        events.push(serde_json::json!({"protocol":"ord-tap", "payload":event}));
-                        // println!("{:?}: {:?}", txid, event)
-                    },
-                    Err(err) =>{},
-                };
-            }
-        }
+    // the hashes of the indexed blocks, to notice when they are reorganized out of the chain
+    let mut history = BlockHistory::default();
+    let mut current_height = 0;
+    if let Some(cursor) = cursor {
+        // events of the blocks above the cursor are from an interrupted run or were already indexed
+        sink.rollback_to(protocol, &cursor)?;
+        history.push(cursor.height, cursor.block_hash);
+        current_height = cursor.height + 1;
+    }
 
-        // ===Atomicals===
-        "atom-arc20" => {
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"atom");
-            // let raw_envelopes = ord::RawEnvelope::from_transaction(&rawtx);
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let brc20_event = match decode_atom_arc20(inscription) {
-                    std::result::Result::Ok(event) => {
-                        // println!("{:?}: {:?}", txid, event);
-                        events.push(serde_json::json!({"protocol":"atom-arc20", "payload":event}));
-                    },
-                    Err(err) =>{},
-                } ;
-            }
-        }
-        "atom-relam" => {
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"atom");
-            // let raw_envelopes = ord::RawEnvelope::from_transaction(&rawtx);
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let event = match decode_atom_relam(inscription) {
-                    std::result::Result::Ok(event) => {
-                        // println!("{:?}: {:?}", txid, event),
-                        events.push(serde_json::json!({"protocol":"atom-relam", "payload":event}));
-                    },
-                    Err(err) =>{},
-                } ;
-            }
-        }
-        "atom-nft" => {
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"atom");
-            // let raw_envelopes = ord::RawEnvelope::from_transaction(&rawtx);
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let _ = match decode_atom_nft(inscription) {
-                    std::result::Result::Ok(event) => {
-                        events.push(serde_json::json!({"protocol":"atom-nft", "payload":event}));
-                        // println!("{:?}: {:?}", txid, event)
-                    },
-                    Err(err) =>{},
-                } ;
-            }
-        }
-        "atom-others" => {
-            let envelopes = ord::ParsedEnvelope::from_transaction(&rawtx, b"atom");
-            // let raw_envelopes = ord::RawEnvelope::from_transaction(&rawtx);
-            for item in envelopes.iter() {
-                // let body = item.clone().payload.body.unwrap();
-                let inscription = item.payload.clone();
-                let _ = match decode_atom_others(inscription) {
-                    std::result::Result::Ok(event) => {
-                        events.push(serde_json::json!({"protocol":"atom-others", "payload":event}));
-                        // println!("{:?}: {:?}", txid, event)
-                    },
-                    Err(err) =>{},
-                } ;
+    let mut fetchers = source_fetchers(connect, options.fetchers)?;
+    // the wait for the next block is logged once
+    let mut idle = false;
+    loop {
+        let tip_height = source.tip_height()?;
+        if current_height > tip_height{
+            if !idle {
+                eprintln!("{} | best height is {:?}, waiting for {:?}...", Local::now().format("%Y-%m-%d %H:%M:%S"), tip_height, tip_height+1);
+                idle = true;
             }
+            notifier.wait()?;
+            continue;
         }
-
-        // ===stamps===
-        "stamp-src20" =>{
-            match decode_stamp_src20(&rawtx) {
-                // std::result::Result::Ok(event)=>println!("{:?}: {:?}", txid, event),
-                std::result::Result::Ok(event) => {
-                    events.push(serde_json::json!({"protocol":"stamp-src20", "payload":event}));
-                }
-                Err(err) => {}
+        idle = false;
+
+        // blocks up to the tip go through the pipeline, which stops at the first
+        // one not building on the indexed blocks
+        let heights = (current_height..=tip_height).collect::<Vec<u64>>();
+        let mut reorg = false;
+        pipeline::run(&heights, &mut fetchers, registry, protocol, options, |block| {
+            if history.is_reorg(block.height, &block.header) {
+                reorg = true;
+                return Ok(false);
             }
+            eprintln!("{} | processing the height {:?}/{:?} {:?}...", Local::now().format("%Y-%m-%d %H:%M:%S"), block.height, tip_height, block.height as f64 / tip_height as f64);
+            let cursor = Cursor {
+                height: block.height,
+                block_hash: block.hash,
+            };
+            sink.write_block(Some(block.height), &block.events, Some((protocol, &cursor)))?;
+            history.push(block.height, block.hash);
+            current_height = block.height + 1;
+            Ok(true)
+        })?;
+
+        if reorg {
+            let fork = history.fork_point(|height| source.block_hash(height))?;
+            eprintln!("{} | reorg detected at height {:?}, rolling back events above {:?}...", Local::now().format("%Y-%m-%d %H:%M:%S"), current_height, fork);
+            let cursor = Cursor {
+                height: fork,
+                block_hash: history.hash(fork).expect("fork point is remembered"),
+            };
+            sink.rollback_to(protocol, &cursor)?;
+            history.rollback(fork);
+            current_height = fork + 1;
         }
+    }
+}
 
-        // ===runes===
-        "rune-alpha" => {
-            match decode_rune_alpha(&rawtx) {
-                std::result::Result::Ok(event) => {
-                    events.push(serde_json::json!({"protocol":"rune-alpha", "payload":event}));
-                },
-                Err(err) => {}
-            }
-        }
-        "rune-stone" =>{
-            match decode_rune_stone(&rawtx) {
-                std::result::Result::Ok(event) => {
-                    events.push(serde_json::json!({"protocol":"rune-stone", "payload":event}));
-                },
-                Err(err) => {}
-                // std::result::Result::Ok(event)=>println!("{:?}: {:?}", txid, event),
-            }
-        }
-        // "rune-alpha" => {
-        //     match decode_rune_alpha(&rawtx) {
-        //         std::result::Result::Ok(event) => {
-        //             println!("{:?}: {:?}", txid, event);
-        //             let _ = block_on(save_to_pg(&event));
-        //         },
-        //         Err(err) => {}
-        //     }
-        // }
-        _ => {
-            // Default case if none of the above match
-            println!("Unknown Protocol Name {:?}", protocol);
+/// the first block of a run building a state in memory, `activation` if
+/// omitted. These runs are one-shot exports: the state isn't persisted, so each
+/// run builds it again from the start, without cursor to resume from, reorg
+/// handling or following the tip. A run starting after the activation would
+/// miss the operations before it and write wrong events.
+fn stateful_start(state: &str, start: Option<u64>, activation: u64) -> Result<u64> {
+    match start {
+        Some(start) if start > activation => {
+            bail!("--start {start} is after block {activation}, the {state} would miss the blocks before it")
         }
+        Some(start) => Ok(start),
+        None => Ok(activation),
     }
-    return events
 }
 
-#[derive(Serialize, Eq, PartialEq, Deserialize, Debug)]
-struct DecodedEvent{
-    height: Option<i64>, //if decode a single txid event this will be None
-    blocktime: Option<i32>,
-    txhash: String,
-    txindex: Option<i32>,
-    protocol: String,
-    payload: serde_json::Value
+/// build the brc-20 ledger from the blocks of `start` (the first brc-20 deploy
+/// if omitted) to `end` (the tip if omitted) and write its verdicts and balance
+/// changes, block by block
+pub fn run_brc20(connect: &dyn Fn() -> Result<Box<dyn BlockSource>>, start: Option<u64>, end: Option<u64>, network: Network, options: &PipelineOptions, sink: &mut dyn EventSink) -> Result<Brc20Ledger> {
+    let start = stateful_start("brc-20 ledger", start, if network == Network::Bitcoin { brc20::FIRST_HEIGHT } else { 0 })?;
+    let source = connect()?;
+    let end = match end {
+        Some(end) => end,
+        None => source.tip_height()?,
+    };
+    let heights = (start..=end).collect::<Vec<u64>>();
+    let mut ledger = Brc20Ledger::new(network);
+    let mut prevouts = Prevouts::new(Some(source.as_ref()));
+    pipeline::run(&heights, &mut source_fetchers(connect, options.fetchers)?, &Registry::default(), "ord-brc20", options, |block| {
+        let events = ledger.index_block(&block.block, block.height, &block.events, &mut prevouts)?;
+        if !events.is_empty() {
+            eprintln!("{} | height {:?}/{:?}, {} brc-20 ledger events", Local::now().format("%Y-%m-%d %H:%M:%S"), block.height, end, events.len());
+        }
+        sink.write_block(Some(block.height), &events, None)?;
+        Ok(true)
+    })?;
+    Ok(ledger)
 }
 
-async fn save_event_to_pg(event: &DecodedEvent, conn: &str) -> Result<(), sqlx::Error> {
-    let pool = PgPoolOptions::new()
-    .max_connections(5)
-    .connect(conn)
-    .await?;
-
-    // let pool = PgPool::connect(&conn)
-    // .await
-    // .expect("Failed to connect to the database");
-
-    sqlx::query("INSERT INTO public.inscan_events (height, blocktime, txhash, txindex, protocol, payload) VALUES ($1, $2, $3, $4, $5, $6)")
-        .bind(event.height)
-        .bind(event.blocktime)
-        .bind(&event.txhash)
-        .bind(event.txindex)
-        .bind(&event.protocol)
-        .bind(&event.payload)
-        .execute(&pool)
-        .await?;
-
-    std::result::Result::Ok(())
+/// follow the ord, bitmap, brc-420 and sns inscriptions revealed from `start`
+/// (the first inscription if omitted) to `end` (the tip if omitted) and write
+/// their transfers, block by block
+pub fn run_transfers(connect: &dyn Fn() -> Result<Box<dyn BlockSource>>, start: Option<u64>, end: Option<u64>, network: Network, options: &PipelineOptions, sink: &mut dyn EventSink) -> Result<InscriptionTracker> {
+    let start = stateful_start("inscription tracker", start, if network == Network::Bitcoin { transfer::FIRST_HEIGHT } else { 0 })?;
+    let source = connect()?;
+    let end = match end {
+        Some(end) => end,
+        None => source.tip_height()?,
+    };
+    let heights = (start..=end).collect::<Vec<u64>>();
+    let mut tracker = InscriptionTracker::new(network);
+    let mut prevouts = Prevouts::new(Some(source.as_ref()));
+    pipeline::run(&heights, &mut source_fetchers(connect, options.fetchers)?, &transfer::registry(), ALL_PROTOCOLS, options, |block| {
+        let events = tracker.index_block(&block.block, block.height, &block.events, &mut prevouts)?;
+        if !events.is_empty() {
+            eprintln!("{} | height {:?}/{:?}, {} transfers, {} inscriptions tracked", Local::now().format("%Y-%m-%d %H:%M:%S"), block.height, end, events.len(), tracker.len());
+        }
+        sink.write_block(Some(block.height), &events, None)?;
+        Ok(true)
+    })?;
+    Ok(tracker)
 }
 
-fn write_jsonl(event: &DecodedEvent, file_path: &str) -> Result<(), Error> {
-    let file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(file_path)?;
-    // let file = File::create(file_path)?;
-    let mut writer = BufWriter::new(file);
-    let json_value = serde_json::to_value(event).unwrap();
-    serde_json::to_writer(&mut writer, &json_value)?;
-    writeln!(&mut writer)?;
-    writer.flush()?;
-    Ok(())
+/// build the runes state from the blocks of `start` (the first rune block if
+/// omitted) to `end` (the tip if omitted) and write the etchings, mints, burns
+/// and output balance changes, block by block
+pub fn run_runes(connect: &dyn Fn() -> Result<Box<dyn BlockSource>>, start: Option<u64>, end: Option<u64>, network: Network, options: &PipelineOptions, sink: &mut dyn EventSink) -> Result<RuneIndexer> {
+    let start = stateful_start("runes state", start, Rune::first_rune_height(network).into())?;
+    let source = connect()?;
+    let end = match end {
+        Some(end) => end,
+        None => source.tip_height()?,
+    };
+    let heights = (start..=end).collect::<Vec<u64>>();
+    let mut indexer = RuneIndexer::new(network);
+    pipeline::run(&heights, &mut source_fetchers(connect, options.fetchers)?, &Registry::empty(), ALL_PROTOCOLS, options, |block| {
+        let events = indexer.index_block(&block.block, block.height, source.as_ref())?;
+        if !events.is_empty() {
+            eprintln!("{} | height {:?}/{:?}, {} rune events", Local::now().format("%Y-%m-%d %H:%M:%S"), block.height, end, events.len());
+        }
+        sink.write_block(Some(block.height), &events, None)?;
+        Ok(true)
+    })?;
+    Ok(indexer)
 }
 
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{ord::Inscription, sink::JsonlSink},
+        bitcoin::{consensus::serialize, locktime::absolute::LockTime, OutPoint, ScriptBuf, Sequence, TxIn},
+    };
 
-fn split_string(input_string: &str, delimiter: &str) -> Vec<String> {
-    if input_string.contains(delimiter) {
-        input_string.split(delimiter).map(|s| s.to_string()).collect()
-    } else {
-        vec![input_string.to_string()]
-    }
-}
-
-pub fn run_txs(rpc: &Client, txids: &String, protocol: &str, output:&String) {
-    let txs = split_string(&txids, ",");
-    for tx in txs{
-        let txid = Txid::from_str(&tx).unwrap();
-        let mut results:Vec<serde_json::Value> = Vec::new();
-        if protocol == "all"{
-            for item in SUPPORT_PROTOCOLS.iter(){
-                let result = decode_tx(rpc, &txid, item);
-                results.extend(result.clone());
-                //TODO: if result contain ord- then break.
-                if !result.is_empty() && result[0].to_string().contains("ord-"){
-                    break;
-                }
-            }
-        }else{
-            let result = decode_tx(rpc, &txid, protocol);
-            results.extend(result);
-        }
-        
-        for evt in results{
-            let event = DecodedEvent{
-                height: None, 
-                blocktime: None,
-                txhash: txid.to_string(),
-                txindex: None,
-                protocol: String::from(evt["protocol"].as_str().unwrap()),
-                payload: evt.get("payload").unwrap().clone()
-            };
-            let _ = write_jsonl(&event, output);
+    fn transaction() -> Transaction {
+        let inscription = Inscription::new(
+            Some("text/plain;charset=utf-8".into()),
+            Some(r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"1000"}"#.into()),
+        );
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: inscription.to_witness(),
+            }],
+            output: Vec::new(),
         }
     }
-}
 
-pub fn run_blocks(rpc: &Client, block_number: &String, protocol: &str, output:&String) {
-    // init posrgres connection every block
-    let blocks:Vec<u64> = if block_number.contains(","){
-        let blocks_str = split_string(&block_number, ",");
-        blocks_str.iter().map(|s| s.parse::<u64>().unwrap()).collect()
+    #[test]
+    fn raw_values_are_hex_or_files_of_hex_or_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = serialize(&transaction());
+        let (hex_file, raw_file) = (dir.path().join("tx.hex"), dir.path().join("tx.bin"));
+        fs::write(&hex_file, format!("{}\n", hex::encode(&bytes))).unwrap();
+        fs::write(&raw_file, &bytes).unwrap();
+
+        assert_eq!(read_raw(&hex::encode(&bytes)).unwrap(), bytes);
+        assert_eq!(read_raw(&format!("@{}", hex_file.display())).unwrap(), bytes);
+        assert_eq!(read_raw(raw_file.to_str().unwrap()).unwrap(), bytes);
+        assert!(read_raw("not hex").is_err());
     }
-    else if block_number.contains(":") {
-        let txs = split_string(&block_number, ":");
-        let start: u64 = txs[0].parse().unwrap();
-        let stop: u64 = txs[1].parse().unwrap();
-        (start..=stop).collect()
-    } else{
-        vec![block_number.parse::<u64>().unwrap()]
-    };
 
-    // iterate over the blocks
-    for block in blocks {
-        let block_hash = rpc.get_block_hash(block).unwrap();
-        
-        let block_data = rpc.get_block(&block_hash).unwrap();
-        let timestamp = block_data.header.time;
-        for (idx, tx) in block_data.txdata.iter().enumerate() {
-            let txid = tx.txid();
-            let mut results:Vec<serde_json::Value> = Vec::new();
-            if protocol == "all"{
-                for item in SUPPORT_PROTOCOLS{
-                    let result = decode_tx(rpc, &txid, item);
-                    results.extend(result.clone());
-                    //TODO: if result contain ord- then break.
-                    if !result.is_empty() && result[0].to_string().contains("ord-"){
-                        break;
-                    }
-                }
-    
-            }else{
-                let result = decode_tx(rpc, &txid, protocol);
-                results.extend(result);
-            }
-            for evt in results{
-                let event = DecodedEvent{
-                    height: Some(block as i64), 
-                    blocktime: Some(timestamp as i32),
-                    txhash: txid.to_string(),
-                    txindex: Some(idx.try_into().unwrap()),
-                    protocol: String::from(evt["protocol"].as_str().unwrap()),
-                    payload: evt.get("payload").unwrap().clone()
-                };
-                if output.starts_with("postgres://"){
-                    let pg = block_on(save_event_to_pg(&event, &output));
-                    match pg {
-                        std::result::Result::Ok(a)=>{},
-                        Err(err)=>{println!("{:?}", err)}
-                    }
-                }else{
-                    let _ = write_jsonl(&event, output);
+    #[test]
+    fn raw_transactions_are_decoded_without_rpc() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("events.jsonl");
+        let mut sink = JsonlSink::open(output.to_str().unwrap()).unwrap();
 
-                }
-            }
-        }
+        run_raw_txs(&[hex::encode(serialize(&transaction()))], &Registry::default(), "all", &mut sink).unwrap();
+        let events = fs::read_to_string(&output).unwrap();
+        let event: crate::DecodedEvent = serde_json::from_str(events.lines().next().unwrap()).unwrap();
+        assert_eq!((event.txhash, event.event.protocol()), (transaction().txid().to_string(), "ord-brc20"));
+        assert!(run_raw_blocks(&[hex::encode(serialize(&transaction()))], &Registry::default(), "all", &mut sink).is_err());
     }
-}
-
 
-pub fn index_realtime(rpc: &Client, start_height:u64, protocol: &str, output:&String){
-    let mut current_height = start_height;
-    // delete the data start_height incase of duplicate
-    loop {
-        let rpc_height = rpc.get_block_count().unwrap();
-        if current_height > rpc_height{
-            thread::sleep(Duration::from_secs(1)); // sleep 2sec
-            println!("{} | best height is {:?}, waiting for {:?}, sleep 1 sec...", Local::now().format("%Y-%m-%d %H:%M:%S"), rpc_height, rpc_height+1);
-        } else{
-            println!("{} | processing the height {:?}/{:?} {:?}...", Local::now().format("%Y-%m-%d %H:%M:%S"), current_height, rpc_height, current_height as f64 / rpc_height as f64);
-            // process current_block
-            run_blocks(rpc, &current_height.to_string(), &protocol, output);
-            current_height += 1;
-        }
+    #[test]
+    fn stateful_runs_start_at_the_activation_at_the_latest() {
+        assert_eq!(stateful_start("runes state", None, 840000).unwrap(), 840000);
+        assert_eq!(stateful_start("runes state", Some(839000), 840000).unwrap(), 839000);
+        assert!(stateful_start("runes state", Some(840001), 840000).is_err());
     }
 }
@@ -0,0 +1,336 @@
+use {
+    crate::{
+        cursor::{self, Cursor},
+        decoder::{DecodedEvent, EventKey},
+    },
+    super::EventSink,
+    anyhow::{Context, Result},
+    std::{
+        collections::{HashMap, HashSet},
+        fs::{File, OpenOptions},
+        io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+        ops::Range,
+    },
+};
+
+/// bytes read at once when reading the file backwards
+const TAIL_CHUNK: u64 = 64 * 1024;
+
+/// the byte ranges of the lines of each height in a jsonl file
+type LineIndex = HashMap<Option<u64>, Vec<Range<u64>>>;
+
+/// a jsonl file of events, appended block after block. The cursor is kept in
+/// a state file next to it, see [`cursor::file_path`].
+pub struct JsonlSink {
+    path: String,
+    /// the height above which the file has no events, known once rolled back
+    above: Option<u64>,
+    /// where the lines of each height are, read the first time an event may
+    /// already be in the file
+    index: Option<LineIndex>,
+    /// keys of the events in the file at the heights of the block being written
+    written: HashMap<Option<u64>, HashSet<EventKey>>,
+    /// the file while a block is written, and its length
+    writer: Option<(BufWriter<File>, u64)>,
+}
+
+impl JsonlSink {
+    pub fn open(path: &str) -> Result<Self> {
+        Ok(Self {
+            path: path.to_string(),
+            above: None,
+            index: None,
+            written: HashMap::new(),
+            writer: None,
+        })
+    }
+
+    fn open_file(&self) -> Result<Option<File>> {
+        match File::open(&self.path) {
+            Ok(file) => Ok(Some(file)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// the lines of the file from the last one, with their offset, while `take` returns true
+    fn lines_backwards(&self, mut take: impl FnMut(u64, &[u8]) -> bool) -> Result<()> {
+        let Some(mut file) = self.open_file()? else {
+            return Ok(());
+        };
+        let mut position = file.metadata()?.len();
+        // the bytes from `position` to the start of the last line not taken yet
+        let mut buffer = Vec::new();
+        let mut at_end = true;
+        loop {
+            while let Some(newline) = buffer.iter().rposition(|byte| *byte == b'\n') {
+                if !take(position + newline as u64 + 1, &buffer[newline + 1..]) {
+                    return Ok(());
+                }
+                buffer.truncate(newline);
+            }
+            if position == 0 {
+                take(0, &buffer);
+                return Ok(());
+            }
+            let len = position.min(TAIL_CHUNK);
+            position -= len;
+            let mut chunk = vec![0; len as usize];
+            file.seek(SeekFrom::Start(position))?;
+            file.read_exact(&mut chunk)?;
+            chunk.extend_from_slice(&buffer);
+            buffer = chunk;
+            if std::mem::take(&mut at_end) && buffer.last() == Some(&b'\n') {
+                buffer.pop();
+            }
+        }
+    }
+
+    /// read the whole file once for the lines of each height
+    fn read_index(&self) -> Result<LineIndex> {
+        let mut index = LineIndex::new();
+        let Some(file) = self.open_file()? else {
+            return Ok(index);
+        };
+        let mut reader = BufReader::new(file);
+        let mut offset = 0;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let len = reader.read_until(b'\n', &mut line)? as u64;
+            if len == 0 {
+                return Ok(index);
+            }
+            if let Ok(event) = serde_json::from_slice::<serde_json::Value>(&line) {
+                add_line(&mut index, event["height"].as_u64(), offset..offset + len);
+            }
+            offset += len;
+        }
+    }
+
+    /// the keys of the events of `height` in the file
+    fn read_keys(&mut self, height: Option<u64>) -> Result<HashSet<EventKey>> {
+        if height.zip(self.above).is_some_and(|(height, above)| height > above) {
+            // the blocks above the rollback are all written by this run
+            return Ok(HashSet::new());
+        }
+        let (writer, _) = self.writer.as_mut().context("event written outside of a block")?;
+        writer.flush()?;
+        if self.index.is_none() {
+            self.index = Some(self.read_index()?);
+        }
+        let mut keys = HashSet::new();
+        let Some(mut file) = self.open_file()? else {
+            return Ok(keys);
+        };
+        for range in self.index.iter().flat_map(|index| index.get(&height)).flatten() {
+            let mut lines = vec![0; (range.end - range.start) as usize];
+            file.seek(SeekFrom::Start(range.start))?;
+            file.read_exact(&mut lines)?;
+            keys.extend(lines.split(|byte| *byte == b'\n').filter_map(|line| serde_json::from_slice(line).ok()));
+        }
+        Ok(keys)
+    }
+}
+
+/// add a line to the index, extending the range of the previous line of the same height
+fn add_line(index: &mut LineIndex, height: Option<u64>, line: Range<u64>) {
+    let ranges = index.entry(height).or_default();
+    match ranges.last_mut() {
+        Some(last) if last.end == line.start => last.end = line.end,
+        _ => ranges.push(line),
+    }
+}
+
+impl EventSink for JsonlSink {
+    fn begin_block(&mut self, _height: Option<u64>) -> Result<()> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        let len = file.metadata()?.len();
+        self.writer = Some((BufWriter::new(file), len));
+        self.written.clear();
+        Ok(())
+    }
+
+    /// append the event unless it is in the file already. The keys of the
+    /// heights of the block are read from the file, only the blocks of a
+    /// resumed or rolled back run are known to be missing from it.
+    fn write_event(&mut self, event: &DecodedEvent) -> Result<()> {
+        let height = event.height.map(|height| height as u64);
+        if !self.written.contains_key(&height) {
+            let keys = self.read_keys(height)?;
+            self.written.insert(height, keys);
+        }
+        if !self.written.entry(height).or_default().insert(event.key()) {
+            return Ok(());
+        }
+
+        let (writer, len) = self.writer.as_mut().context("event written outside of a block")?;
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        writer.write_all(&line)?;
+        if let Some(index) = &mut self.index {
+            add_line(index, height, *len..*len + line.len() as u64);
+        }
+        *len += line.len() as u64;
+        if let (Some(above), Some(height)) = (&mut self.above, height) {
+            *above = (*above).max(height);
+        }
+        Ok(())
+    }
+
+    fn commit_block(&mut self, cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        let (mut writer, _) = self.writer.take().context("block committed before it began")?;
+        writer.flush()?;
+        self.written.clear();
+        if let Some((protocol, cursor)) = cursor {
+            // the events must be on disk before the cursor moves past them
+            writer.get_ref().sync_data()?;
+            cursor::save_file(&self.path, protocol, cursor)?;
+        }
+        Ok(())
+    }
+
+    /// truncate the events of the blocks above the cursor at the end of the file
+    /// and move the state file back to it. Blocks are appended in ascending
+    /// order, so they are the trailing lines, read from the end.
+    fn rollback_to(&mut self, protocol: &str, cursor: &Cursor) -> Result<()> {
+        // nothing is dropped from the output of another protocol
+        cursor::load_file(&self.path, protocol)?;
+        let mut truncate_at = None;
+        self.lines_backwards(|offset, line| {
+            let above = serde_json::from_slice::<serde_json::Value>(line)
+                .ok()
+                .and_then(|event| event["height"].as_u64())
+                .is_some_and(|height| height > cursor.height);
+            if above {
+                truncate_at = Some(offset);
+            }
+            above
+        })?;
+
+        if let Some(len) = truncate_at {
+            OpenOptions::new().write(true).open(&self.path)?.set_len(len)?;
+            self.index = None;
+        }
+        self.above = Some(cursor.height);
+        cursor::save_file(&self.path, protocol, cursor)
+    }
+
+    fn load_cursor(&self, protocol: &str) -> Result<Option<Cursor>> {
+        cursor::load_file(&self.path, protocol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::test_util::{cursor, event},
+    };
+
+    #[test]
+    fn events_already_in_the_file_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let path = path.to_str().unwrap();
+
+        JsonlSink::open(path).unwrap().write_block(Some(1), &[event(1, "aa"), event(1, "bb")], None).unwrap();
+        let mut sink = JsonlSink::open(path).unwrap();
+        sink.write_block(Some(2), &[event(1, "bb"), event(2, "cc")], None).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 3);
+
+        // rolled back events can be written again
+        sink.rollback_to("all", &cursor(1)).unwrap();
+        sink.write_block(Some(2), &[event(2, "cc")], None).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn rollback_truncates_the_events_above_the_fork() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let path = path.to_str().unwrap();
+        let lines = [
+            r#"{"height":null,"txhash":"aa","protocol":"ord","payload":{}}"#,
+            r#"{"height":100,"txhash":"bb","protocol":"ord","payload":{}}"#,
+            r#"{"height":101,"txhash":"cc","protocol":"ord","payload":{}}"#,
+            r#"{"height":102,"txhash":"dd","protocol":"ord","payload":{}}"#,
+            r#"{"height":102,"txhash":"ee","protocol":"ord","payload":{}}"#,
+        ];
+        std::fs::write(path, lines.join("\n") + "\n").unwrap();
+        let mut sink = JsonlSink::open(path).unwrap();
+
+        sink.rollback_to("all", &cursor(102)).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), lines.join("\n") + "\n");
+
+        sink.rollback_to("all", &cursor(100)).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), lines[..2].join("\n") + "\n");
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(100)));
+    }
+
+    #[test]
+    fn blocks_are_written_once_before_the_cursor_moves() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let path = path.to_str().unwrap();
+        let event = event(100, "aa");
+        let cursor = cursor(100);
+        let mut sink = JsonlSink::open(path).unwrap();
+
+        sink.write_block(Some(100), std::slice::from_ref(&event), None).unwrap();
+        assert_eq!(sink.load_cursor("all").unwrap(), None);
+        let next = DecodedEvent { ordinal: 1, ..event.clone() };
+        sink.write_block(Some(100), &[event, next], Some(("all", &cursor))).unwrap();
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor));
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn events_are_looked_up_by_height() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let path = path.to_str().unwrap();
+        let events = |height: i64| {
+            let event = event(height, &format!("{height:064x}"));
+            [event.clone(), DecodedEvent { ordinal: 1, ..event }]
+        };
+        let mut sink = JsonlSink::open(path).unwrap();
+        for height in 1..=1000 {
+            sink.write_block(Some(height as u64), &events(height), Some(("ord-bitmap", &cursor(height as u64)))).unwrap();
+        }
+
+        // the file spans several chunks, read backwards line by line
+        let mut lines = Vec::new();
+        sink.lines_backwards(|offset, line| {
+            lines.push((offset, line.to_vec()));
+            true
+        })
+        .unwrap();
+        let mut offset = 0;
+        let mut forward = Vec::new();
+        for line in std::fs::read(path).unwrap().split_inclusive(|byte| *byte == b'\n') {
+            forward.push((offset, line[..line.len() - 1].to_vec()));
+            offset += line.len() as u64;
+        }
+        forward.reverse();
+        assert!(std::fs::metadata(path).unwrap().len() > 2 * TAIL_CHUNK);
+        assert_eq!(lines, forward);
+
+        // a resumed run writes above the cursor, without reading the file
+        let mut sink = JsonlSink::open(path).unwrap();
+        sink.rollback_to("ord-bitmap", &cursor(1000)).unwrap();
+        sink.write_block(Some(1001), &events(1001), Some(("ord-bitmap", &cursor(1001)))).unwrap();
+        assert!(sink.index.is_none());
+        assert!(sink.load_cursor("ord-sns").is_err());
+
+        // decoding a block again only writes its new events
+        let mut sink = JsonlSink::open(path).unwrap();
+        let new = DecodedEvent { ordinal: 2, ..event(500, &format!("{:064x}", 500)) };
+        sink.write_block(Some(500), &[&events(500)[..], &[new]].concat(), None).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 2003);
+        assert_eq!(sink.index.unwrap()[&Some(500)].len(), 2);
+    }
+}
@@ -0,0 +1,150 @@
+use {
+    crate::{cursor::Cursor, decoder::DecodedEvent},
+    anyhow::Result,
+    futures::executor::block_on,
+};
+
+pub use self::{
+    jsonl::JsonlSink,
+    parquet::{ParquetSink, DEFAULT_BLOCKS_PER_FILE},
+    postgres::PgSink,
+    rotating::{Compression, Manifest, RotatingJsonlSink, WindowFile},
+    sqlite::SqliteSink,
+    stdout::StdoutSink,
+};
+
+mod jsonl;
+mod parquet;
+mod postgres;
+mod rotating;
+mod sqlite;
+mod stdout;
+
+/// where decoded events are written, block by block.
+///
+/// A block is written with `begin_block`, `write_event` for each of its events
+/// and `commit_block`. Sinks keeping the events (files, databases) remember the
+/// cursor given to `commit_block` as the last fully written block, so an
+/// indexing run can resume after it.
+pub trait EventSink {
+    /// start the events of the block at `height`, or of transactions decoded without block
+    fn begin_block(&mut self, height: Option<u64>) -> Result<()>;
+
+    fn write_event(&mut self, event: &DecodedEvent) -> Result<()>;
+
+    /// the block is complete. With a cursor, it becomes the last block indexed for `protocol`.
+    fn commit_block(&mut self, cursor: Option<(&str, &Cursor)>) -> Result<()>;
+
+    /// drop the events of the blocks above the cursor, which becomes the last
+    /// block indexed for `protocol`
+    fn rollback_to(&mut self, protocol: &str, cursor: &Cursor) -> Result<()>;
+
+    /// the last block indexed for `protocol`, `None` if unknown
+    fn load_cursor(&self, protocol: &str) -> Result<Option<Cursor>>;
+
+    /// the run is over. Sinks buffering blocks, like parquet files of several
+    /// blocks each, write what they hold.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// write the events of a block in one go
+    fn write_block(&mut self, height: Option<u64>, events: &[DecodedEvent], cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        self.begin_block(height)?;
+        for event in events {
+            self.write_event(event)?;
+        }
+        self.commit_block(cursor)
+    }
+}
+
+/// the sink of an `--out-file` or `--out-db` value: `-` for stdout, a `postgres://`
+/// or `sqlite://` url for a database and a jsonl file path otherwise
+pub fn open(output: &str) -> Result<Box<dyn EventSink>> {
+    if output == "-" {
+        Ok(Box::new(StdoutSink::new()))
+    } else if output.starts_with("postgres://") {
+        Ok(Box::new(block_on(PgSink::connect(output))?))
+    } else if output.starts_with("sqlite:") {
+        Ok(Box::new(block_on(SqliteSink::connect(output))?))
+    } else {
+        Ok(Box::new(JsonlSink::open(output)?))
+    }
+}
+
+/// writes every event to several sinks at once
+pub struct FanOut {
+    sinks: Vec<Box<dyn EventSink>>,
+}
+
+impl FanOut {
+    pub fn new(sinks: Vec<Box<dyn EventSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl EventSink for FanOut {
+    fn begin_block(&mut self, height: Option<u64>) -> Result<()> {
+        self.sinks.iter_mut().try_for_each(|sink| sink.begin_block(height))
+    }
+
+    fn write_event(&mut self, event: &DecodedEvent) -> Result<()> {
+        self.sinks.iter_mut().try_for_each(|sink| sink.write_event(event))
+    }
+
+    fn commit_block(&mut self, cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        self.sinks.iter_mut().try_for_each(|sink| sink.commit_block(cursor))
+    }
+
+    fn rollback_to(&mut self, protocol: &str, cursor: &Cursor) -> Result<()> {
+        self.sinks.iter_mut().try_for_each(|sink| sink.rollback_to(protocol, cursor))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.sinks.iter_mut().try_for_each(|sink| sink.finish())
+    }
+
+    /// the lowest cursor of the sinks, so none of them misses a block when
+    /// resuming. Sinks ahead of it are rolled back to it.
+    fn load_cursor(&self, protocol: &str) -> Result<Option<Cursor>> {
+        let mut lowest: Option<Cursor> = None;
+        for sink in &self.sinks {
+            if let Some(cursor) = sink.load_cursor(protocol)? {
+                if lowest.is_none_or(|lowest| cursor.height < lowest.height) {
+                    lowest = Some(cursor);
+                }
+            }
+        }
+        Ok(lowest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::test_util::{cursor, event},
+    };
+
+    #[test]
+    fn fan_out_writes_every_sink_and_resumes_from_the_lowest_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.jsonl");
+        let second = dir.path().join("second.jsonl");
+        let (first, second) = (first.to_str().unwrap(), second.to_str().unwrap());
+
+        open(first).unwrap().write_block(Some(1), &[event(1, "aa")], Some(("all", &cursor(1)))).unwrap();
+
+        let mut sink = FanOut::new(vec![open(first).unwrap(), open(second).unwrap(), open("-").unwrap()]);
+        sink.write_block(Some(2), &[event(2, "bb")], Some(("all", &cursor(2)))).unwrap();
+        assert_eq!(std::fs::read_to_string(first).unwrap().lines().count(), 2);
+        assert_eq!(std::fs::read_to_string(second).unwrap().lines().count(), 1);
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(2)));
+
+        open(second).unwrap().rollback_to("all", &cursor(1)).unwrap();
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(1)));
+        sink.rollback_to("all", &cursor(1)).unwrap();
+        assert_eq!(std::fs::read_to_string(first).unwrap().lines().count(), 1);
+        assert_eq!(std::fs::read_to_string(second).unwrap().lines().count(), 0);
+    }
+}
@@ -0,0 +1,547 @@
+use {
+    crate::{
+        cursor::{self, Cursor},
+        decoder::{DecodedEvent, EventKey},
+        Event,
+    },
+    super::EventSink,
+    anyhow::{Context, Result},
+    arrow_array::{
+        builder::{Int32Builder, Int64Builder, StringBuilder, UInt32Builder},
+        cast::AsArray,
+        types::Int64Type,
+        ArrayRef, BooleanArray, RecordBatch,
+    },
+    arrow_schema::{DataType, Field, Schema},
+    ordinals::{Edict, Rune, RuneId, SpacedRune},
+    parquet::{
+        arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter},
+        basic::Compression,
+        file::properties::WriterProperties,
+    },
+    std::{
+        collections::{btree_map::Entry, BTreeMap, HashSet},
+        fs::{self, File},
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+};
+
+/// blocks per file when not told otherwise
+pub const DEFAULT_BLOCKS_PER_FILE: u64 = 1000;
+
+/// a directory of parquet files, partitioned by protocol as
+/// `protocol=<protocol>/events-<first>-<last>.parquet`.
+///
+/// Each block is appended as a row group to a temporary file per protocol,
+/// renamed once the window of `blocks` heights it belongs to is complete, or
+/// when the run finishes. A file never spans two windows, so a window written
+/// over two runs is split in two files. The cursor, in a state file next to the
+/// directory, only moves once the files are renamed.
+///
+/// Every file has the columns of [`DecodedEvent`], with the payload as json
+/// text, followed by the typed columns of its protocol, see [`typed_fields`].
+pub struct ParquetSink {
+    dir: PathBuf,
+    blocks: u64,
+    window: Option<Window>,
+}
+
+/// the blocks of a file being filled
+struct Window {
+    /// the heights of its first and last blocks, `None` for transactions decoded without block
+    range: Option<(u64, u64)>,
+    /// the temporary file of each protocol
+    files: BTreeMap<String, (PathBuf, ArrowWriter<File>)>,
+    /// the events of the block being written, by protocol
+    block: BTreeMap<String, Vec<DecodedEvent>>,
+    keys: HashSet<EventKey>,
+    /// the protocol and cursor of its last committed block
+    cursor: Option<(String, Cursor)>,
+}
+
+impl ParquetSink {
+    pub fn open(dir: &str, blocks: u64) -> Result<Self> {
+        let dir = PathBuf::from(dir.trim_end_matches('/'));
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            blocks: blocks.max(1),
+            window: None,
+        })
+    }
+
+    /// the output name the cursor state file is named after, `<dir>.cursor`
+    fn output(&self) -> String {
+        self.dir.to_string_lossy().into_owned()
+    }
+
+    fn slot(&self, height: Option<u64>) -> Option<u64> {
+        height.map(|height| height / self.blocks)
+    }
+
+    /// write the files of the window, then move the cursor to its last block
+    fn flush(&mut self) -> Result<()> {
+        if let Some((protocol, cursor)) = self.close()? {
+            cursor::save_file(&self.output(), &protocol, &cursor)?;
+        }
+        Ok(())
+    }
+
+    /// complete the files of the window and rename them, returning its cursor
+    fn close(&mut self) -> Result<Option<(String, Cursor)>> {
+        let Some(window) = self.window.take() else {
+            return Ok(None);
+        };
+        for (tmp, writer) in window.files.into_values() {
+            writer.close()?;
+            fs::rename(&tmp, tmp.with_file_name(file_name(window.range)))?;
+        }
+        Ok(window.cursor)
+    }
+
+    /// the written files of every partition, with their range of heights
+    fn files(&self) -> Result<Vec<(PathBuf, u64, u64)>> {
+        let mut files = Vec::new();
+        for partition in fs::read_dir(&self.dir)? {
+            let partition = partition?.path();
+            if !partition.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&partition)? {
+                let path = file?.path();
+                if let Some((first, last)) = path.file_name().and_then(|name| parse_file_name(&name.to_string_lossy())) {
+                    files.push((path, first, last));
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+impl EventSink for ParquetSink {
+    fn begin_block(&mut self, height: Option<u64>) -> Result<()> {
+        let slot = self.slot(height);
+        if self
+            .window
+            .as_ref()
+            .is_some_and(|window| self.slot(window.range.map(|(first, _)| first)) != slot)
+        {
+            self.flush()?;
+        }
+        let window = self.window.get_or_insert_with(|| Window {
+            range: height.map(|height| (height, height)),
+            files: BTreeMap::new(),
+            block: BTreeMap::new(),
+            keys: HashSet::new(),
+            cursor: None,
+        });
+        window.block.clear();
+        window.keys.clear();
+        if let (Some((_, last)), Some(height)) = (&mut window.range, height) {
+            *last = height;
+        }
+        Ok(())
+    }
+
+    fn write_event(&mut self, event: &DecodedEvent) -> Result<()> {
+        let window = self.window.as_mut().context("event written outside of a block")?;
+        if window.keys.insert(event.key()) {
+            window
+                .block
+                .entry(event.event.protocol().to_string())
+                .or_default()
+                .push(event.clone());
+        }
+        Ok(())
+    }
+
+    /// the block is a row group of the files of its protocols, which are
+    /// complete once the last block of the window is committed
+    fn commit_block(&mut self, cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        let blocks = self.blocks;
+        let window = self.window.as_mut().context("block committed before it began")?;
+        for (protocol, events) in std::mem::take(&mut window.block) {
+            let batch = record_batch(&protocol, &events)?;
+            let (_, writer) = match window.files.entry(protocol) {
+                Entry::Occupied(file) => file.into_mut(),
+                Entry::Vacant(file) => {
+                    let partition = self.dir.join(format!("protocol={}", file.key()));
+                    fs::create_dir_all(&partition)?;
+                    let tmp = partition.join(file_name(window.range)).with_extension("parquet.tmp");
+                    let writer = ArrowWriter::try_new(File::create(&tmp)?, batch.schema(), Some(properties()))?;
+                    file.insert((tmp, writer))
+                }
+            };
+            writer.write(&batch)?;
+            writer.flush()?;
+        }
+        if let Some((protocol, cursor)) = cursor {
+            window.cursor = Some((protocol.to_string(), *cursor));
+        }
+        if window.range.is_some_and(|(_, last)| last % blocks == blocks - 1) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// complete the files of the window, delete the files starting above the
+    /// cursor and rewrite the ones spanning it without their blocks above it
+    fn rollback_to(&mut self, protocol: &str, cursor: &Cursor) -> Result<()> {
+        // nothing is dropped from the output of another protocol
+        cursor::load_file(&self.output(), protocol)?;
+        self.close()?;
+        for (path, first, last) in self.files()? {
+            if first > cursor.height {
+                fs::remove_file(&path)?;
+            } else if last > cursor.height {
+                truncate_file(&path, &path.with_file_name(file_name(Some((first, cursor.height)))), cursor.height)?;
+            }
+        }
+        cursor::save_file(&self.output(), protocol, cursor)
+    }
+
+    /// the cursor of the last written file
+    fn load_cursor(&self, protocol: &str) -> Result<Option<Cursor>> {
+        cursor::load_file(&self.output(), protocol)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+/// `events-<first>-<last>.parquet`, `events-unconfirmed.parquet` for transactions without block
+fn file_name(range: Option<(u64, u64)>) -> String {
+    match range {
+        Some((first, last)) => format!("events-{first}-{last}.parquet"),
+        None => "events-unconfirmed.parquet".to_string(),
+    }
+}
+
+fn parse_file_name(name: &str) -> Option<(u64, u64)> {
+    let (first, last) = name.strip_prefix("events-")?.strip_suffix(".parquet")?.split_once('-')?;
+    Some((first.parse().ok()?, last.parse().ok()?))
+}
+
+fn properties() -> WriterProperties {
+    WriterProperties::builder().set_compression(Compression::SNAPPY).build()
+}
+
+/// write through a temporary file, so readers never see a partial file
+fn write_batch(path: &Path, batch: &RecordBatch) -> Result<()> {
+    let tmp = path.with_extension("parquet.tmp");
+    let mut writer = ArrowWriter::try_new(File::create(&tmp)?, batch.schema(), Some(properties()))?;
+    writer.write(batch)?;
+    writer.close()?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// rewrite the file at `path` to `to` with only the rows at or below `height`
+fn truncate_file(path: &Path, to: &Path, height: u64) -> Result<()> {
+    let mut batches = Vec::new();
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    let schema = reader.schema().clone();
+    for batch in reader.build()? {
+        let batch = batch?;
+        let keep: BooleanArray = batch
+            .column_by_name("height")
+            .context("parquet file without height column")?
+            .as_primitive::<Int64Type>()
+            .iter()
+            .map(|row| Some(row.is_none_or(|row| row as u64 <= height)))
+            .collect();
+        batches.push(arrow_select::filter::filter_record_batch(&batch, &keep)?);
+    }
+    fs::remove_file(path)?;
+    write_batch(to, &arrow_select::concat::concat_batches(&schema, &batches)?)
+}
+
+fn text(name: &str) -> Field {
+    Field::new(name, DataType::Utf8, true)
+}
+
+fn int(name: &str) -> Field {
+    Field::new(name, DataType::UInt32, true)
+}
+
+/// the typed columns of a protocol: `op`, `tick` and the amounts of brc-20 (as
+/// inscribed text, they may have 18 decimals), and for runes the etched rune,
+/// the minted rune id and one row per edict with its rune `id`, `amount` and
+/// `output`
+pub fn typed_fields(protocol: &str) -> Vec<Field> {
+    match protocol {
+        "ord-brc20" => ["op", "tick", "amt", "max", "lim", "dec"].map(text).to_vec(),
+        "rune-stone" => vec![
+            text("op"),
+            text("rune"),
+            text("mint"),
+            int("pointer"),
+            int("edict"),
+            text("id"),
+            text("amount"),
+            int("output"),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Clone)]
+enum Cell {
+    Text(Option<String>),
+    Int(Option<u32>),
+}
+
+fn brc20_row(op: &str, tick: &str, amt: Option<&str>, max: Option<&str>, lim: Option<&str>, dec: Option<&str>) -> Vec<Cell> {
+    [Some(op), Some(tick), amt, max, lim, dec]
+        .map(|value| Cell::Text(value.map(str::to_string)))
+        .to_vec()
+}
+
+fn rune_rows(op: &str, rune: Option<String>, mint: Option<RuneId>, pointer: Option<u32>, edicts: &[Edict]) -> Vec<Vec<Cell>> {
+    let row = |edict: Option<(usize, &Edict)>| {
+        vec![
+            Cell::Text(Some(op.to_string())),
+            Cell::Text(rune.clone()),
+            Cell::Text(mint.map(|mint| mint.to_string())),
+            Cell::Int(pointer),
+            Cell::Int(edict.map(|(index, _)| index as u32)),
+            Cell::Text(edict.map(|(_, edict)| edict.id.to_string())),
+            Cell::Text(edict.map(|(_, edict)| edict.amount.to_string())),
+            Cell::Int(edict.map(|(_, edict)| edict.output)),
+        ]
+    };
+    if edicts.is_empty() {
+        vec![row(None)]
+    } else {
+        edicts.iter().enumerate().map(|edict| row(Some(edict))).collect()
+    }
+}
+
+/// the typed cells of the rows of an event, nothing for untyped events
+fn typed_rows(event: &Event) -> Vec<Vec<Cell>> {
+    match event {
+        Event::Brc20Deploy(deploy) => vec![brc20_row(
+            "deploy",
+            &deploy.tick,
+            None,
+            Some(&deploy.max),
+            deploy.lim(),
+            deploy.dec(),
+        )],
+        Event::Brc20Mint(mint) => vec![brc20_row("mint", &mint.tick, Some(&mint.amt), None, None, None)],
+        Event::Brc20Transfer(transfer) => vec![brc20_row("transfer", &transfer.tick, Some(&transfer.amt), None, None, None)],
+        Event::RuneEtching(runestone) | Event::RuneMint(runestone) | Event::RuneEdicts(runestone) => {
+            let op = match event {
+                Event::RuneEtching(_) => "etching",
+                Event::RuneMint(_) => "mint",
+                _ => "edicts",
+            };
+            let rune = runestone.etching.and_then(|etching| {
+                etching
+                    .rune
+                    .map(|rune| SpacedRune::new(rune, etching.spacers.unwrap_or_default()).to_string())
+            });
+            rune_rows(op, rune, runestone.mint, runestone.pointer, &runestone.edicts)
+        }
+        Event::RuneCenotaph(cenotaph) => rune_rows("cenotaph", cenotaph.etching.as_ref().map(Rune::to_string), cenotaph.mint, None, &[]),
+        _ => Vec::new(),
+    }
+}
+
+enum Builder {
+    Text(StringBuilder),
+    Int(UInt32Builder),
+}
+
+/// the rows of the events of one protocol, one per event or more for typed
+/// events with several rows
+fn record_batch(protocol: &str, events: &[DecodedEvent]) -> Result<RecordBatch> {
+    let typed = typed_fields(protocol);
+    let mut fields = vec![
+        Field::new("height", DataType::Int64, true),
+        Field::new("blocktime", DataType::Int32, true),
+        Field::new("txhash", DataType::Utf8, false),
+        Field::new("txindex", DataType::Int32, true),
+        Field::new("input", DataType::UInt32, false),
+        Field::new("offset", DataType::UInt32, false),
+        Field::new("ordinal", DataType::UInt32, false),
+        Field::new("protocol", DataType::Utf8, false),
+        Field::new("payload", DataType::Utf8, false),
+    ];
+
+    let mut height = Int64Builder::new();
+    let mut blocktime = Int32Builder::new();
+    let mut txhash = StringBuilder::new();
+    let mut txindex = Int32Builder::new();
+    let mut input = UInt32Builder::new();
+    let mut offset = UInt32Builder::new();
+    let mut ordinal = UInt32Builder::new();
+    let mut protocols = StringBuilder::new();
+    let mut payload = StringBuilder::new();
+    let mut columns: Vec<Builder> = typed
+        .iter()
+        .map(|field| match field.data_type() {
+            DataType::UInt32 => Builder::Int(UInt32Builder::new()),
+            _ => Builder::Text(StringBuilder::new()),
+        })
+        .collect();
+
+    for event in events {
+        let json = event.event.payload()?.to_string();
+        let mut rows = typed_rows(&event.event);
+        if rows.is_empty() {
+            rows.push(
+                typed
+                    .iter()
+                    .map(|field| match field.data_type() {
+                        DataType::UInt32 => Cell::Int(None),
+                        _ => Cell::Text(None),
+                    })
+                    .collect(),
+            );
+        }
+        for row in rows {
+            height.append_option(event.height);
+            blocktime.append_option(event.blocktime);
+            txhash.append_value(&event.txhash);
+            txindex.append_option(event.txindex);
+            input.append_value(event.input);
+            offset.append_value(event.offset);
+            ordinal.append_value(event.ordinal);
+            protocols.append_value(protocol);
+            payload.append_value(&json);
+            for (column, cell) in columns.iter_mut().zip(row) {
+                match (column, cell) {
+                    (Builder::Text(column), Cell::Text(value)) => column.append_option(value),
+                    (Builder::Int(column), Cell::Int(value)) => column.append_option(value),
+                    _ => unreachable!("typed cells follow the typed fields"),
+                }
+            }
+        }
+    }
+
+    let mut arrays: Vec<ArrayRef> = vec![
+        Arc::new(height.finish()),
+        Arc::new(blocktime.finish()),
+        Arc::new(txhash.finish()),
+        Arc::new(txindex.finish()),
+        Arc::new(input.finish()),
+        Arc::new(offset.finish()),
+        Arc::new(ordinal.finish()),
+        Arc::new(protocols.finish()),
+        Arc::new(payload.finish()),
+    ];
+    for column in &mut columns {
+        arrays.push(match column {
+            Builder::Text(column) => Arc::new(column.finish()),
+            Builder::Int(column) => Arc::new(column.finish()),
+        });
+    }
+    fields.extend(typed);
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::test_util::{self, cursor},
+        arrow_array::types::UInt32Type,
+    };
+
+    fn event(height: i64, txhash: &str, protocol: &str, payload: serde_json::Value) -> DecodedEvent {
+        DecodedEvent {
+            event: Event::from_parts(protocol, payload),
+            ..test_util::event(height, txhash)
+        }
+    }
+
+    fn brc20(height: i64, txhash: &str) -> DecodedEvent {
+        event(height, txhash, "ord-brc20", serde_json::json!({"p": "brc-20", "op": "mint", "tick": "ordi", "amt": "1000"}))
+    }
+
+    fn read(path: &Path) -> RecordBatch {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+        let schema = reader.schema().clone();
+        let batches = reader.build().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        arrow_select::concat::concat_batches(&schema, &batches).unwrap()
+    }
+
+    fn text_column(batch: &RecordBatch, name: &str) -> Vec<Option<String>> {
+        batch
+            .column_by_name(name)
+            .unwrap()
+            .as_string::<i32>()
+            .iter()
+            .map(|value| value.map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn windows_are_written_per_protocol_once_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("events");
+        let mut sink = ParquetSink::open(out.to_str().unwrap(), 2).unwrap();
+        let rune = serde_json::json!({"Runestone": {
+            "edicts": [{"id": "840000:1", "amount": 5, "output": 1}, {"id": "840000:1", "amount": 7, "output": 2}],
+            "etching": null,
+            "mint": null,
+            "pointer": null,
+        }});
+
+        sink.write_block(Some(2), &[brc20(2, "aa")], Some(("all", &cursor(2)))).unwrap();
+        assert!(out.join("protocol=ord-brc20/events-2-2.parquet.tmp").exists());
+        assert!(!out.join("protocol=ord-brc20/events-2-3.parquet").exists());
+        assert_eq!(sink.load_cursor("all").unwrap(), None);
+
+        sink.write_block(Some(3), &[brc20(3, "bb"), event(3, "cc", "rune-stone", rune)], Some(("all", &cursor(3)))).unwrap();
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(3)));
+
+        // a row group per block
+        let file = File::open(out.join("protocol=ord-brc20/events-2-3.parquet")).unwrap();
+        assert_eq!(ParquetRecordBatchReaderBuilder::try_new(file).unwrap().metadata().num_row_groups(), 2);
+        let batch = read(&out.join("protocol=ord-brc20/events-2-3.parquet"));
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(text_column(&batch, "tick"), [Some("ordi".into()), Some("ordi".into())]);
+        assert_eq!(text_column(&batch, "amt"), [Some("1000".into()), Some("1000".into())]);
+        assert_eq!(text_column(&batch, "max"), [None, None]);
+
+        let batch = read(&out.join("protocol=rune-stone/events-2-3.parquet"));
+        assert_eq!(text_column(&batch, "op"), [Some("edicts".into()), Some("edicts".into())]);
+        assert_eq!(text_column(&batch, "id"), [Some("840000:1".into()), Some("840000:1".into())]);
+        assert_eq!(text_column(&batch, "amount"), [Some("5".into()), Some("7".into())]);
+        let output = batch.column_by_name("output").unwrap().as_primitive::<UInt32Type>();
+        assert_eq!((output.value(0), output.value(1)), (1, 2));
+
+        // a partial window is written when the run finishes
+        sink.write_block(Some(4), &[brc20(4, "dd")], Some(("all", &cursor(4)))).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(read(&out.join("protocol=ord-brc20/events-4-4.parquet")).num_rows(), 1);
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(4)));
+    }
+
+    #[test]
+    fn rollback_rewrites_the_files_above_the_fork() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("events");
+        let mut sink = ParquetSink::open(out.to_str().unwrap(), 4).unwrap();
+        for height in 0..6 {
+            sink.write_block(Some(height), &[brc20(height as i64, &format!("{height:02x}"))], Some(("all", &cursor(height))))
+                .unwrap();
+        }
+        sink.finish().unwrap();
+        let partition = out.join("protocol=ord-brc20");
+
+        sink.rollback_to("all", &cursor(4)).unwrap();
+        assert!(partition.join("events-0-3.parquet").exists());
+        assert!(!partition.join("events-4-5.parquet").exists());
+        assert_eq!(text_column(&read(&partition.join("events-4-4.parquet")), "txhash"), [Some("04".into())]);
+
+        sink.rollback_to("all", &cursor(2)).unwrap();
+        assert!(!partition.join("events-4-4.parquet").exists());
+        assert_eq!(read(&partition.join("events-0-2.parquet")).num_rows(), 3);
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(2)));
+    }
+}
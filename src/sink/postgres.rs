@@ -0,0 +1,114 @@
+use {
+    crate::{
+        cursor::{self, Cursor},
+        db,
+        decoder::{DecodedEvent, ALL_PROTOCOLS},
+    },
+    super::EventSink,
+    anyhow::Result,
+    futures::executor::block_on,
+    sqlx::{postgres::PgPoolOptions, PgPool, Postgres, QueryBuilder},
+};
+
+/// rows per INSERT statement, postgres takes at most 65535 parameters per statement
+const INSERT_BATCH: usize = 5000;
+
+/// the `public.inscan_events` table, through one connection pool for the whole process.
+/// The schema must be up to date, see [`db`].
+pub struct PgSink {
+    pool: PgPool,
+    /// the events of the block being written, inserted on commit
+    block: Vec<DecodedEvent>,
+}
+
+impl PgSink {
+    pub async fn connect(url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(url)
+            .await?;
+        db::check(&pool, &db::PG_MIGRATOR).await?;
+        Ok(Self { pool, block: Vec::new() })
+    }
+
+    /// write the events of a block in one transaction, with the cursor of `protocol`
+    /// when indexing, so a block is either fully written or not at all. Events
+    /// already in the table are updated in place.
+    async fn save_block(&self, events: &[DecodedEvent], cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        let rows = events
+            .iter()
+            .map(|event| Ok((event, event.event.payload()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut tx = self.pool.begin().await?;
+        for batch in rows.chunks(INSERT_BATCH) {
+            QueryBuilder::<Postgres>::new(
+                "INSERT INTO public.inscan_events (height, blocktime, txhash, txindex, input, envelope_offset, ordinal, protocol, payload) ",
+            )
+            .push_values(batch, |mut row, (event, payload)| {
+                row.push_bind(event.height)
+                    .push_bind(event.blocktime)
+                    .push_bind(&event.txhash)
+                    .push_bind(event.txindex)
+                    .push_bind(event.input as i32)
+                    .push_bind(event.offset as i32)
+                    .push_bind(event.ordinal as i32)
+                    .push_bind(event.event.protocol())
+                    .push_bind(payload);
+            })
+            .push(
+                " ON CONFLICT (txhash, input, envelope_offset, protocol, ordinal) DO UPDATE SET
+                height = EXCLUDED.height, blocktime = EXCLUDED.blocktime, txindex = EXCLUDED.txindex, payload = EXCLUDED.payload",
+            )
+            .build()
+            .execute(&mut *tx)
+            .await?;
+        }
+        if let Some((protocol, cursor)) = cursor {
+            cursor::save_pg(&mut tx, protocol, cursor).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// delete the events of `protocol` of the blocks above the cursor, every
+    /// event for `all`, and move the cursor of `protocol` back to it. The events
+    /// of the other protocols follow their own cursor.
+    async fn rollback(&self, protocol: &str, cursor: &Cursor) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let mut events = QueryBuilder::<Postgres>::new("DELETE FROM public.inscan_events WHERE height > ");
+        events.push_bind(cursor.height as i32);
+        if protocol != ALL_PROTOCOLS {
+            events.push(" AND protocol = ").push_bind(protocol);
+        }
+        events.build().execute(&mut *tx).await?;
+        cursor::save_pg(&mut tx, protocol, cursor).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+impl EventSink for PgSink {
+    fn begin_block(&mut self, _height: Option<u64>) -> Result<()> {
+        self.block.clear();
+        Ok(())
+    }
+
+    fn write_event(&mut self, event: &DecodedEvent) -> Result<()> {
+        self.block.push(event.clone());
+        Ok(())
+    }
+
+    fn commit_block(&mut self, cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        let events = std::mem::take(&mut self.block);
+        block_on(self.save_block(&events, cursor))
+    }
+
+    fn rollback_to(&mut self, protocol: &str, cursor: &Cursor) -> Result<()> {
+        block_on(self.rollback(protocol, cursor))
+    }
+
+    fn load_cursor(&self, protocol: &str) -> Result<Option<Cursor>> {
+        block_on(cursor::load_pg(&self.pool, protocol))
+    }
+}
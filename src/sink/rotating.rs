@@ -0,0 +1,425 @@
+use {
+    crate::{
+        cursor::{self, Cursor},
+        decoder::{DecodedEvent, EventKey},
+    },
+    super::EventSink,
+    anyhow::{Context, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashSet,
+        fs::{self, File, OpenOptions},
+        io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+        path::{Path, PathBuf},
+    },
+};
+
+/// the manifest of the completed files, in the output directory
+pub const MANIFEST: &str = "manifest.json";
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zstd,
+    Gzip,
+}
+
+impl Compression {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Zstd => "zst",
+            Self::Gzip => "gz",
+        }
+    }
+
+    /// the compression of a file named after its extension, `None` for plain files
+    pub fn of_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "zst" => Some(Self::Zstd),
+            "gz" => Some(Self::Gzip),
+            _ => None,
+        }
+    }
+
+    /// the lines of a file written with this compression, across the frames
+    /// appended to it
+    pub fn reader(self, file: File) -> Result<Box<dyn BufRead>> {
+        Ok(match self {
+            Self::Zstd => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+            Self::Gzip => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(BufReader::new(file)))),
+        })
+    }
+}
+
+enum Encoder {
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+}
+
+impl Encoder {
+    fn new(compression: Compression, file: File) -> Result<Self> {
+        let file = BufWriter::new(file);
+        Ok(match compression {
+            Compression::Zstd => Self::Zstd(zstd::Encoder::new(file, 0)?),
+            Compression::Gzip => Self::Gzip(flate2::write::GzEncoder::new(file, flate2::Compression::default())),
+        })
+    }
+
+    /// end the compressed stream and sync the file
+    fn finish(self) -> Result<()> {
+        let writer = match self {
+            Self::Zstd(encoder) => encoder.finish()?,
+            Self::Gzip(encoder) => encoder.finish()?,
+        };
+        writer.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+        Ok(())
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Zstd(encoder) => encoder.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Zstd(encoder) => encoder.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// a completed file of the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WindowFile {
+    pub file: String,
+    /// the heights of its first and last blocks, none for transactions decoded without block
+    pub first: Option<u64>,
+    pub last: Option<u64>,
+    pub events: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub windows: Vec<WindowFile>,
+}
+
+/// the file being written
+struct Window {
+    range: Option<(u64, u64)>,
+    encoder: Encoder,
+    keys: HashSet<EventKey>,
+    events: u64,
+    /// the protocol and cursor of its last committed block
+    cursor: Option<(String, Cursor)>,
+}
+
+/// a directory of compressed jsonl files, one per window of `blocks` heights,
+/// named `events-<first>-<last>.jsonl.<zst|gz>`.
+///
+/// A window is written to a `.tmp` file, renamed and added to the manifest once
+/// its last block is committed, or when the run finishes. A file never spans
+/// two windows, so a window written over two runs is split in two files. The
+/// cursor, in a state file next to the directory, only moves with the renames:
+/// the `.tmp` file left by a crash is deleted and its blocks indexed again.
+pub struct RotatingJsonlSink {
+    dir: PathBuf,
+    blocks: u64,
+    compression: Compression,
+    window: Option<Window>,
+}
+
+impl RotatingJsonlSink {
+    pub fn open(dir: &str, blocks: u64, compression: Compression) -> Result<Self> {
+        let dir = PathBuf::from(dir.trim_end_matches('/'));
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "tmp") {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(Self {
+            dir,
+            blocks: blocks.max(1),
+            compression,
+            window: None,
+        })
+    }
+
+    /// the output name the cursor state file is named after, `<dir>.cursor`
+    fn output(&self) -> String {
+        self.dir.to_string_lossy().into_owned()
+    }
+
+    fn slot(&self, height: Option<u64>) -> Option<u64> {
+        height.map(|height| height / self.blocks)
+    }
+
+    fn file_name(&self, range: Option<(u64, u64)>) -> String {
+        let extension = self.compression.extension();
+        match range {
+            Some((first, last)) => format!("events-{first}-{last}.jsonl.{extension}"),
+            None => format!("events-unconfirmed.jsonl.{extension}"),
+        }
+    }
+
+    fn tmp_path(&self, range: Option<(u64, u64)>) -> PathBuf {
+        let first = range.map_or("unconfirmed".to_string(), |(first, _)| first.to_string());
+        self.dir.join(format!("events-{first}.jsonl.{}.tmp", self.compression.extension()))
+    }
+
+    pub fn manifest(&self) -> Result<Manifest> {
+        match fs::read(self.dir.join(MANIFEST)) {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save_manifest(&self, manifest: &Manifest) -> Result<()> {
+        let path = self.dir.join(MANIFEST);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(manifest)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// rename the file of the window and add it to the manifest, then move the cursor
+    fn complete(&mut self) -> Result<()> {
+        let Some(window) = self.window.take() else {
+            return Ok(());
+        };
+        window.encoder.finish()?;
+        let file = self.file_name(window.range);
+        fs::rename(self.tmp_path(window.range), self.dir.join(&file))?;
+
+        let mut manifest = self.manifest()?;
+        manifest.windows.retain(|entry| entry.file != file);
+        manifest.windows.push(WindowFile {
+            file,
+            first: window.range.map(|(first, _)| first),
+            last: window.range.map(|(_, last)| last),
+            events: window.events,
+        });
+        manifest.windows.sort_by_key(|entry| entry.first);
+        self.save_manifest(&manifest)?;
+
+        if let Some((protocol, cursor)) = window.cursor {
+            cursor::save_file(&self.output(), &protocol, &cursor)?;
+        }
+        Ok(())
+    }
+
+    /// copy the events at or below `height` of the file `from` to the file `to`,
+    /// returning how many they are and their keys
+    fn rewrite(&self, from: &Path, to: &Path, height: u64) -> Result<(u64, HashSet<EventKey>)> {
+        let tmp = PathBuf::from(format!("{}.rewrite.tmp", to.display()));
+        let mut encoder = Encoder::new(self.compression, File::create(&tmp)?)?;
+        let mut events = 0;
+        let mut keys = HashSet::new();
+        for line in self.compression.reader(File::open(from)?)?.split(b'\n') {
+            let line = line?;
+            let above = serde_json::from_slice::<serde_json::Value>(&line)
+                .ok()
+                .and_then(|event| event["height"].as_u64())
+                .is_some_and(|event| event > height);
+            if !above {
+                keys.extend(serde_json::from_slice::<EventKey>(&line).ok());
+                encoder.write_all(&line)?;
+                encoder.write_all(b"\n")?;
+                events += 1;
+            }
+        }
+        encoder.finish()?;
+        fs::remove_file(from)?;
+        fs::rename(tmp, to)?;
+        Ok((events, keys))
+    }
+}
+
+impl EventSink for RotatingJsonlSink {
+    fn begin_block(&mut self, height: Option<u64>) -> Result<()> {
+        let slot = self.slot(height);
+        if self
+            .window
+            .as_ref()
+            .is_some_and(|window| self.slot(window.range.map(|(first, _)| first)) != slot)
+        {
+            self.complete()?;
+        }
+        match &mut self.window {
+            Some(window) => {
+                if let (Some((_, last)), Some(height)) = (&mut window.range, height) {
+                    *last = height;
+                }
+            }
+            None => {
+                let range = height.map(|height| (height, height));
+                let file = File::create(self.tmp_path(range))?;
+                self.window = Some(Window {
+                    range,
+                    encoder: Encoder::new(self.compression, file)?,
+                    keys: HashSet::new(),
+                    events: 0,
+                    cursor: None,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn write_event(&mut self, event: &DecodedEvent) -> Result<()> {
+        let window = self.window.as_mut().context("event written outside of a block")?;
+        if window.keys.insert(event.key()) {
+            serde_json::to_writer(&mut window.encoder, event)?;
+            window.encoder.write_all(b"\n")?;
+            window.events += 1;
+        }
+        Ok(())
+    }
+
+    /// the file is completed once the last block of its window is committed
+    fn commit_block(&mut self, cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        let blocks = self.blocks;
+        let window = self.window.as_mut().context("block committed before it began")?;
+        if let Some((protocol, cursor)) = cursor {
+            window.cursor = Some((protocol.to_string(), *cursor));
+        }
+        if window.range.is_some_and(|(_, last)| last % blocks == blocks - 1) {
+            self.complete()?;
+        }
+        Ok(())
+    }
+
+    /// drop the events of the blocks above the cursor from the file being
+    /// written and the completed ones, which are renamed after their new last block
+    fn rollback_to(&mut self, protocol: &str, cursor: &Cursor) -> Result<()> {
+        // nothing is dropped from the output of another protocol
+        cursor::load_file(&self.output(), protocol)?;
+        if let Some(window) = self.window.take() {
+            let tmp = self.tmp_path(window.range);
+            match window.range {
+                Some((first, last)) if first <= cursor.height => {
+                    window.encoder.finish()?;
+                    let range = Some((first, last.min(cursor.height)));
+                    let (events, keys) = self.rewrite(&tmp, &tmp, cursor.height)?;
+                    let file = OpenOptions::new().append(true).open(&tmp)?;
+                    self.window = Some(Window {
+                        range,
+                        encoder: Encoder::new(self.compression, file)?,
+                        keys,
+                        events,
+                        cursor: Some((protocol.to_string(), *cursor)),
+                    });
+                }
+                Some(_) => {
+                    drop(window);
+                    fs::remove_file(tmp)?;
+                }
+                None => self.window = Some(window),
+            }
+        }
+
+        let mut manifest = self.manifest()?;
+        let mut windows = Vec::new();
+        for mut entry in manifest.windows {
+            match (entry.first, entry.last) {
+                (Some(first), _) if first > cursor.height => fs::remove_file(self.dir.join(&entry.file))?,
+                (Some(first), Some(last)) if last > cursor.height => {
+                    let file = self.file_name(Some((first, cursor.height)));
+                    (entry.events, _) = self.rewrite(&self.dir.join(&entry.file), &self.dir.join(&file), cursor.height)?;
+                    entry.file = file;
+                    entry.last = Some(cursor.height);
+                    windows.push(entry);
+                }
+                _ => windows.push(entry),
+            }
+        }
+        manifest.windows = windows;
+        self.save_manifest(&manifest)?;
+
+        // blocks at or below the cursor still in the `.tmp` file are not
+        // completed yet, the saved cursor stays before them
+        if self.window.as_ref().is_none_or(|window| window.range.is_none()) {
+            cursor::save_file(&self.output(), protocol, cursor)?;
+        }
+        Ok(())
+    }
+
+    /// the cursor of the last completed file
+    fn load_cursor(&self, protocol: &str) -> Result<Option<Cursor>> {
+        cursor::load_file(&self.output(), protocol)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.complete()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::test_util::{cursor, event},
+    };
+
+    fn lines(sink: &RotatingJsonlSink, file: &str) -> usize {
+        let reader = sink.compression.reader(File::open(sink.dir.join(file)).unwrap()).unwrap();
+        reader.lines().count()
+    }
+
+    fn write(sink: &mut RotatingJsonlSink, heights: std::ops::Range<u64>) {
+        for height in heights {
+            sink.write_block(Some(height), &[event(height as i64, &format!("{height:02x}"))], Some(("all", &cursor(height)))).unwrap();
+        }
+    }
+
+    #[test]
+    fn windows_are_renamed_once_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("events");
+        let mut sink = RotatingJsonlSink::open(out.to_str().unwrap(), 2, Compression::Zstd).unwrap();
+
+        write(&mut sink, 0..3);
+        assert_eq!(lines(&sink, "events-0-1.jsonl.zst"), 2);
+        assert!(out.join("events-2.jsonl.zst.tmp").exists());
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(1)));
+        let files = sink.manifest().unwrap().windows;
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].first, files[0].last, files[0].events), (Some(0), Some(1), 2));
+
+        // the `.tmp` file of a crashed run is dropped, its blocks are indexed again
+        drop(sink);
+        let mut sink = RotatingJsonlSink::open(out.to_str().unwrap(), 2, Compression::Zstd).unwrap();
+        assert!(!out.join("events-2.jsonl.zst.tmp").exists());
+        write(&mut sink, 2..5);
+        sink.finish().unwrap();
+        assert_eq!(lines(&sink, "events-2-3.jsonl.zst"), 2);
+        assert_eq!(lines(&sink, "events-4-4.jsonl.zst"), 1);
+        assert_eq!(sink.manifest().unwrap().windows.len(), 3);
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(4)));
+    }
+
+    #[test]
+    fn rollback_rewrites_the_files_above_the_fork() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("events");
+        let mut sink = RotatingJsonlSink::open(out.to_str().unwrap(), 4, Compression::Gzip).unwrap();
+
+        write(&mut sink, 0..7);
+        sink.rollback_to("all", &cursor(5)).unwrap();
+        write(&mut sink, 6..8);
+        assert_eq!(lines(&sink, "events-4-7.jsonl.gz"), 4);
+
+        sink.rollback_to("all", &cursor(2)).unwrap();
+        assert!(!out.join("events-4-7.jsonl.gz").exists());
+        assert_eq!(lines(&sink, "events-0-2.jsonl.gz"), 3);
+        let files = sink.manifest().unwrap().windows;
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].file.as_str(), files[0].events), ("events-0-2.jsonl.gz", 3));
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(2)));
+    }
+}
@@ -0,0 +1,186 @@
+use {
+    crate::{
+        cursor::{self, Cursor},
+        db,
+        decoder::{DecodedEvent, ALL_PROTOCOLS},
+    },
+    super::EventSink,
+    anyhow::Result,
+    futures::executor::block_on,
+    sqlx::{QueryBuilder, Sqlite, SqlitePool},
+};
+
+/// rows per INSERT statement, sqlite takes at most 32766 parameters per statement
+const INSERT_BATCH: usize = 1000;
+
+/// the `inscan_events` table of a sqlite file, with the schema of the postgres
+/// output and payloads stored as json text. The schema must be up to date, see [`db`].
+pub struct SqliteSink {
+    pool: SqlitePool,
+    /// the events of the block being written, inserted on commit
+    block: Vec<DecodedEvent>,
+}
+
+impl SqliteSink {
+    pub async fn connect(url: &str) -> Result<Self> {
+        let pool = db::connect_sqlite(url).await?;
+        db::check(&pool, &db::SQLITE_MIGRATOR).await?;
+        Ok(Self { pool, block: Vec::new() })
+    }
+
+    /// write the events of a block in one transaction, with the cursor of `protocol`
+    /// when indexing. Events already in the table are updated in place.
+    async fn save_block(&self, events: &[DecodedEvent], cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        let rows = events
+            .iter()
+            .map(|event| Ok((event, event.event.payload()?.to_string())))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut tx = self.pool.begin().await?;
+        for batch in rows.chunks(INSERT_BATCH) {
+            QueryBuilder::<Sqlite>::new(
+                "INSERT INTO inscan_events (height, blocktime, txhash, txindex, input, envelope_offset, ordinal, protocol, payload) ",
+            )
+            .push_values(batch, |mut row, (event, payload)| {
+                row.push_bind(event.height)
+                    .push_bind(event.blocktime)
+                    .push_bind(&event.txhash)
+                    .push_bind(event.txindex)
+                    .push_bind(event.input)
+                    .push_bind(event.offset)
+                    .push_bind(event.ordinal)
+                    .push_bind(event.event.protocol())
+                    .push_bind(payload);
+            })
+            .push(
+                " ON CONFLICT (txhash, input, envelope_offset, protocol, ordinal) DO UPDATE SET
+                height = excluded.height, blocktime = excluded.blocktime, txindex = excluded.txindex, payload = excluded.payload",
+            )
+            .build()
+            .execute(&mut *tx)
+            .await?;
+        }
+        if let Some((protocol, cursor)) = cursor {
+            cursor::save_sqlite(&mut tx, protocol, cursor).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// delete the events of `protocol` of the blocks above the cursor, every
+    /// event for `all`, and move the cursor of `protocol` back to it
+    async fn rollback(&self, protocol: &str, cursor: &Cursor) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let mut events = QueryBuilder::<Sqlite>::new("DELETE FROM inscan_events WHERE height > ");
+        events.push_bind(cursor.height as i64);
+        if protocol != ALL_PROTOCOLS {
+            events.push(" AND protocol = ").push_bind(protocol);
+        }
+        events.build().execute(&mut *tx).await?;
+        cursor::save_sqlite(&mut tx, protocol, cursor).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+impl EventSink for SqliteSink {
+    fn begin_block(&mut self, _height: Option<u64>) -> Result<()> {
+        self.block.clear();
+        Ok(())
+    }
+
+    fn write_event(&mut self, event: &DecodedEvent) -> Result<()> {
+        self.block.push(event.clone());
+        Ok(())
+    }
+
+    fn commit_block(&mut self, cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        let events = std::mem::take(&mut self.block);
+        block_on(self.save_block(&events, cursor))
+    }
+
+    fn rollback_to(&mut self, protocol: &str, cursor: &Cursor) -> Result<()> {
+        block_on(self.rollback(protocol, cursor))
+    }
+
+    fn load_cursor(&self, protocol: &str) -> Result<Option<Cursor>> {
+        block_on(cursor::load_sqlite(&self.pool, protocol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            test_util::{cursor, event},
+            Event,
+        },
+        sqlx::Row,
+    };
+
+    #[test]
+    fn blocks_are_upserted_and_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("events.db").display());
+        block_on(async {
+            let pool = db::connect_sqlite(&url).await.unwrap();
+            db::init(&pool, &db::SQLITE_MIGRATOR).await.unwrap();
+        });
+
+        let mut sink = block_on(SqliteSink::connect(&url)).unwrap();
+        assert_eq!(sink.load_cursor("all").unwrap(), None);
+        sink.write_block(Some(1), &[event(1, "aa"), event(1, "bb")], Some(("all", &cursor(1)))).unwrap();
+        sink.write_block(Some(2), &[event(1, "bb"), event(2, "cc")], Some(("all", &cursor(2)))).unwrap();
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(2)));
+
+        let rows = |sink: &SqliteSink| {
+            block_on(sqlx::query("SELECT txhash, height, payload FROM inscan_events ORDER BY txhash").fetch_all(&sink.pool))
+                .unwrap()
+                .iter()
+                .map(|row| (row.get::<String, _>("txhash"), row.get::<i64, _>("height"), row.get::<String, _>("payload")))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            rows(&sink),
+            [
+                ("aa".into(), 1, r#"{"mint":"1.bitmap"}"#.into()),
+                ("bb".into(), 1, r#"{"mint":"1.bitmap"}"#.into()),
+                ("cc".into(), 2, r#"{"mint":"2.bitmap"}"#.into()),
+            ]
+        );
+
+        sink.rollback_to("all", &cursor(1)).unwrap();
+        assert_eq!(sink.load_cursor("all").unwrap(), Some(cursor(1)));
+        assert_eq!(rows(&sink).len(), 2);
+
+        // a single protocol would delete the rows of every protocol on rollback
+        assert!(sink.load_cursor("ord-sns").is_err());
+        assert!(sink.write_block(Some(2), &[event(2, "dd")], Some(("ord-sns", &cursor(2)))).is_err());
+        assert_eq!(rows(&sink).len(), 2);
+    }
+
+    #[test]
+    fn rollbacks_keep_the_events_of_the_other_protocols() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("events.db").display());
+        block_on(async {
+            let pool = db::connect_sqlite(&url).await.unwrap();
+            db::init(&pool, &db::SQLITE_MIGRATOR).await.unwrap();
+        });
+
+        let mut sink = block_on(SqliteSink::connect(&url)).unwrap();
+        let sns = DecodedEvent {
+            event: Event::from_parts("ord-sns", serde_json::json!({"p":"sns","op":"reg","name":"a.sats"})),
+            ..event(2, "dd")
+        };
+        sink.write_block(Some(2), &[event(2, "cc")], Some(("ord-bitmap", &cursor(2)))).unwrap();
+        sink.write_block(Some(2), &[sns], Some(("ord-sns", &cursor(2)))).unwrap();
+        sink.rollback_to("ord-bitmap", &cursor(1)).unwrap();
+        assert_eq!(sink.load_cursor("ord-bitmap").unwrap(), Some(cursor(1)));
+        assert_eq!(sink.load_cursor("ord-sns").unwrap(), Some(cursor(2)));
+        let txhashes = block_on(sqlx::query_scalar::<_, String>("SELECT txhash FROM inscan_events").fetch_all(&sink.pool));
+        assert_eq!(txhashes.unwrap(), ["dd"]);
+        assert!(sink.load_cursor("all").is_err());
+    }
+}
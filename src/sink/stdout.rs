@@ -0,0 +1,61 @@
+use {
+    crate::{cursor::Cursor, decoder::DecodedEvent},
+    super::EventSink,
+    anyhow::Result,
+    serde::Serialize,
+    std::io::{self, Write},
+};
+
+/// prints events as jsonl to stdout, to pipe them into another program
+#[derive(Default)]
+pub struct StdoutSink {
+    out: Vec<u8>,
+}
+
+/// printed when the blocks above `rollback_to` were reorganized out of the
+/// chain, so the reader can drop the events it got for them
+#[derive(Serialize)]
+struct Rollback<'a> {
+    rollback_to: &'a Cursor,
+}
+
+impl StdoutSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl EventSink for StdoutSink {
+    fn begin_block(&mut self, _height: Option<u64>) -> Result<()> {
+        self.out.clear();
+        Ok(())
+    }
+
+    fn write_event(&mut self, event: &DecodedEvent) -> Result<()> {
+        serde_json::to_writer(&mut self.out, event)?;
+        self.out.push(b'\n');
+        Ok(())
+    }
+
+    /// print the events once the block is complete
+    fn commit_block(&mut self, _cursor: Option<(&str, &Cursor)>) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&self.out)?;
+        stdout.flush()?;
+        self.out.clear();
+        Ok(())
+    }
+
+    fn rollback_to(&mut self, _protocol: &str, cursor: &Cursor) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        serde_json::to_writer(&mut stdout, &Rollback { rollback_to: cursor })?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// stdout keeps nothing to resume from
+    fn load_cursor(&self, _protocol: &str) -> Result<Option<Cursor>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,174 @@
+use {
+    super::BlockSource,
+    anyhow::{anyhow, bail, Context, Result},
+    bitcoin::{block::Header, consensus::deserialize, Block, BlockHash, Transaction, Txid},
+    std::{io::Read, str::FromStr, time::Duration},
+};
+
+/// the REST api of an Esplora server, as served by electrs (e.g.
+/// `https://blockstream.info/api`), or by a local stand-in with the same endpoints
+pub struct EsploraSource {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl EsploraSource {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().timeout(Duration::from_secs(60)).build(),
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<ureq::Response> {
+        let url = format!("{}{path}", self.url);
+        match self.agent.get(&url).call() {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(status, response)) => {
+                let message = response.into_string().unwrap_or_default();
+                Err(anyhow!("GET {url} failed with status {status}: {}", message.trim()))
+            }
+            Err(err) => Err(anyhow!(err).context(format!("GET {url} failed"))),
+        }
+    }
+
+    fn text(&self, path: &str) -> Result<String> {
+        let text = self.get(path)?.into_string()?;
+        Ok(text.trim().to_string())
+    }
+
+    fn bytes(&self, path: &str) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.get(path)?.into_reader().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl BlockSource for EsploraSource {
+    fn tip_height(&self) -> Result<u64> {
+        let height = self.text("/blocks/tip/height")?;
+        height.parse().with_context(|| format!("invalid tip height {height:?}"))
+    }
+
+    fn block_hash(&self, height: u64) -> Result<BlockHash> {
+        let hash = self.text(&format!("/block-height/{height}"))?;
+        BlockHash::from_str(&hash).with_context(|| format!("invalid block hash {hash:?}"))
+    }
+
+    fn block(&self, hash: &BlockHash) -> Result<Block> {
+        let block: Block = deserialize(&self.bytes(&format!("/block/{hash}/raw"))?).with_context(|| format!("invalid block {hash}"))?;
+        if block.block_hash() != *hash {
+            return Err(anyhow!("block {hash} was served as {}", block.block_hash()));
+        }
+        Ok(block)
+    }
+
+    fn block_header(&self, hash: &BlockHash) -> Result<Header> {
+        let header = hex::decode(self.text(&format!("/block/{hash}/header"))?)?;
+        deserialize(&header).with_context(|| format!("invalid header of block {hash}"))
+    }
+
+    fn raw_tx(&self, txid: &Txid) -> Result<Transaction> {
+        deserialize(&self.bytes(&format!("/tx/{txid}/raw"))?).with_context(|| format!("invalid transaction {txid}"))
+    }
+
+    fn mempool_txids(&self) -> Result<Vec<Txid>> {
+        let txids = self.text("/mempool/txids")?;
+        serde_json::from_str(&txids).context("invalid mempool txids")
+    }
+
+    fn tx_height(&self, txid: &Txid) -> Result<Option<u64>> {
+        let status = self.text(&format!("/tx/{txid}/status"))?;
+        let status: serde_json::Value = serde_json::from_str(&status).with_context(|| format!("invalid status of transaction {txid}"))?;
+        Ok(status.get("block_height").and_then(serde_json::Value::as_u64))
+    }
+
+    /// from the pages of 25 transactions of `/block/:hash/txs/:start`
+    fn block_fees(&self, hash: &BlockHash) -> Result<Vec<u64>> {
+        let block: serde_json::Value = serde_json::from_str(&self.text(&format!("/block/{hash}"))?).with_context(|| format!("invalid block {hash}"))?;
+        let count = block["tx_count"].as_u64().with_context(|| format!("no tx_count for block {hash}"))?;
+        let mut fees = Vec::new();
+        while (fees.len() as u64) < count {
+            let page = self.text(&format!("/block/{hash}/txs/{}", fees.len()))?;
+            let txs: Vec<serde_json::Value> = serde_json::from_str(&page).with_context(|| format!("invalid transactions of block {hash}"))?;
+            if txs.is_empty() {
+                bail!("block {hash} has {count} transactions, {} were served", fees.len());
+            }
+            for tx in txs {
+                fees.push(tx["fee"].as_u64().with_context(|| format!("no fee for transaction {} of block {hash}", fees.len()))?);
+            }
+        }
+        Ok(fees)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        bitcoin::{blockdata::constants::genesis_block, consensus::serialize, Network},
+        std::{
+            collections::HashMap,
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+            thread,
+        },
+    };
+
+    /// an http server answering the paths of `routes`, and 404 to the others
+    fn serve(routes: HashMap<String, Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let (status, body) = match routes.get(path) {
+                    Some(body) => ("200 OK", body.clone()),
+                    None => ("404 Not Found", b"Block not found".to_vec()),
+                };
+                write!(stream, "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn blocks_and_transactions_are_read_from_the_api() {
+        let block = genesis_block(Network::Regtest);
+        let (hash, txid) = (block.block_hash(), block.txdata[0].txid());
+        let url = serve(HashMap::from([
+            ("/blocks/tip/height".to_string(), b"0".to_vec()),
+            ("/block-height/0".to_string(), hash.to_string().into_bytes()),
+            (format!("/block/{hash}/raw"), serialize(&block)),
+            (format!("/block/{hash}/header"), hex::encode(serialize(&block.header)).into_bytes()),
+            (format!("/tx/{txid}/raw"), serialize(&block.txdata[0])),
+            ("/mempool/txids".to_string(), format!(r#"["{txid}"]"#).into_bytes()),
+            (format!("/tx/{txid}/status"), br#"{"confirmed":true,"block_height":0,"block_hash":"0f9188f1"}"#.to_vec()),
+            (format!("/block/{hash}"), br#"{"tx_count":26}"#.to_vec()),
+            (format!("/block/{hash}/txs/0"), format!("[{{\"fee\":0}}{}]", r#",{"fee":2}"#.repeat(24)).into_bytes()),
+            (format!("/block/{hash}/txs/25"), br#"[{"fee":3}]"#.to_vec()),
+        ]));
+
+        let source = EsploraSource::new(&format!("{url}/"));
+        assert_eq!(source.tip_height().unwrap(), 0);
+        assert_eq!(source.block_hash(0).unwrap(), hash);
+        assert_eq!(source.block_at(0).unwrap(), block);
+        assert_eq!(source.block_header(&hash).unwrap(), block.header);
+        assert_eq!(source.raw_tx(&txid).unwrap(), block.txdata[0]);
+        assert_eq!(source.mempool_txids().unwrap(), [txid]);
+        assert_eq!(source.tx_height(&txid).unwrap(), Some(0));
+        assert_eq!(source.block_fees(&hash).unwrap(), [vec![0], vec![2; 24], vec![3]].concat());
+
+        let err = source.block_hash(1).unwrap_err();
+        assert_eq!(err.to_string(), format!("GET {url}/block-height/1 failed with status 404: Block not found"));
+    }
+}
@@ -0,0 +1,91 @@
+use {
+    anyhow::{anyhow, Context, Result},
+    bitcoin::{block::Header, Amount, Block, BlockHash, Transaction, Txid},
+    bitcoincore_rpc::{Client, RpcApi},
+};
+
+pub use self::esplora::EsploraSource;
+
+mod esplora;
+
+/// where blocks and transactions are read from: bitcoind rpc, or the http api
+/// of an Esplora server (electrs), which needs no `-txindex` node.
+///
+/// A source is used from one thread at a time, each block fetcher of the
+/// pipeline has its own.
+pub trait BlockSource: Send + Sync {
+    /// the height of the tip of the best chain
+    fn tip_height(&self) -> Result<u64>;
+
+    /// the hash of the block at `height` in the best chain
+    fn block_hash(&self, height: u64) -> Result<BlockHash>;
+
+    fn block(&self, hash: &BlockHash) -> Result<Block>;
+
+    /// the header of a block, in the best chain or not
+    fn block_header(&self, hash: &BlockHash) -> Result<Header>;
+
+    /// a transaction of the chain, or of the mempool
+    fn raw_tx(&self, txid: &Txid) -> Result<Transaction>;
+
+    /// the transactions of the mempool
+    fn mempool_txids(&self) -> Result<Vec<Txid>>;
+
+    /// the height of the block of a transaction, `None` while it is unconfirmed
+    fn tx_height(&self, txid: &Txid) -> Result<Option<u64>>;
+
+    /// the fee of each transaction of a block, 0 for the coinbase
+    fn block_fees(&self, hash: &BlockHash) -> Result<Vec<u64>>;
+
+    /// the block at `height` in the best chain
+    fn block_at(&self, height: u64) -> Result<Block> {
+        self.block(&self.block_hash(height)?)
+    }
+}
+
+impl BlockSource for Client {
+    fn tip_height(&self) -> Result<u64> {
+        Ok(self.get_block_count()?)
+    }
+
+    fn block_hash(&self, height: u64) -> Result<BlockHash> {
+        Ok(self.get_block_hash(height)?)
+    }
+
+    fn block(&self, hash: &BlockHash) -> Result<Block> {
+        Ok(self.get_block(hash)?)
+    }
+
+    fn block_header(&self, hash: &BlockHash) -> Result<Header> {
+        Ok(self.get_block_header(hash)?)
+    }
+
+    fn raw_tx(&self, txid: &Txid) -> Result<Transaction> {
+        Ok(self.get_raw_transaction(txid, None)?)
+    }
+
+    fn mempool_txids(&self) -> Result<Vec<Txid>> {
+        Ok(self.get_raw_mempool()?)
+    }
+
+    fn tx_height(&self, txid: &Txid) -> Result<Option<u64>> {
+        match self.get_raw_transaction_info(txid, None)?.blockhash {
+            Some(hash) => Ok(Some(self.get_block_header_info(&hash)?.height as u64)),
+            None => Ok(None),
+        }
+    }
+
+    /// from `getblock` with verbosity 2, which has the fees in btc
+    fn block_fees(&self, hash: &BlockHash) -> Result<Vec<u64>> {
+        let block: serde_json::Value = self.call("getblock", &[hash.to_string().into(), 2.into()])?;
+        let txs = block["tx"].as_array().with_context(|| format!("no transactions in block {hash}"))?;
+        txs.iter()
+            .enumerate()
+            .map(|(index, tx)| match tx["fee"].as_f64() {
+                Some(fee) => Ok(Amount::from_btc(fee)?.to_sat()),
+                None if index == 0 => Ok(0),
+                None => Err(anyhow!("no fee for transaction {index} of block {hash}, is the node pruned?")),
+            })
+            .collect()
+    }
+}
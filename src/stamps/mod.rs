@@ -0,0 +1,2 @@
+mod src20;
+pub use src20::*;
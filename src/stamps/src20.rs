@@ -0,0 +1,10 @@
+use crate::ord::brcX::brc20::{Brc20Deploy, Brc20Mint, Brc20Transfer};
+
+pub const PROTOCOL: &str = "src-20";
+
+/// `{"p":"src-20","op":"deploy",...}`, same fields as brc-20
+pub type Src20Deploy = Brc20Deploy;
+/// `{"p":"src-20","op":"mint",...}`
+pub type Src20Mint = Brc20Mint;
+/// `{"p":"src-20","op":"transfer",...}`
+pub type Src20Transfer = Brc20Transfer;
@@ -0,0 +1,59 @@
+use {
+    crate::{cursor::Cursor, DecodedEvent, Event},
+    bitcoin::{
+        blockdata::constants::genesis_block, hashes::Hash, locktime::absolute::LockTime, Block, BlockHash, Network, OutPoint,
+        ScriptBuf, Sequence, Transaction, TxIn, TxOut, WPubkeyHash, Witness,
+    },
+};
+
+/// a p2wpkh script of the key hash `[owner; 20]`
+pub fn script(owner: u8) -> ScriptBuf {
+    ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::from_byte_array([owner; 20]))
+}
+
+/// a transaction spending `inputs`, with an output of the value to each owner
+pub fn tx(inputs: &[(OutPoint, Witness)], outputs: &[(u8, u64)]) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: inputs
+            .iter()
+            .map(|(previous_output, witness)| TxIn {
+                previous_output: *previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: witness.clone(),
+            })
+            .collect(),
+        output: outputs.iter().map(|(owner, value)| TxOut { value: *value, script_pubkey: script(*owner) }).collect(),
+    }
+}
+
+/// a regtest block of the transactions
+pub fn block(txdata: Vec<Transaction>) -> Block {
+    let mut block = genesis_block(Network::Regtest);
+    block.txdata = txdata;
+    block
+}
+
+/// a bitmap mint of the block at `height`
+pub fn event(height: i64, txhash: &str) -> DecodedEvent {
+    DecodedEvent {
+        height: Some(height),
+        blocktime: None,
+        txhash: txhash.into(),
+        txindex: Some(0),
+        input: 0,
+        offset: 0,
+        ordinal: 0,
+        event: Event::from_parts("ord-bitmap", serde_json::json!({"mint": format!("{height}.bitmap")})),
+    }
+}
+
+/// the cursor of the block at `height`, its hash made of the height
+pub fn cursor(height: u64) -> Cursor {
+    Cursor {
+        height,
+        block_hash: BlockHash::from_byte_array([height as u8; 32]),
+    }
+}
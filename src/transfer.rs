@@ -0,0 +1,399 @@
+use {
+    crate::{
+        decoder::{located_events, ord as ord_decoder, DecodedEvent, EnvelopeDecoder, Location, Registry, ORD_ENVELOPE},
+        event::Event,
+        ord::{InscriptionId, ParsedEnvelope},
+        sat_flow::{self, Prevouts},
+    },
+    anyhow::{bail, Result},
+    bitcoin::{Block, Network, OutPoint, TxOut},
+    ordinals::{Height, SatPoint},
+    serde::{Deserialize, Serialize},
+    std::collections::{BTreeMap, HashMap},
+};
+
+/// the protocol of the transfers of the tracker
+pub const TRANSFER_PROTOCOL: &str = "ord-transfer";
+/// the height of the first inscription on mainnet
+pub const FIRST_HEIGHT: u64 = 767430;
+/// the protocols of the inscriptions followed by the tracker, plain `ord`
+/// inscriptions last
+pub const TRACKED_PROTOCOLS: [&str; 4] = ["ord-brc420", "ord-bitmap", "ord-sns", "ord"];
+
+/// an inscription moved by the spend of the output holding it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InscriptionTransfer {
+    pub inscription_id: InscriptionId,
+    /// the protocol of the inscription, one of [`TRACKED_PROTOCOLS`]
+    pub protocol: String,
+    pub old_outpoint: OutPoint,
+    /// `None` when the inscription is paid as fee and the coinbase doesn't claim it
+    pub new_outpoint: Option<OutPoint>,
+    /// of the inscription in its new output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    /// the address of the new output, or the hex of its script without address.
+    /// `None` when lost or burned in an OP_RETURN output.
+    pub owner: Option<String>,
+}
+
+/// the decoders of the tracked protocols, with `ord` for the other inscriptions
+pub fn registry() -> Registry {
+    let mut registry = Registry::empty();
+    registry
+        .register(EnvelopeDecoder::new("ord-brc420", ORD_ENVELOPE, ord_decoder::decode_ord_brc420))
+        .register(EnvelopeDecoder::new("ord-bitmap", ORD_ENVELOPE, ord_decoder::decode_ord_bitmap))
+        .register(EnvelopeDecoder::new("ord-sns", ORD_ENVELOPE, ord_decoder::decode_ord_sns))
+        .register(ord_decoder::Ord);
+    registry
+}
+
+/// an inscription in an unspent output
+#[derive(Debug, Clone, Copy)]
+struct Tracked {
+    inscription_id: InscriptionId,
+    offset: u64,
+    protocol: &'static str,
+}
+
+/// the sats of the coinbase of a block: the subsidy, then the fees of the
+/// transactions in block order, which are summed as far as needed
+struct Fees<'a> {
+    block: &'a Block,
+    /// the sats of the coinbase before the fee of each transaction: the
+    /// subsidy, then the fees of the transactions before it
+    offsets: Vec<u64>,
+}
+
+impl<'a> Fees<'a> {
+    fn new(block: &'a Block, height: u64) -> Self {
+        Self {
+            block,
+            offsets: vec![Height(height as u32).subsidy()],
+        }
+    }
+
+    /// where the sat at `offset` among the sats of the inputs of the transaction
+    /// at `txindex` goes, with its output: to the outputs of the transaction
+    /// first in first out, else to the coinbase as fee. `None` if the coinbase
+    /// doesn't claim it.
+    fn locate(&mut self, txindex: usize, offset: u64, prevouts: &mut Prevouts) -> Result<Option<(SatPoint, &'a TxOut)>> {
+        let tx = &self.block.txdata[txindex];
+        if let Some(satpoint) = sat_flow::locate(tx, offset) {
+            return Ok(Some((satpoint, &tx.output[satpoint.outpoint.vout as usize])));
+        }
+        if txindex == 0 {
+            return Ok(None);
+        }
+        let fee = offset - tx.output.iter().map(|output| output.value).sum::<u64>();
+        let coinbase = &self.block.txdata[0];
+        let offset = self.offset(txindex, prevouts)? + fee;
+        Ok(sat_flow::locate(coinbase, offset).map(|satpoint| (satpoint, &coinbase.output[satpoint.outpoint.vout as usize])))
+    }
+
+    /// the sats of the coinbase before the fee of the transaction at `txindex`
+    fn offset(&mut self, txindex: usize, prevouts: &mut Prevouts) -> Result<u64> {
+        if self.offsets.len() > txindex {
+            return Ok(self.offsets[txindex]);
+        }
+        match prevouts.source() {
+            // the fees of the whole block at once, rather than the inputs of its transactions
+            Some(source) => {
+                let fees = source.block_fees(&self.block.block_hash())?;
+                if fees.len() != self.block.txdata.len() {
+                    bail!("{} fees for the {} transactions of block {}", fees.len(), self.block.txdata.len(), self.block.block_hash());
+                }
+                for fee in &fees[..fees.len() - 1] {
+                    self.offsets.push(self.offsets[self.offsets.len() - 1] + fee);
+                }
+            }
+            // the outputs of the transactions before are in `prevouts` already
+            None => {
+                while self.offsets.len() <= txindex {
+                    let tx = &self.block.txdata[self.offsets.len() - 1];
+                    let fee = match self.offsets.len() {
+                        1 => 0,
+                        _ => prevouts.input_offset(tx, tx.input.len())?.saturating_sub(tx.output.iter().map(|output| output.value).sum()),
+                    };
+                    self.offsets.push(self.offsets[self.offsets.len() - 1] + fee);
+                }
+            }
+        }
+        Ok(self.offsets[txindex])
+    }
+}
+
+/// where the inscriptions are: each is given a [`SatPoint`] when revealed, its
+/// `pointer` if within the outputs, else the first sat of its input, and
+/// follows its sat first in first out through the transactions spending it.
+///
+/// Inscriptions paid as fee go to the coinbase, as in ord: its sats are the
+/// subsidy, then the fees of the transactions of the block in order. Those the
+/// coinbase doesn't claim are lost.
+pub struct InscriptionTracker {
+    network: Network,
+    /// by the output holding them
+    outputs: HashMap<OutPoint, Vec<Tracked>>,
+    locations: HashMap<InscriptionId, SatPoint>,
+}
+
+impl InscriptionTracker {
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            outputs: HashMap::new(),
+            locations: HashMap::new(),
+        }
+    }
+
+    /// the current location of an inscription, `None` if it isn't tracked or lost
+    pub fn location(&self, inscription_id: &InscriptionId) -> Option<SatPoint> {
+        self.locations.get(inscription_id).copied()
+    }
+
+    /// the number of inscriptions tracked
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// apply the block at `height` and return the transfers of its transactions.
+    ///
+    /// `events` are the events of the block decoded by [`registry`], which tell
+    /// the revealed inscriptions and their protocol. `prevouts` gives the values
+    /// of the inputs before an inscription, when it isn't on the first input.
+    pub fn index_block(&mut self, block: &Block, height: u64, events: &[DecodedEvent], prevouts: &mut Prevouts) -> Result<Vec<DecodedEvent>> {
+        let mut revealed: BTreeMap<usize, Vec<&DecodedEvent>> = BTreeMap::new();
+        for event in events.iter().filter(|event| TRACKED_PROTOCOLS.contains(&event.event.protocol())) {
+            if let Some(txindex) = event.txindex {
+                revealed.entry(txindex as usize).or_default().push(event);
+            }
+        }
+
+        let mut decoded = Vec::new();
+        let mut fees = Fees::new(block, height);
+        for (txindex, tx) in block.txdata.iter().enumerate() {
+            let transfers = self.transfer(txindex, &mut fees, prevouts)?;
+            if let Some(events) = revealed.get(&txindex) {
+                self.reveal(txindex, events, &mut fees, prevouts)?;
+            }
+            prevouts.add(tx);
+
+            let events = transfers.into_iter().map(|(location, transfer)| (location, Event::InscriptionTransfer(transfer))).collect();
+            decoded.extend(located_events(tx, Some((block, height, txindex)), events));
+        }
+        prevouts.clear();
+        Ok(decoded)
+    }
+
+    /// move the inscriptions of the outputs spent by the transaction at `txindex`
+    fn transfer(&mut self, txindex: usize, fees: &mut Fees, prevouts: &mut Prevouts) -> Result<Vec<(Location, InscriptionTransfer)>> {
+        let tx = &fees.block.txdata[txindex];
+        let mut transfers = Vec::new();
+        for (input, txin) in tx.input.iter().enumerate() {
+            let Some(inscriptions) = self.outputs.remove(&txin.previous_output) else {
+                continue;
+            };
+            let input_offset = prevouts.input_offset(tx, input)?;
+            for inscription in inscriptions {
+                let located = fees.locate(txindex, input_offset + inscription.offset, prevouts)?;
+                let satpoint = located.map(|(satpoint, _)| satpoint);
+                let owner = located.and_then(|(_, output)| {
+                    (!output.script_pubkey.is_op_return()).then(|| sat_flow::owner(&output.script_pubkey, self.network))
+                });
+                if let (Some(satpoint), Some(_)) = (satpoint, &owner) {
+                    self.track(inscription.inscription_id, satpoint, inscription.protocol);
+                } else {
+                    // lost or burned, it never moves again
+                    self.locations.remove(&inscription.inscription_id);
+                }
+                transfers.push((
+                    Location { input: input as u32, offset: 0 },
+                    InscriptionTransfer {
+                        inscription_id: inscription.inscription_id,
+                        protocol: inscription.protocol.into(),
+                        old_outpoint: txin.previous_output,
+                        new_outpoint: satpoint.map(|satpoint| satpoint.outpoint),
+                        offset: satpoint.map(|satpoint| satpoint.offset),
+                        owner,
+                    },
+                ));
+            }
+        }
+        Ok(transfers)
+    }
+
+    /// give the inscriptions of the transaction at `txindex` their first location.
+    /// Every envelope is an inscription, of the protocol of the event found at it.
+    fn reveal(&mut self, txindex: usize, events: &[&DecodedEvent], fees: &mut Fees, prevouts: &mut Prevouts) -> Result<()> {
+        let tx = &fees.block.txdata[txindex];
+        let envelopes = ParsedEnvelope::from_transaction(tx, ORD_ENVELOPE);
+        let output_value = tx.output.iter().map(|output| output.value).sum::<u64>();
+        let txid = tx.txid();
+        for (index, envelope) in envelopes.iter().enumerate() {
+            let protocol = events
+                .iter()
+                .find(|event| event.input == envelope.input && event.offset == envelope.offset && event.event.protocol() != "ord")
+                .and_then(|event| TRACKED_PROTOCOLS.iter().find(|protocol| **protocol == event.event.protocol()))
+                .copied()
+                .unwrap_or("ord");
+            let offset = match envelope.payload.pointer() {
+                Some(pointer) if pointer < output_value => pointer,
+                _ => prevouts.input_offset(tx, envelope.input as usize)?,
+            };
+            let Some((satpoint, output)) = fees.locate(txindex, offset, prevouts)? else {
+                continue;
+            };
+            if output.script_pubkey.is_op_return() {
+                continue;
+            }
+            self.track(InscriptionId { txid, index: index as u32 }, satpoint, protocol);
+        }
+        Ok(())
+    }
+
+    fn track(&mut self, inscription_id: InscriptionId, satpoint: SatPoint, protocol: &'static str) {
+        self.outputs.entry(satpoint.outpoint).or_default().push(Tracked {
+            inscription_id,
+            offset: satpoint.offset,
+            protocol,
+        });
+        self.locations.insert(inscription_id, satpoint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            ord::Inscription,
+            test_util::{block, script, tx},
+        },
+        bitcoin::{Transaction, Witness},
+    };
+
+    fn index(tracker: &mut InscriptionTracker, prevouts: &mut Prevouts, txdata: Vec<Transaction>) -> Vec<InscriptionTransfer> {
+        let block = block(txdata);
+        let events = registry().decode_block(&block, 1, "all");
+        tracker
+            .index_block(&block, 1, &events, prevouts)
+            .unwrap()
+            .into_iter()
+            .map(|event| match event.event {
+                Event::InscriptionTransfer(transfer) => transfer,
+                event => panic!("unexpected event {event:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inscriptions_follow_their_sats() {
+        let funding = tx(&[(OutPoint::null(), Witness::new())], &[(1, 1000), (1, 2000)]);
+        let bitmap = Inscription::new(Some("text/plain".into()), Some(b"100.bitmap".to_vec()));
+        let mut image = Inscription::new(Some("image/png".into()), Some(vec![1, 2, 3]));
+        // the first sat of the second output
+        image.pointer = Some(Inscription::pointer_value(800));
+        let reveal = tx(
+            &[(OutPoint::new(funding.txid(), 0), Witness::new()), (OutPoint::new(funding.txid(), 1), bitmap.to_witness())],
+            &[(1, 800), (2, 2000)],
+        );
+        let (mut tracker, mut prevouts) = (InscriptionTracker::new(Network::Regtest), Prevouts::new(None));
+        assert_eq!(index(&mut tracker, &mut prevouts, vec![funding.clone()]), []);
+        prevouts.add(&funding);
+        assert_eq!(index(&mut tracker, &mut prevouts, vec![reveal.clone()]), []);
+        let bitmap_id = InscriptionId { txid: reveal.txid(), index: 0 };
+        // revealed on the second input, its sat is the 1000th of the transaction
+        assert_eq!(tracker.location(&bitmap_id), Some(SatPoint { outpoint: OutPoint::new(reveal.txid(), 1), offset: 200 }));
+
+        let reveal_image = tx(&[(OutPoint::new(reveal.txid(), 0), image.to_witness())], &[(3, 500), (4, 200)]);
+        prevouts.add(&reveal);
+        index(&mut tracker, &mut prevouts, vec![reveal_image.clone()]);
+        let image_id = InscriptionId { txid: reveal_image.txid(), index: 0 };
+        // the pointer is past the outputs, so it is ignored
+        assert_eq!(tracker.location(&image_id), Some(SatPoint { outpoint: OutPoint::new(reveal_image.txid(), 0), offset: 0 }));
+
+        // the image moves along with the bitmap, the bitmap goes as fee
+        prevouts.add(&reveal_image);
+        let send = tx(
+            &[(OutPoint::new(reveal_image.txid(), 0), Witness::new()), (OutPoint::new(reveal.txid(), 1), Witness::new())],
+            &[(5, 100), (6, 400)],
+        );
+        let transfers = index(&mut tracker, &mut prevouts, vec![send.clone()]);
+        assert_eq!(
+            transfers,
+            [
+                InscriptionTransfer {
+                    inscription_id: image_id,
+                    protocol: "ord".into(),
+                    old_outpoint: OutPoint::new(reveal_image.txid(), 0),
+                    new_outpoint: Some(OutPoint::new(send.txid(), 0)),
+                    offset: Some(0),
+                    owner: Some(sat_flow::owner(&script(5), Network::Regtest)),
+                },
+                InscriptionTransfer {
+                    inscription_id: bitmap_id,
+                    protocol: "ord-bitmap".into(),
+                    old_outpoint: OutPoint::new(reveal.txid(), 1),
+                    new_outpoint: None,
+                    offset: None,
+                    owner: None,
+                },
+            ]
+        );
+        assert_eq!(tracker.location(&bitmap_id), None);
+        assert_eq!(tracker.len(), 1);
+
+        let event = Event::InscriptionTransfer(transfers[0].clone());
+        assert_eq!(event.protocol(), TRANSFER_PROTOCOL);
+        assert_eq!(Event::from_parts(TRANSFER_PROTOCOL, event.payload().unwrap()), event);
+    }
+
+    #[test]
+    fn inscriptions_paid_as_fee_go_to_the_coinbase() {
+        let funding = tx(&[(OutPoint::null(), Witness::new())], &[(1, 1000), (1, 2000), (1, 300)]);
+        let bitmap = Inscription::new(Some("text/plain".into()), Some(b"200.bitmap".to_vec()));
+        let reveal = tx(&[(OutPoint::new(funding.txid(), 0), bitmap.to_witness())], &[(1, 900)]);
+        let (mut tracker, mut prevouts) = (InscriptionTracker::new(Network::Regtest), Prevouts::new(None));
+        prevouts.add(&funding);
+        let coinbase = tx(&[(OutPoint::null(), Witness::new())], &[(7, 1)]);
+        index(&mut tracker, &mut prevouts, vec![coinbase, reveal.clone()]);
+        let bitmap_id = InscriptionId { txid: reveal.txid(), index: 0 };
+
+        // a transaction paying 500 as fee, then one paying the bitmap as fee,
+        // the 50th sat of its fee
+        let other = tx(&[(OutPoint::new(funding.txid(), 1), Witness::new())], &[(3, 1500)]);
+        let send = tx(
+            &[(OutPoint::new(funding.txid(), 2), Witness::new()), (OutPoint::new(reveal.txid(), 0), Witness::new())],
+            &[(4, 250)],
+        );
+        let subsidy = Height(1).subsidy();
+        let coinbase = tx(&[(OutPoint::null(), Witness::new())], &[(7, subsidy), (8, 2000)]);
+        prevouts.add(&funding);
+        prevouts.add(&reveal);
+        let transfers = index(&mut tracker, &mut prevouts, vec![coinbase.clone(), other, send]);
+        let in_coinbase = SatPoint { outpoint: OutPoint::new(coinbase.txid(), 1), offset: 550 };
+        assert_eq!(
+            transfers,
+            [InscriptionTransfer {
+                inscription_id: bitmap_id,
+                protocol: "ord-bitmap".into(),
+                old_outpoint: OutPoint::new(reveal.txid(), 0),
+                new_outpoint: Some(in_coinbase.outpoint),
+                offset: Some(550),
+                owner: Some(sat_flow::owner(&script(8), Network::Regtest)),
+            }]
+        );
+        assert_eq!(tracker.location(&bitmap_id), Some(in_coinbase));
+
+        // and moves with the coinbase output
+        let spend = tx(&[(in_coinbase.outpoint, Witness::new())], &[(9, 2000)]);
+        prevouts.add(&coinbase);
+        let transfers = index(&mut tracker, &mut prevouts, vec![tx(&[(OutPoint::null(), Witness::new())], &[(7, 2)]), spend.clone()]);
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(tracker.location(&bitmap_id), Some(SatPoint { outpoint: OutPoint::new(spend.txid(), 0), offset: 550 }));
+    }
+}